@@ -0,0 +1,160 @@
+//! Command-line arguments.
+
+use {
+    clap::{Args, Parser, Subcommand, ValueEnum},
+    solana_address::Address,
+    solana_hash::Hash,
+    std::path::PathBuf,
+};
+
+/// Deploys and manages programs owned by the upgradeable BPF loader.
+#[derive(Debug, Parser)]
+#[command(name = "loader-v3", version)]
+pub struct Cli {
+    /// JSON RPC URL of the cluster.
+    #[arg(
+        long,
+        short = 'u',
+        global = true,
+        default_value = "http://127.0.0.1:8899"
+    )]
+    pub url: String,
+    /// Fee payer, as a keypair file or, in offline mode, an address.
+    /// Defaults to the Solana CLI default keypair.
+    #[arg(long, short = 'k', global = true)]
+    pub keypair: Option<String>,
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Display)]
+    pub output: OutputFormat,
+    /// Emit unsigned transactions instead of sending them.
+    #[arg(long, global = true, requires = "blockhash")]
+    pub offline: bool,
+    /// Recent or durable nonce blockhash used in offline mode.
+    #[arg(long, global = true)]
+    pub blockhash: Option<Hash>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Display,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Deploy a new program.
+    Deploy(DeployArgs),
+    /// Write a program into a new buffer without deploying it.
+    WriteBuffer(WriteBufferArgs),
+    /// Upgrade a program, extending it first if the new ELF does not fit.
+    Upgrade(UpgradeArgs),
+    /// Extend a program's ProgramData account.
+    Extend(ExtendArgs),
+    /// Change the authority of a program or buffer.
+    SetAuthority(SetAuthorityArgs),
+    /// Close a program or buffer, reclaiming its lamports.
+    Close(CloseArgs),
+    /// Show a program, ProgramData or buffer account.
+    Show(ShowArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct DeployArgs {
+    /// Program ELF file.
+    pub program_file: PathBuf,
+    /// Program account keypair, generated if omitted.
+    #[arg(long)]
+    pub program_id: Option<String>,
+    /// Buffer account keypair, generated if omitted.
+    #[arg(long)]
+    pub buffer: Option<String>,
+    /// Upgrade authority, defaults to the fee payer.
+    #[arg(long)]
+    pub upgrade_authority: Option<String>,
+    /// Maximum program size, defaults to the ELF size.
+    #[arg(long)]
+    pub max_len: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+pub struct WriteBufferArgs {
+    /// Program ELF file.
+    pub program_file: PathBuf,
+    /// Buffer account keypair, generated if omitted.
+    #[arg(long)]
+    pub buffer: Option<String>,
+    /// Buffer authority, defaults to the fee payer.
+    #[arg(long)]
+    pub buffer_authority: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct UpgradeArgs {
+    pub program_id: Address,
+    /// New program ELF file.
+    pub program_file: PathBuf,
+    /// Buffer account keypair, generated if omitted.
+    #[arg(long)]
+    pub buffer: Option<String>,
+    /// Upgrade authority, defaults to the fee payer.
+    #[arg(long)]
+    pub upgrade_authority: Option<String>,
+    /// Recipient of the buffer's lamports, defaults to the fee payer.
+    #[arg(long)]
+    pub spill: Option<Address>,
+    /// Bytes to extend the program by before upgrading. Computed from the
+    /// deployed program when online.
+    #[arg(long)]
+    pub additional_bytes: Option<u32>,
+}
+
+#[derive(Debug, Args)]
+pub struct ExtendArgs {
+    pub program_id: Address,
+    pub additional_bytes: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct SetAuthorityArgs {
+    /// Program or, with `--buffer`, buffer address.
+    pub address: Address,
+    /// The address is a buffer rather than a program.
+    #[arg(long)]
+    pub buffer: bool,
+    /// Current authority, defaults to the fee payer.
+    #[arg(long)]
+    pub authority: Option<String>,
+    #[arg(long, required_unless_present = "final", conflicts_with = "final")]
+    pub new_authority: Option<String>,
+    /// Make the program immutable.
+    #[arg(long = "final", id = "final", conflicts_with = "checked")]
+    pub finalize: bool,
+    /// Require the new authority to sign.
+    #[arg(long)]
+    pub checked: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CloseArgs {
+    /// Program or, with `--buffer`, buffer address.
+    pub address: Address,
+    /// The address is a buffer rather than a program.
+    #[arg(long)]
+    pub buffer: bool,
+    /// Authority of the account, defaults to the fee payer.
+    #[arg(long)]
+    pub authority: Option<String>,
+    /// Recipient of the reclaimed lamports, defaults to the fee payer.
+    #[arg(long)]
+    pub recipient: Option<Address>,
+    /// Confirm closing a program, after which its address can never be
+    /// deployed to again. Not needed for buffers.
+    #[arg(long)]
+    pub bypass_warning: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ShowArgs {
+    pub address: Address,
+}
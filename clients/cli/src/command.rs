@@ -0,0 +1,474 @@
+//! Command implementations.
+
+use {
+    crate::{
+        args::{
+            CloseArgs, Command, DeployArgs, ExtendArgs, SetAuthorityArgs, ShowArgs, UpgradeArgs,
+            WriteBufferArgs,
+        },
+        error::CliError,
+        output::{AccountOutput, CommandOutput, UnsignedTransaction},
+        signer::SignerArg,
+        Cli,
+    },
+    base64::{prelude::BASE64_STANDARD, Engine},
+    solana_account::Account,
+    solana_address::Address,
+    solana_hash::Hash,
+    solana_instruction::Instruction,
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{
+        capacity::{plan_extend, Exact},
+        elf::validate,
+        guard::{close_program, make_immutable, Irreversible},
+        instruction::set_authority as set_authority_instruction,
+        instructions::{CloseBuilder, ExtendProgramBuilder, SetAuthorityCheckedBuilder},
+        plan::{DeployPlanBuilder, UpgradePlanBuilder, WriteBufferPlanBuilder},
+        rpc::LoaderRpc,
+        state::{
+            find_program_data_address, LoaderState, ProgramData, StateError, BUFFER_HEADER_SIZE,
+            MAX_PROGRAM_DATA_LEN,
+        },
+        verify::ProgramHash,
+    },
+    solana_message::Message,
+    solana_rent::Rent,
+    solana_transaction::Transaction,
+    std::path::Path,
+};
+
+/// Runs a parsed command line against `rpc`.
+pub fn run<R: LoaderRpc>(cli: &Cli, rpc: &mut R) -> Result<CommandOutput, CliError> {
+    let payer = match &cli.keypair {
+        Some(keypair) => SignerArg::parse(keypair)?,
+        None => SignerArg::parse(&default_keypair_path())?,
+    };
+    let mut context = Context {
+        rpc,
+        payer,
+        signers: Vec::new(),
+        offline: cli.offline,
+        blockhash: cli.blockhash,
+    };
+    match &cli.command {
+        Command::Deploy(args) => deploy(&mut context, args),
+        Command::WriteBuffer(args) => write_buffer(&mut context, args),
+        Command::Upgrade(args) => upgrade(&mut context, args),
+        Command::Extend(args) => extend(&mut context, args),
+        Command::SetAuthority(args) => set_authority(&mut context, args),
+        Command::Close(args) => close(&mut context, args),
+        Command::Show(args) => show(&context, args),
+    }
+}
+
+fn default_keypair_path() -> String {
+    let home = std::env::var("HOME").unwrap_or_default();
+    format!("{home}/.config/solana/id.json")
+}
+
+/// State shared by the commands: the RPC client and the keypairs available to
+/// sign.
+struct Context<'a, R> {
+    rpc: &'a mut R,
+    payer: SignerArg,
+    signers: Vec<SignerArg>,
+    offline: bool,
+    blockhash: Option<Hash>,
+}
+
+impl<R: LoaderRpc> Context<'_, R> {
+    fn payer(&self) -> Address {
+        self.payer.address()
+    }
+
+    /// Resolves a signer argument, defaulting to the fee payer.
+    fn signer(&mut self, arg: Option<&str>) -> Result<Address, CliError> {
+        let Some(arg) = arg else {
+            return Ok(self.payer());
+        };
+        let signer = SignerArg::parse(arg)?;
+        let address = signer.address();
+        self.signers.push(signer);
+        Ok(address)
+    }
+
+    /// Resolves the signer of a new account, generating a keypair if none
+    /// is given. Offline, the generated keypair would be lost, so the
+    /// argument is required.
+    fn new_signer(&mut self, arg: Option<&str>, name: &'static str) -> Result<Address, CliError> {
+        match arg {
+            Some(arg) => self.signer(Some(arg)),
+            None if self.offline => Err(CliError::OfflineArgument(name)),
+            None => {
+                let signer = SignerArg::from(Keypair::new());
+                let address = signer.address();
+                self.signers.push(signer);
+                Ok(address)
+            }
+        }
+    }
+
+    /// Rent used to fund new accounts. Offline, the default rent is assumed.
+    fn rent(&self) -> Result<Rent, CliError> {
+        if self.offline {
+            return Ok(Rent::default());
+        }
+        Ok(self.rpc.get_rent()?)
+    }
+
+    /// Fetches an account owned by the loader.
+    fn loader_account(&self, address: &Address) -> Result<Account, CliError> {
+        Ok(self.rpc.get_loader_account(address)?)
+    }
+
+    fn keypair(&self, address: &Address) -> Result<&Keypair, CliError> {
+        std::iter::once(&self.payer)
+            .chain(&self.signers)
+            .filter(|signer| signer.address() == *address)
+            .find_map(SignerArg::keypair)
+            .ok_or(CliError::MissingKeypair(*address))
+    }
+
+    /// Sends one transaction per instruction list, in order, or builds them
+    /// unsigned in offline mode.
+    fn execute(
+        &mut self,
+        transactions: impl IntoIterator<Item = Vec<Instruction>>,
+        output: &mut CommandOutput,
+    ) -> Result<(), CliError> {
+        let payer = self.payer();
+        for instructions in transactions {
+            let mut transaction =
+                Transaction::new_unsigned(Message::new(&instructions, Some(&payer)));
+            let signers: Vec<Address> = transaction
+                .message
+                .signer_keys()
+                .into_iter()
+                .copied()
+                .collect();
+
+            if self.offline {
+                transaction.message.recent_blockhash = self
+                    .blockhash
+                    .ok_or(CliError::OfflineArgument("blockhash"))?;
+                let bytes = bincode::serialize(&transaction)
+                    .map_err(|error| CliError::Transaction(error.to_string()))?;
+                output.transactions.push(UnsignedTransaction {
+                    transaction: BASE64_STANDARD.encode(bytes),
+                    signers: signers.iter().map(Address::to_string).collect(),
+                });
+                continue;
+            }
+
+            let keypairs = signers
+                .iter()
+                .map(|signer| self.keypair(signer))
+                .collect::<Result<Vec<_>, _>>()?;
+            let blockhash = self.rpc.get_latest_blockhash()?;
+            transaction
+                .try_sign(&keypairs, blockhash)
+                .map_err(|error| CliError::Transaction(error.to_string()))?;
+            let signature = self.rpc.send_and_confirm_transaction(&transaction)?;
+            output.signatures.push(signature.to_string());
+        }
+        Ok(())
+    }
+}
+
+fn read_elf(path: &Path, max_data_len: usize) -> Result<Vec<u8>, CliError> {
+    let elf = std::fs::read(path).map_err(|error| CliError::Read {
+        path: path.display().to_string(),
+        message: error.to_string(),
+    })?;
+    let report = validate(&elf, max_data_len);
+    if !report.is_valid() {
+        let errors: Vec<String> = report.errors().map(ToString::to_string).collect();
+        return Err(CliError::InvalidElf(errors.join("; ")));
+    }
+    Ok(elf)
+}
+
+fn deploy<R: LoaderRpc>(
+    context: &mut Context<'_, R>,
+    args: &DeployArgs,
+) -> Result<CommandOutput, CliError> {
+    let elf = read_elf(&args.program_file, MAX_PROGRAM_DATA_LEN)?;
+    // The loader only rejects an out-of-range length in the final deploy
+    // step, after the buffer has been funded and written.
+    if let Some(max_len) = args.max_len {
+        let in_range = usize::try_from(max_len)
+            .is_ok_and(|max_len| (elf.len()..=MAX_PROGRAM_DATA_LEN).contains(&max_len));
+        if !in_range {
+            return Err(CliError::InvalidMaxLen {
+                max_len,
+                min: elf.len(),
+                max: MAX_PROGRAM_DATA_LEN,
+            });
+        }
+    }
+    let program = context.new_signer(args.program_id.as_deref(), "program-id")?;
+    let buffer = context.new_signer(args.buffer.as_deref(), "buffer")?;
+    let authority = context.signer(args.upgrade_authority.as_deref())?;
+
+    let mut builder = DeployPlanBuilder::new();
+    builder
+        .payer(context.payer())
+        .program(program)
+        .buffer(buffer)
+        .upgrade_authority(authority)
+        .rent(context.rent()?);
+    if let Some(max_len) = args.max_len {
+        builder.max_data_len(max_len);
+    }
+    let plan = builder.build(&elf);
+
+    let mut output = CommandOutput {
+        program_id: Some(program.to_string()),
+        buffer: Some(buffer.to_string()),
+        ..CommandOutput::default()
+    };
+    context.execute(
+        plan.steps.into_iter().map(|step| step.instructions),
+        &mut output,
+    )?;
+    Ok(output)
+}
+
+fn write_buffer<R: LoaderRpc>(
+    context: &mut Context<'_, R>,
+    args: &WriteBufferArgs,
+) -> Result<CommandOutput, CliError> {
+    let elf = read_elf(&args.program_file, MAX_PROGRAM_DATA_LEN)?;
+    let buffer = context.new_signer(args.buffer.as_deref(), "buffer")?;
+    let authority = context.signer(args.buffer_authority.as_deref())?;
+
+    let plan = WriteBufferPlanBuilder::new()
+        .payer(context.payer())
+        .buffer(buffer)
+        .buffer_authority(authority)
+        .rent(context.rent()?)
+        .build(&elf);
+
+    let mut output = CommandOutput {
+        buffer: Some(buffer.to_string()),
+        ..CommandOutput::default()
+    };
+    context.execute(
+        plan.steps.into_iter().map(|step| step.instructions),
+        &mut output,
+    )?;
+    Ok(output)
+}
+
+fn upgrade<R: LoaderRpc>(
+    context: &mut Context<'_, R>,
+    args: &UpgradeArgs,
+) -> Result<CommandOutput, CliError> {
+    let elf = read_elf(&args.program_file, MAX_PROGRAM_DATA_LEN)?;
+    let buffer = context.new_signer(args.buffer.as_deref(), "buffer")?;
+    let authority = context.signer(args.upgrade_authority.as_deref())?;
+    let additional_bytes = match args.additional_bytes {
+        Some(additional_bytes) => Some(additional_bytes),
+        None if context.offline => None,
+        None => {
+            let program_data =
+                context.loader_account(&find_program_data_address(&args.program_id))?;
+            let max_data_len = ProgramData::unpack(&program_data.data)?.data.len();
+            plan_extend(&Exact, max_data_len, elf.len())?.map(|args| args.additional_bytes)
+        }
+    };
+
+    let mut builder = UpgradePlanBuilder::new();
+    builder
+        .payer(context.payer())
+        .program(args.program_id)
+        .buffer(buffer)
+        .upgrade_authority(authority)
+        .spill(args.spill.unwrap_or(context.payer()))
+        .rent(context.rent()?);
+    if let Some(additional_bytes) = additional_bytes {
+        builder.additional_bytes(additional_bytes);
+    }
+    let plan = builder.build(&elf);
+
+    let mut output = CommandOutput {
+        program_id: Some(args.program_id.to_string()),
+        buffer: Some(buffer.to_string()),
+        ..CommandOutput::default()
+    };
+    context.execute(
+        plan.steps.into_iter().map(|step| step.instructions),
+        &mut output,
+    )?;
+    Ok(output)
+}
+
+fn extend<R: LoaderRpc>(
+    context: &mut Context<'_, R>,
+    args: &ExtendArgs,
+) -> Result<CommandOutput, CliError> {
+    let instruction = ExtendProgramBuilder::new()
+        .program_data_account(find_program_data_address(&args.program_id))
+        .program_account(args.program_id)
+        .system_program(Some(solana_system_interface::program::ID))
+        .payer(Some(context.payer()))
+        .additional_bytes(args.additional_bytes)
+        .instruction();
+
+    let mut output = CommandOutput {
+        program_id: Some(args.program_id.to_string()),
+        ..CommandOutput::default()
+    };
+    context.execute([vec![instruction]], &mut output)?;
+    Ok(output)
+}
+
+/// Checks that `address` holds a Buffer account, or a Program account if
+/// `buffer` is false. Skipped in offline mode.
+fn check_kind<R: LoaderRpc>(
+    context: &Context<'_, R>,
+    address: &Address,
+    buffer: bool,
+) -> Result<(), CliError> {
+    if context.offline {
+        return Ok(());
+    }
+    let account = context.loader_account(address)?;
+    match (LoaderState::unpack(&account.data)?, buffer) {
+        (LoaderState::Buffer { .. }, true) | (LoaderState::Program { .. }, false) => Ok(()),
+        (_, true) => Err(StateError::UnexpectedState { expected: "Buffer" }.into()),
+        (_, false) => Err(StateError::UnexpectedState {
+            expected: "Program",
+        }
+        .into()),
+    }
+}
+
+fn set_authority<R: LoaderRpc>(
+    context: &mut Context<'_, R>,
+    args: &SetAuthorityArgs,
+) -> Result<CommandOutput, CliError> {
+    check_kind(context, &args.address, args.buffer)?;
+    let authority = context.signer(args.authority.as_deref())?;
+    let new_authority = match &args.new_authority {
+        Some(new_authority) => Some(context.signer(Some(new_authority))?),
+        None => None,
+    };
+    let account = if args.buffer {
+        if new_authority.is_none() {
+            return Err(CliError::ImmutableBuffer);
+        }
+        args.address
+    } else {
+        find_program_data_address(&args.address)
+    };
+
+    let instruction = match new_authority {
+        Some(new_authority) if args.checked => SetAuthorityCheckedBuilder::new()
+            .buffer_or_program_data_account(account)
+            .current_authority(authority)
+            .new_authority(new_authority)
+            .instruction(),
+        Some(new_authority) => {
+            set_authority_instruction(&account, &authority, Some(&new_authority))
+        }
+        // Passing `--final` is the acknowledgement.
+        None => make_immutable(&args.address, &authority, Irreversible::acknowledge()),
+    };
+
+    let mut output = CommandOutput {
+        new_authority: Some(format_authority(new_authority)),
+        ..CommandOutput::default()
+    };
+    if args.buffer {
+        output.buffer = Some(args.address.to_string());
+    } else {
+        output.program_id = Some(args.address.to_string());
+    }
+    context.execute([vec![instruction]], &mut output)?;
+    Ok(output)
+}
+
+fn close<R: LoaderRpc>(
+    context: &mut Context<'_, R>,
+    args: &CloseArgs,
+) -> Result<CommandOutput, CliError> {
+    check_kind(context, &args.address, args.buffer)?;
+    let authority = context.signer(args.authority.as_deref())?;
+    let recipient = args.recipient.unwrap_or(context.payer());
+
+    let mut output = CommandOutput::default();
+    let instruction = if args.buffer {
+        output.buffer = Some(args.address.to_string());
+        CloseBuilder::new()
+            .buffer_or_program_data_account(args.address)
+            .destination_account(recipient)
+            .authority(Some(authority))
+            .instruction()
+    } else {
+        if !args.bypass_warning {
+            return Err(CliError::IrreversibleClose(args.address));
+        }
+        output.program_id = Some(args.address.to_string());
+        // Passing `--bypass-warning` is the acknowledgement.
+        close_program(
+            &args.address,
+            &recipient,
+            &authority,
+            Irreversible::acknowledge(),
+        )
+    };
+    context.execute([vec![instruction]], &mut output)?;
+    Ok(output)
+}
+
+fn show<R: LoaderRpc>(
+    context: &Context<'_, R>,
+    args: &ShowArgs,
+) -> Result<CommandOutput, CliError> {
+    if context.offline {
+        return Err(CliError::RequiresOnline("show"));
+    }
+    let account = context.loader_account(&args.address)?;
+    let mut output = AccountOutput {
+        address: args.address.to_string(),
+        lamports: account.lamports,
+        ..AccountOutput::default()
+    };
+    match LoaderState::unpack(&account.data)? {
+        LoaderState::Uninitialized => output.kind = "uninitialized".to_string(),
+        LoaderState::Buffer { authority } => {
+            output.kind = "buffer".to_string();
+            output.authority = Some(format_authority(authority));
+            output.data_len = Some(account.data.len().saturating_sub(BUFFER_HEADER_SIZE));
+            output.hash = Some(ProgramHash::of_buffer(&account.data)?.to_string());
+        }
+        LoaderState::Program { program_data } => {
+            let program_data_account = context.loader_account(&program_data)?;
+            output.kind = "program".to_string();
+            output.program_data = Some(program_data.to_string());
+            describe_program_data(&mut output, &program_data_account.data)?;
+        }
+        LoaderState::ProgramData { .. } => {
+            output.kind = "programData".to_string();
+            describe_program_data(&mut output, &account.data)?;
+        }
+    }
+    Ok(CommandOutput {
+        account: Some(output),
+        ..CommandOutput::default()
+    })
+}
+
+fn describe_program_data(output: &mut AccountOutput, data: &[u8]) -> Result<(), CliError> {
+    let program_data = ProgramData::unpack(data)?;
+    output.authority = Some(format_authority(program_data.upgrade_authority));
+    output.last_deploy_slot = Some(program_data.slot);
+    output.data_len = Some(program_data.data.len());
+    output.hash = Some(ProgramHash::of_program_data(data)?.to_string());
+    Ok(())
+}
+
+fn format_authority(authority: Option<Address>) -> String {
+    authority.map_or_else(|| "none".to_string(), |address| address.to_string())
+}
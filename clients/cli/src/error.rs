@@ -0,0 +1,56 @@
+use {
+    solana_address::Address,
+    solana_loader_v3_program_client::{capacity::CapacityError, rpc::RpcError, state::StateError},
+    thiserror::Error,
+};
+
+/// Errors returned by CLI commands.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum CliError {
+    #[error("failed to read `{path}`: {message}")]
+    Read { path: String, message: String },
+    #[error("invalid program: {0}")]
+    InvalidElf(String),
+    #[error("--max-len {max_len} must be between the program length {min} and {max}")]
+    InvalidMaxLen {
+        max_len: u64,
+        min: usize,
+        max: usize,
+    },
+    #[error("`{0}` is neither an address nor a keypair file")]
+    InvalidSigner(String),
+    #[error("no keypair available to sign for {0}")]
+    MissingKeypair(Address),
+    #[error("--{0} is required in offline mode")]
+    OfflineArgument(&'static str),
+    #[error("`{0}` needs a connection to the cluster")]
+    RequiresOnline(&'static str),
+    #[error("account {0} does not exist")]
+    AccountNotFound(Address),
+    #[error("account {0} is not owned by the loader")]
+    NotOwnedByLoader(Address),
+    #[error("closing program {0} is irreversible, pass --bypass-warning to confirm")]
+    IrreversibleClose(Address),
+    #[error("buffer authorities cannot be removed")]
+    ImmutableBuffer,
+    #[error(transparent)]
+    State(#[from] StateError),
+    #[error(transparent)]
+    Capacity(#[from] CapacityError),
+    #[error("RPC request failed: {0}")]
+    Rpc(String),
+    #[error("transaction failed: {0}")]
+    Transaction(String),
+}
+
+impl From<RpcError> for CliError {
+    fn from(error: RpcError) -> Self {
+        match error {
+            RpcError::Request(message) => Self::Rpc(message),
+            RpcError::Transaction(message) => Self::Transaction(message),
+            RpcError::AccountNotFound(address) => Self::AccountNotFound(address),
+            RpcError::NotOwnedByLoader(address) => Self::NotOwnedByLoader(address),
+            RpcError::State(error) => Self::State(error),
+        }
+    }
+}
@@ -0,0 +1,10 @@
+//! Command-line interface for deploying and managing programs owned by the
+//! upgradeable BPF loader.
+
+pub mod args;
+pub mod command;
+pub mod error;
+pub mod output;
+pub mod signer;
+
+pub use {args::Cli, command::run, error::CliError, output::CommandOutput};
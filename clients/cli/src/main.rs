@@ -0,0 +1,22 @@
+use {
+    clap::Parser,
+    solana_commitment_config::CommitmentConfig,
+    solana_loader_v3_cli::{run, Cli},
+    solana_rpc_client::rpc_client::RpcClient,
+    std::process::ExitCode,
+};
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let mut rpc = RpcClient::new_with_commitment(cli.url.clone(), CommitmentConfig::confirmed());
+    match run(&cli, &mut rpc) {
+        Ok(output) => {
+            print!("{}", output.format(cli.output));
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("Error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
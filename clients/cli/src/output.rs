@@ -0,0 +1,125 @@
+//! Command results, printed as text or JSON.
+
+use {
+    crate::args::OutputFormat,
+    serde::{Deserialize, Serialize},
+    std::fmt,
+};
+
+/// Result of a command.
+///
+/// Addresses, hashes and signatures are base58 strings except for the ELF
+/// hash, which is hex as printed by `solana-verify`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandOutput {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer: Option<String>,
+    /// Authority after a `set-authority`, `"none"` for immutable programs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_authority: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<AccountOutput>,
+    /// Signatures of the transactions sent, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signatures: Vec<String>,
+    /// Unsigned transactions built in offline mode, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transactions: Vec<UnsignedTransaction>,
+}
+
+/// A transaction built in offline mode.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnsignedTransaction {
+    /// Base64 bincode-serialized transaction with empty signatures.
+    pub transaction: String,
+    /// Accounts that must sign, the fee payer first.
+    pub signers: Vec<String>,
+}
+
+/// A decoded loader account.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountOutput {
+    pub address: String,
+    /// `program`, `programData`, `buffer` or `uninitialized`.
+    pub kind: String,
+    pub lamports: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program_data: Option<String>,
+    /// Upgrade or buffer authority, `"none"` if immutable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authority: Option<String>,
+    /// Slot of the last deploy or upgrade.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_deploy_slot: Option<u64>,
+    /// Bytes available for the program.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_len: Option<usize>,
+    /// `solana-verify` hash of the program.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl CommandOutput {
+    pub fn format(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Display => self.to_string(),
+            OutputFormat::Json => {
+                serde_json::to_string_pretty(self).expect("output is serializable")
+            }
+        }
+    }
+}
+
+impl fmt::Display for CommandOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(program_id) = &self.program_id {
+            writeln!(f, "Program Id: {program_id}")?;
+        }
+        if let Some(buffer) = &self.buffer {
+            writeln!(f, "Buffer: {buffer}")?;
+        }
+        if let Some(new_authority) = &self.new_authority {
+            writeln!(f, "New Authority: {new_authority}")?;
+        }
+        if let Some(account) = &self.account {
+            write!(f, "{account}")?;
+        }
+        for signature in &self.signatures {
+            writeln!(f, "Signature: {signature}")?;
+        }
+        for transaction in &self.transactions {
+            writeln!(f, "Transaction: {}", transaction.transaction)?;
+            writeln!(f, "Signers: {}", transaction.signers.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AccountOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Address: {}", self.address)?;
+        writeln!(f, "Kind: {}", self.kind)?;
+        if let Some(program_data) = &self.program_data {
+            writeln!(f, "ProgramData Address: {program_data}")?;
+        }
+        if let Some(authority) = &self.authority {
+            writeln!(f, "Authority: {authority}")?;
+        }
+        if let Some(slot) = self.last_deploy_slot {
+            writeln!(f, "Last Deployed In Slot: {slot}")?;
+        }
+        if let Some(data_len) = self.data_len {
+            writeln!(f, "Data Length: {data_len} bytes")?;
+        }
+        writeln!(f, "Balance: {} lamports", self.lamports)?;
+        if let Some(hash) = &self.hash {
+            writeln!(f, "Hash: {hash}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+//! Signer arguments.
+
+use {
+    crate::error::CliError,
+    solana_address::Address,
+    solana_keypair::{read_keypair_file, Keypair},
+    solana_signer::Signer,
+    std::str::FromStr,
+};
+
+/// An account given on the command line, either as a keypair file that can
+/// sign or as a bare address.
+///
+/// Bare addresses are only useful in offline mode, where transactions are
+/// emitted unsigned.
+#[derive(Debug)]
+pub enum SignerArg {
+    Keypair(Keypair),
+    Address(Address),
+}
+
+impl SignerArg {
+    /// Parses an address, falling back to reading a keypair file.
+    pub fn parse(value: &str) -> Result<Self, CliError> {
+        if let Ok(address) = Address::from_str(value) {
+            return Ok(Self::Address(address));
+        }
+        read_keypair_file(value)
+            .map(Self::Keypair)
+            .map_err(|_| CliError::InvalidSigner(value.to_string()))
+    }
+
+    pub fn address(&self) -> Address {
+        match self {
+            Self::Keypair(keypair) => keypair.pubkey(),
+            Self::Address(address) => *address,
+        }
+    }
+
+    pub fn keypair(&self) -> Option<&Keypair> {
+        match self {
+            Self::Keypair(keypair) => Some(keypair),
+            Self::Address(_) => None,
+        }
+    }
+}
+
+impl From<Keypair> for SignerArg {
+    fn from(keypair: Keypair) -> Self {
+        Self::Keypair(keypair)
+    }
+}
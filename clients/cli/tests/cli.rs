@@ -0,0 +1,598 @@
+mod common;
+
+use {
+    base64::{prelude::BASE64_STANDARD, Engine},
+    common::{TestContext, ASSOCIATED_TOKEN, MEMO},
+    solana_address::Address,
+    solana_hash::Hash,
+    solana_loader_v3_cli::{CliError, CommandOutput},
+    solana_loader_v3_program_client::{
+        elf::ExtractedElf,
+        state::{find_program_data_address, Buffer, ProgramData, StateError, MAX_PROGRAM_DATA_LEN},
+        verify::ProgramHash,
+        ID as LOADER_V3_ID,
+    },
+    solana_signer::Signer,
+    solana_transaction::Transaction,
+    std::str::FromStr,
+};
+
+fn address(value: &Option<String>) -> Address {
+    Address::from_str(value.as_deref().unwrap()).unwrap()
+}
+
+#[test]
+fn deploy_and_show() {
+    // Given a program file.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+
+    // When we deploy it.
+    let output = context.run(&["deploy", &program_file]).unwrap();
+
+    // Then the program is deployed with the payer as upgrade authority.
+    let program = address(&output.program_id);
+    let program_data = context
+        .account(&find_program_data_address(&program))
+        .unwrap();
+    let deployed = ExtractedElf::from_program_data(&program_data.data).unwrap();
+    assert_eq!(deployed.elf, context.elf(&MEMO));
+    assert_eq!(
+        ProgramData::unpack(&program_data.data)
+            .unwrap()
+            .upgrade_authority,
+        Some(context.payer.pubkey())
+    );
+
+    // And the buffer was consumed.
+    assert!(context.account(&address(&output.buffer)).is_none());
+
+    // And `show` describes the program.
+    let shown = context
+        .run(&["show", &program.to_string()])
+        .unwrap()
+        .account
+        .unwrap();
+    assert_eq!(shown.kind, "program");
+    assert_eq!(
+        shown.program_data,
+        Some(find_program_data_address(&program).to_string())
+    );
+    assert_eq!(shown.authority, Some(context.payer.pubkey().to_string()));
+    assert_eq!(shown.data_len, Some(context.elf(&MEMO).len()));
+    assert_eq!(
+        shown.hash,
+        Some(ProgramHash::of_elf(&context.elf(&MEMO)).to_string())
+    );
+}
+
+#[test]
+fn deploy_with_keypairs_and_max_len() {
+    // Given program, buffer and upgrade authority keypairs.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+    let (program, program_path) = context.keypair("program");
+    let (buffer, buffer_path) = context.keypair("buffer");
+    let (authority, authority_path) = context.keypair("authority");
+    let max_len = context.elf(&MEMO).len() + 1000;
+
+    // When we deploy with them.
+    let output = context
+        .run(&[
+            "deploy",
+            &program_file,
+            "--program-id",
+            &program_path,
+            "--buffer",
+            &buffer_path,
+            "--upgrade-authority",
+            &authority_path,
+            "--max-len",
+            &max_len.to_string(),
+        ])
+        .unwrap();
+
+    // Then the given accounts are used.
+    assert_eq!(output.program_id, Some(program.pubkey().to_string()));
+    assert_eq!(output.buffer, Some(buffer.pubkey().to_string()));
+    let program_data = context
+        .account(&find_program_data_address(&program.pubkey()))
+        .unwrap();
+    let program_data = ProgramData::unpack(&program_data.data).unwrap();
+    assert_eq!(program_data.upgrade_authority, Some(authority.pubkey()));
+    assert_eq!(program_data.data.len(), max_len);
+}
+
+#[test]
+fn deploy_rejects_max_len_out_of_range() {
+    // Given a program file.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+    let elf_len = context.elf(&MEMO).len();
+    let balance = context.account(&context.payer.pubkey()).unwrap().lamports;
+
+    // When we deploy with a max length the ProgramData account cannot have,
+    // or one too small for the program.
+    for max_len in [MAX_PROGRAM_DATA_LEN as u64 + 1, elf_len as u64 - 1] {
+        let error = context
+            .run(&["deploy", &program_file, "--max-len", &max_len.to_string()])
+            .unwrap_err();
+
+        // Then it is rejected before any transaction is sent.
+        assert_eq!(
+            error,
+            CliError::InvalidMaxLen {
+                max_len,
+                min: elf_len,
+                max: MAX_PROGRAM_DATA_LEN,
+            }
+        );
+    }
+    assert_eq!(
+        context.account(&context.payer.pubkey()).unwrap().lamports,
+        balance
+    );
+}
+
+#[test]
+fn write_buffer() {
+    // Given a program file and a buffer authority.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+    let (authority, authority_path) = context.keypair("authority");
+
+    // When we write it into a buffer.
+    let output = context
+        .run(&[
+            "write-buffer",
+            &program_file,
+            "--buffer-authority",
+            &authority_path,
+        ])
+        .unwrap();
+
+    // Then the buffer holds the ELF.
+    let buffer = context.account(&address(&output.buffer)).unwrap();
+    assert_eq!(buffer.owner, LOADER_V3_ID);
+    let unpacked = Buffer::unpack(&buffer.data).unwrap();
+    assert_eq!(unpacked.authority, Some(authority.pubkey()));
+    assert_eq!(unpacked.data, context.elf(&MEMO));
+
+    // And `show` describes it.
+    let shown = context
+        .run(&["show", output.buffer.as_deref().unwrap()])
+        .unwrap()
+        .account
+        .unwrap();
+    assert_eq!(shown.kind, "buffer");
+    assert_eq!(shown.authority, Some(authority.pubkey().to_string()));
+}
+
+#[test]
+fn upgrade_extends_when_needed() {
+    // Given a deployed program and a larger replacement.
+    let mut context = TestContext::new();
+    let old_file = context.program_file("memo", &MEMO);
+    let new_file = context.program_file("associated-token", &ASSOCIATED_TOKEN);
+    let program_id = context
+        .run(&["deploy", &old_file])
+        .unwrap()
+        .program_id
+        .unwrap();
+    assert!(context.elf(&ASSOCIATED_TOKEN).len() > context.elf(&MEMO).len());
+
+    // When we upgrade it.
+    let output = context.run(&["upgrade", &program_id, &new_file]).unwrap();
+
+    // Then the program was extended and now holds the new ELF.
+    let program = address(&output.program_id);
+    let program_data = context
+        .account(&find_program_data_address(&program))
+        .unwrap();
+    let unpacked = ProgramData::unpack(&program_data.data).unwrap();
+    assert_eq!(unpacked.data.len(), context.elf(&ASSOCIATED_TOKEN).len());
+    assert_eq!(
+        ExtractedElf::from_program_data(&program_data.data)
+            .unwrap()
+            .elf,
+        context.elf(&ASSOCIATED_TOKEN)
+    );
+}
+
+#[test]
+fn extend() {
+    // Given a deployed program.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+    let program_id = context
+        .run(&["deploy", &program_file])
+        .unwrap()
+        .program_id
+        .unwrap();
+
+    // When we extend it.
+    context.run(&["extend", &program_id, "1024"]).unwrap();
+
+    // Then the ProgramData account grew.
+    let program_data = context
+        .account(&find_program_data_address(
+            &Address::from_str(&program_id).unwrap(),
+        ))
+        .unwrap();
+    assert_eq!(
+        ProgramData::unpack(&program_data.data).unwrap().data.len(),
+        context.elf(&MEMO).len() + 1024
+    );
+}
+
+#[test]
+fn set_authority_unchecked_checked_and_final() {
+    // Given a deployed program.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+    let program_id = context
+        .run(&["deploy", &program_file])
+        .unwrap()
+        .program_id
+        .unwrap();
+    let program_data_address = find_program_data_address(&Address::from_str(&program_id).unwrap());
+    let upgrade_authority = |context: &TestContext| {
+        let account = context.account(&program_data_address).unwrap();
+        ProgramData::unpack(&account.data)
+            .unwrap()
+            .upgrade_authority
+    };
+
+    // When we hand it to an address without its signature.
+    let (first, first_path) = context.keypair("first");
+    let output = context
+        .run(&[
+            "set-authority",
+            &program_id,
+            "--new-authority",
+            &first.pubkey().to_string(),
+        ])
+        .unwrap();
+    assert_eq!(output.new_authority, Some(first.pubkey().to_string()));
+    assert_eq!(upgrade_authority(&context), Some(first.pubkey()));
+
+    // And then, checked, to a second authority that signs.
+    let (second, second_path) = context.keypair("second");
+    context
+        .run(&[
+            "set-authority",
+            &program_id,
+            "--authority",
+            &first_path,
+            "--new-authority",
+            &second_path,
+            "--checked",
+        ])
+        .unwrap();
+    assert_eq!(upgrade_authority(&context), Some(second.pubkey()));
+
+    // And a checked change to an address that cannot sign fails.
+    let error = context
+        .run(&[
+            "set-authority",
+            &program_id,
+            "--authority",
+            &second_path,
+            "--new-authority",
+            &first.pubkey().to_string(),
+            "--checked",
+        ])
+        .unwrap_err();
+    assert_eq!(error, CliError::MissingKeypair(first.pubkey()));
+
+    // And finally make it immutable.
+    let output = context
+        .run(&[
+            "set-authority",
+            &program_id,
+            "--authority",
+            &second_path,
+            "--final",
+        ])
+        .unwrap();
+    assert_eq!(output.new_authority, Some("none".to_string()));
+    assert_eq!(upgrade_authority(&context), None);
+}
+
+#[test]
+fn set_buffer_authority() {
+    // Given a buffer.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+    let buffer = context
+        .run(&["write-buffer", &program_file])
+        .unwrap()
+        .buffer
+        .unwrap();
+
+    // When we change its authority.
+    let (authority, authority_path) = context.keypair("authority");
+    context
+        .run(&[
+            "set-authority",
+            &buffer,
+            "--buffer",
+            "--new-authority",
+            &authority_path,
+            "--checked",
+        ])
+        .unwrap();
+
+    // Then the buffer has the new authority.
+    let account = context
+        .account(&Address::from_str(&buffer).unwrap())
+        .unwrap();
+    assert_eq!(
+        Buffer::unpack(&account.data).unwrap().authority,
+        Some(authority.pubkey())
+    );
+
+    // And it cannot be made immutable.
+    let error = context
+        .run(&[
+            "set-authority",
+            &buffer,
+            "--buffer",
+            "--authority",
+            &authority_path,
+            "--final",
+        ])
+        .unwrap_err();
+    assert_eq!(error, CliError::ImmutableBuffer);
+}
+
+#[test]
+fn set_authority_rejects_wrong_account_kind() {
+    // Given a buffer.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+    let buffer = context
+        .run(&["write-buffer", &program_file])
+        .unwrap()
+        .buffer
+        .unwrap();
+
+    // When we treat it as a program.
+    let error = context
+        .run(&["set-authority", &buffer, "--final"])
+        .unwrap_err();
+
+    // Then the command fails before sending anything.
+    assert_eq!(
+        error,
+        CliError::State(StateError::UnexpectedState {
+            expected: "Program"
+        })
+    );
+}
+
+#[test]
+fn close_buffer() {
+    // Given a buffer.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+    let buffer = context
+        .run(&["write-buffer", &program_file])
+        .unwrap()
+        .buffer
+        .unwrap();
+    let buffer = Address::from_str(&buffer).unwrap();
+    let lamports = context.account(&buffer).unwrap().lamports;
+    let recipient = Address::new_unique();
+
+    // When we close it.
+    context
+        .run(&[
+            "close",
+            &buffer.to_string(),
+            "--buffer",
+            "--recipient",
+            &recipient.to_string(),
+        ])
+        .unwrap();
+
+    // Then the account is gone and its lamports reclaimed.
+    assert!(context.account(&buffer).is_none());
+    assert_eq!(context.account(&recipient).unwrap().lamports, lamports);
+}
+
+#[test]
+fn close_program_rejects_buffer() {
+    // Given a buffer.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+    let buffer = context
+        .run(&["write-buffer", &program_file])
+        .unwrap()
+        .buffer
+        .unwrap();
+
+    // When we close it as a program.
+    let error = context.run(&["close", &buffer]).unwrap_err();
+
+    // Then the command fails before sending anything.
+    assert_eq!(
+        error,
+        CliError::State(StateError::UnexpectedState {
+            expected: "Program"
+        })
+    );
+}
+
+#[test]
+fn offline_deploy_emits_unsigned_transactions() {
+    // Given program and buffer keypairs and a blockhash.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+    let (program, program_path) = context.keypair("program");
+    let (buffer, buffer_path) = context.keypair("buffer");
+    let blockhash = Hash::new_from_array([7; 32]);
+
+    // When we deploy offline.
+    let output = context
+        .run(&[
+            "--offline",
+            "--blockhash",
+            &blockhash.to_string(),
+            "--output",
+            "json",
+            "deploy",
+            &program_file,
+            "--program-id",
+            &program_path,
+            "--buffer",
+            &buffer_path,
+        ])
+        .unwrap();
+
+    // Then nothing was sent.
+    assert!(output.signatures.is_empty());
+    assert!(context.account(&program.pubkey()).is_none());
+
+    // And the create-buffer, write and deploy transactions are unsigned.
+    let write_count = context.elf(&MEMO).len().div_ceil(1012);
+    assert_eq!(output.transactions.len(), write_count + 2);
+    let payer = context.payer.pubkey().to_string();
+    assert_eq!(
+        output.transactions[0].signers,
+        [payer.clone(), buffer.pubkey().to_string()]
+    );
+    assert_eq!(output.transactions[1].signers, [payer.as_str()]);
+    assert_eq!(
+        output.transactions.last().unwrap().signers,
+        [payer, program.pubkey().to_string()]
+    );
+    for unsigned in &output.transactions {
+        let bytes = BASE64_STANDARD.decode(&unsigned.transaction).unwrap();
+        let transaction: Transaction = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(transaction.message.recent_blockhash, blockhash);
+        assert!(transaction
+            .signatures
+            .iter()
+            .all(|signature| *signature == Default::default()));
+    }
+
+    // And the JSON output round-trips.
+    let json = output.format(solana_loader_v3_cli::args::OutputFormat::Json);
+    assert_eq!(
+        serde_json::from_str::<CommandOutput>(&json).unwrap(),
+        output
+    );
+}
+
+#[test]
+fn offline_accepts_addresses_for_signers() {
+    // Given only the addresses of the accounts involved.
+    let mut context = TestContext::new();
+    let program = Address::new_unique();
+    let authority = Address::new_unique();
+
+    // When we build a close transaction offline. LiteSVM cannot reload a
+    // program whose ProgramData account was closed, so closing programs is
+    // only checked offline.
+    let output = context
+        .run_as(
+            &authority.to_string(),
+            &[
+                "--offline",
+                "--blockhash",
+                &Hash::new_from_array([7; 32]).to_string(),
+                "close",
+                &program.to_string(),
+                "--bypass-warning",
+            ],
+        )
+        .unwrap();
+
+    // Then it lists the authority, which also pays, as the only signer.
+    assert_eq!(output.program_id, Some(program.to_string()));
+    assert_eq!(output.transactions.len(), 1);
+    assert_eq!(output.transactions[0].signers, [authority.to_string()]);
+
+    // And closes the ProgramData account with the Program account writable.
+    let bytes = BASE64_STANDARD
+        .decode(&output.transactions[0].transaction)
+        .unwrap();
+    let transaction: Transaction = bincode::deserialize(&bytes).unwrap();
+    let message = &transaction.message;
+    let accounts = &message.instructions[0].accounts;
+    let key = |index: usize| message.account_keys[usize::from(accounts[index])];
+    assert_eq!(key(0), find_program_data_address(&program));
+    assert_eq!(key(3), program);
+    assert!(message.is_maybe_writable(usize::from(accounts[3]), None));
+}
+
+#[test]
+fn close_program_requires_bypass_warning() {
+    // Given a deployed program and a buffer.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+    let program = context
+        .run(&["deploy", &program_file])
+        .unwrap()
+        .program_id
+        .unwrap();
+    let buffer = context
+        .run(&["write-buffer", &program_file])
+        .unwrap()
+        .buffer
+        .unwrap();
+
+    // When we close the program without confirming.
+    let error = context.run(&["close", &program]).unwrap_err();
+
+    // Then the command fails before sending anything.
+    let program = Address::from_str(&program).unwrap();
+    assert_eq!(error, CliError::IrreversibleClose(program));
+    assert!(context
+        .account(&find_program_data_address(&program))
+        .is_some());
+
+    // While closing a buffer needs no confirmation.
+    context.run(&["close", &buffer, "--buffer"]).unwrap();
+    assert!(context
+        .account(&Address::from_str(&buffer).unwrap())
+        .is_none());
+}
+
+#[test]
+fn offline_requires_new_account_keypairs() {
+    // Given a program file.
+    let mut context = TestContext::new();
+    let program_file = context.program_file("memo", &MEMO);
+
+    // When we deploy offline without naming the program account.
+    let error = context
+        .run(&[
+            "--offline",
+            "--blockhash",
+            &Hash::new_from_array([7; 32]).to_string(),
+            "deploy",
+            &program_file,
+        ])
+        .unwrap_err();
+
+    // Then the command fails, since a generated keypair would be lost.
+    assert_eq!(error, CliError::OfflineArgument("program-id"));
+}
+
+#[test]
+fn rejects_invalid_elf() {
+    // Given a file that is not an ELF.
+    let mut context = TestContext::new();
+    let path = context.path("garbage.so");
+    std::fs::write(&path, [0u8; 128]).unwrap();
+
+    // When we try to deploy it.
+    let error = context
+        .run(&["deploy", &path.display().to_string()])
+        .unwrap_err();
+
+    // Then it is rejected before any transaction is sent.
+    assert!(matches!(error, CliError::InvalidElf(_)));
+}
@@ -0,0 +1,92 @@
+#![allow(dead_code)]
+
+use {
+    clap::Parser,
+    solana_account::Account,
+    solana_address::Address,
+    solana_keypair::{write_keypair_file, Keypair},
+    solana_loader_v3_cli::{run, Cli, CliError, CommandOutput},
+    solana_loader_v3_program_client::svm::SvmRpc,
+    solana_signer::Signer,
+    std::path::PathBuf,
+    tempfile::TempDir,
+};
+
+/// SPL Memo 3.0 and Associated Token Account 1.1, preloaded by LiteSVM.
+/// Their ELF bytes are used as test programs.
+pub const MEMO: Address = Address::from_str_const("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+pub const ASSOCIATED_TOKEN: Address =
+    Address::from_str_const("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// A LiteSVM instance with a funded fee payer whose keypair, like every
+/// other file the test needs, lives in a temporary directory.
+pub struct TestContext {
+    pub rpc: SvmRpc,
+    pub dir: TempDir,
+    pub payer: Keypair,
+}
+
+impl TestContext {
+    pub fn new() -> Self {
+        let mut rpc = SvmRpc::new();
+        let payer = Keypair::new();
+        rpc.svm.airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
+        let context = Self {
+            rpc,
+            dir: TempDir::new().unwrap(),
+            payer,
+        };
+        context.write_keypair("payer", &context.payer);
+        context
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    /// Writes `keypair` to `<name>.json` and returns the path.
+    pub fn write_keypair(&self, name: &str, keypair: &Keypair) -> String {
+        let path = self.path(&format!("{name}.json"));
+        write_keypair_file(keypair, &path).unwrap();
+        path.display().to_string()
+    }
+
+    /// Writes a new keypair to `<name>.json`.
+    pub fn keypair(&self, name: &str) -> (Keypair, String) {
+        let keypair = Keypair::new();
+        let path = self.write_keypair(name, &keypair);
+        (keypair, path)
+    }
+
+    /// Writes the ELF of a preloaded program to `<name>.so`.
+    pub fn program_file(&self, name: &str, program: &Address) -> String {
+        let path = self.path(&format!("{name}.so"));
+        std::fs::write(&path, self.elf(program)).unwrap();
+        path.display().to_string()
+    }
+
+    pub fn elf(&self, program: &Address) -> Vec<u8> {
+        self.rpc.svm.get_account(program).unwrap().data
+    }
+
+    /// Runs `loader-v3` with the payer keypair and `args`.
+    pub fn run(&mut self, args: &[&str]) -> Result<CommandOutput, CliError> {
+        let keypair = self.path("payer.json").display().to_string();
+        self.run_as(&keypair, args)
+    }
+
+    /// Runs `loader-v3` with `keypair` as fee payer and `args`.
+    pub fn run_as(&mut self, keypair: &str, args: &[&str]) -> Result<CommandOutput, CliError> {
+        let cli = Cli::try_parse_from(
+            ["loader-v3", "--keypair", keypair]
+                .into_iter()
+                .chain(args.iter().copied()),
+        )
+        .unwrap();
+        run(&cli, &mut self.rpc)
+    }
+
+    pub fn account(&self, address: &Address) -> Option<Account> {
+        self.rpc.svm.get_account(address)
+    }
+}
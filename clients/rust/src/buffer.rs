@@ -0,0 +1,95 @@
+//! Instructions creating Buffer accounts.
+//!
+//! [`create_buffer`] pairs the system `CreateAccount` instruction funding a
+//! rent-exempt account of the right size with `InitializeBuffer`.
+//!
+//! `InitializeBuffer` accepts any uninitialized account owned by the loader,
+//! so a buffer does not need its own keypair: [`create_buffer_with_seed`]
+//! creates it at an address derived with `create_account_with_seed` from
+//! the payer and a seed taken from the ELF hash. A deploy restarted with the
+//! same payer and ELF finds its buffer at [`find_buffer_address`] again.
+//!
+//! Two concurrent uploads of the same ELF by the same payer share that
+//! address, so only one of them can create the buffer.
+
+use {
+    crate::{
+        instructions::InitializeBufferBuilder, state::buffer_account_size, verify::ProgramHash,
+    },
+    solana_address::Address,
+    solana_instruction::Instruction,
+    solana_rent::Rent,
+    solana_system_interface::instruction::{create_account, create_account_with_seed},
+};
+
+/// Instructions creating a rent-exempt Buffer account for `data_len` bytes
+/// and initializing it with `authority`.
+///
+/// Both `payer` and `buffer` sign the `CreateAccount` instruction.
+pub fn create_buffer(
+    payer: &Address,
+    buffer: &Address,
+    authority: &Address,
+    data_len: usize,
+    rent: &Rent,
+) -> Vec<Instruction> {
+    let size = buffer_account_size(data_len).expect("buffer size overflows");
+    vec![
+        create_account(
+            payer,
+            buffer,
+            rent.minimum_balance(size),
+            size as u64,
+            &crate::LOADER_V3_ID,
+        ),
+        initialize_buffer(buffer, authority),
+    ]
+}
+
+/// Seed of the buffer holding `elf`: the first 16 bytes of its
+/// [`ProgramHash`] in hex, which is the maximum seed length.
+pub fn buffer_seed(elf: &[u8]) -> String {
+    let mut seed = ProgramHash::of_elf(elf).to_string();
+    seed.truncate(solana_address::MAX_SEED_LEN);
+    seed
+}
+
+/// Address of the buffer created by [`create_buffer_with_seed`] for `elf`
+/// and `payer`.
+pub fn find_buffer_address(payer: &Address, elf: &[u8]) -> Address {
+    Address::create_with_seed(payer, &buffer_seed(elf), &crate::LOADER_V3_ID)
+        .expect("seed is valid")
+}
+
+/// Instructions creating a rent-exempt Buffer account for `elf` at
+/// [`find_buffer_address`] and initializing it with `authority`.
+///
+/// Only `payer` signs: it funds the account and is the base of its address.
+pub fn create_buffer_with_seed(
+    payer: &Address,
+    authority: &Address,
+    elf: &[u8],
+    rent: &Rent,
+) -> Vec<Instruction> {
+    let buffer = find_buffer_address(payer, elf);
+    let size = buffer_account_size(elf.len()).expect("buffer size overflows");
+    vec![
+        create_account_with_seed(
+            payer,
+            &buffer,
+            payer,
+            &buffer_seed(elf),
+            rent.minimum_balance(size),
+            size as u64,
+            &crate::LOADER_V3_ID,
+        ),
+        initialize_buffer(&buffer, authority),
+    ]
+}
+
+fn initialize_buffer(buffer: &Address, authority: &Address) -> Instruction {
+    InitializeBufferBuilder::new()
+        .source_account(*buffer)
+        .buffer_authority(*authority)
+        .instruction()
+}
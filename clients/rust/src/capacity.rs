@@ -0,0 +1,139 @@
+//! Capacity planning for ProgramData accounts.
+//!
+//! A [`CapacityPolicy`] decides how many bytes a ProgramData account should
+//! be able to hold for a given ELF length. The planning functions turn that
+//! decision into `DeployWithMaxDataLen` and `ExtendProgram` arguments,
+//! clamped to [`MAX_PROGRAM_DATA_LEN`].
+
+use {
+    crate::{
+        instructions::{DeployWithMaxDataLenInstructionArgs, ExtendProgramInstructionArgs},
+        state::MAX_PROGRAM_DATA_LEN,
+    },
+    thiserror::Error,
+};
+
+/// Errors returned when planning ProgramData capacity.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum CapacityError {
+    #[error("program length {elf_len} exceeds the maximum of {MAX_PROGRAM_DATA_LEN} bytes")]
+    ElfTooLarge { elf_len: usize },
+    #[error("cannot extend by {additional_bytes} bytes in a single instruction")]
+    ExtensionTooLarge { additional_bytes: usize },
+}
+
+/// Strategy deciding the capacity of a ProgramData account.
+pub trait CapacityPolicy {
+    /// Returns the desired number of program bytes for an ELF of `elf_len`
+    /// bytes. The result is clamped by the planning functions, so it may
+    /// exceed [`MAX_PROGRAM_DATA_LEN`].
+    fn capacity(&self, elf_len: usize) -> usize;
+}
+
+/// Allocates exactly the ELF length, leaving no room to grow.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Exact;
+
+impl CapacityPolicy for Exact {
+    fn capacity(&self, elf_len: usize) -> usize {
+        elf_len
+    }
+}
+
+/// Allocates a fixed number of bytes on top of the ELF length.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FixedHeadroom {
+    pub bytes: usize,
+}
+
+impl CapacityPolicy for FixedHeadroom {
+    fn capacity(&self, elf_len: usize) -> usize {
+        elf_len.saturating_add(self.bytes)
+    }
+}
+
+/// Allocates a percentage of the ELF length on top of it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PercentageGrowth {
+    pub percent: u16,
+}
+
+impl CapacityPolicy for PercentageGrowth {
+    fn capacity(&self, elf_len: usize) -> usize {
+        let headroom = elf_len
+            .saturating_mul(usize::from(self.percent))
+            .div_ceil(100);
+        elf_len.saturating_add(headroom)
+    }
+}
+
+/// Allocates room for a number of future upgrades, based on the average
+/// growth between previously deployed ELF lengths.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HistoricalGrowth {
+    /// Previously deployed ELF lengths, oldest first.
+    pub history: Vec<usize>,
+    /// Number of upgrades the account should absorb without extending.
+    pub upgrades: usize,
+}
+
+impl HistoricalGrowth {
+    /// Average growth per upgrade across the history, ignoring shrinking
+    /// programs. Returns zero with fewer than two entries.
+    pub fn average_growth(&self) -> usize {
+        let (Some(first), Some(last)) = (self.history.first(), self.history.last()) else {
+            return 0;
+        };
+        let upgrades = self.history.len().saturating_sub(1);
+        if upgrades == 0 {
+            return 0;
+        }
+        last.saturating_sub(*first).div_ceil(upgrades)
+    }
+}
+
+impl CapacityPolicy for HistoricalGrowth {
+    fn capacity(&self, elf_len: usize) -> usize {
+        elf_len.saturating_add(self.average_growth().saturating_mul(self.upgrades))
+    }
+}
+
+/// Computes the ProgramData capacity for an ELF of `elf_len` bytes under
+/// `policy`, clamped between the ELF length and [`MAX_PROGRAM_DATA_LEN`].
+pub fn plan_capacity(policy: &impl CapacityPolicy, elf_len: usize) -> Result<usize, CapacityError> {
+    if elf_len > MAX_PROGRAM_DATA_LEN {
+        return Err(CapacityError::ElfTooLarge { elf_len });
+    }
+    Ok(policy
+        .capacity(elf_len)
+        .clamp(elf_len, MAX_PROGRAM_DATA_LEN))
+}
+
+/// Plans the `DeployWithMaxDataLen` arguments for an ELF of `elf_len` bytes.
+pub fn plan_deploy(
+    policy: &impl CapacityPolicy,
+    elf_len: usize,
+) -> Result<DeployWithMaxDataLenInstructionArgs, CapacityError> {
+    Ok(DeployWithMaxDataLenInstructionArgs {
+        max_data_len: plan_capacity(policy, elf_len)? as u64,
+    })
+}
+
+/// Plans the `ExtendProgram` arguments needed before upgrading a program
+/// whose ProgramData currently holds `max_data_len` bytes to an ELF of
+/// `elf_len` bytes.
+///
+/// Returns `None` when the ELF already fits and no extension is required.
+pub fn plan_extend(
+    policy: &impl CapacityPolicy,
+    max_data_len: usize,
+    elf_len: usize,
+) -> Result<Option<ExtendProgramInstructionArgs>, CapacityError> {
+    if elf_len <= max_data_len {
+        return Ok(None);
+    }
+    let additional_bytes = plan_capacity(policy, elf_len)?.saturating_sub(max_data_len);
+    let additional_bytes = u32::try_from(additional_bytes)
+        .map_err(|_| CapacityError::ExtensionTooLarge { additional_bytes })?;
+    Ok(Some(ExtendProgramInstructionArgs { additional_bytes }))
+}
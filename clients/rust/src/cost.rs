@@ -0,0 +1,117 @@
+//! Rent and fee estimation for deploying and upgrading programs.
+
+use {
+    crate::state::{buffer_account_size, program_data_account_size, PROGRAM_ACCOUNT_SIZE},
+    solana_rent::Rent,
+    thiserror::Error,
+};
+
+/// Maximum number of program bytes carried by a single `Write` transaction.
+///
+/// This assumes a legacy transaction whose fee payer is also the buffer
+/// authority, so the transaction carries a single signature and three
+/// account keys (authority, buffer and the loader program).
+pub const WRITE_CHUNK_SIZE: usize = 1012;
+
+/// Signatures required by the transaction creating and initializing the
+/// buffer (payer and buffer keypair).
+const CREATE_BUFFER_SIGNATURES: u64 = 2;
+
+/// Signatures required by the `DeployWithMaxDataLen` transaction (payer and
+/// program keypair, the payer also being the upgrade authority).
+const DEPLOY_SIGNATURES: u64 = 2;
+
+/// Errors returned when estimating deploy costs.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum CostError {
+    #[error("program length {elf_len} exceeds max_data_len {max_data_len}")]
+    ElfLargerThanMaxDataLen { elf_len: usize, max_data_len: usize },
+    #[error("arithmetic overflow while estimating costs")]
+    Overflow,
+}
+
+/// Lamports and transactions required to deploy a program.
+///
+/// Fees assume the payer is also the buffer and upgrade authority.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeployCost {
+    /// Rent-exempt balance of the Buffer account holding the ELF.
+    pub buffer_lamports: u64,
+    /// Rent-exempt balance of the Program account.
+    pub program_lamports: u64,
+    /// Rent-exempt balance of the ProgramData account sized for
+    /// `max_data_len`.
+    pub program_data_lamports: u64,
+    /// Number of `Write` transactions needed to upload the ELF.
+    pub write_transactions: u64,
+    /// Signature fees for creating the buffer, writing the ELF and deploying.
+    pub signature_fees: u64,
+    /// Lamports sent to the spill account when `Upgrade` consumes a buffer
+    /// of the same size, assuming the ProgramData account is exactly
+    /// rent-exempt.
+    pub upgrade_spill_refund: u64,
+}
+
+impl DeployCost {
+    /// Estimates the cost of deploying an ELF of `elf_len` bytes into a
+    /// ProgramData account able to hold `max_data_len` bytes.
+    pub fn estimate(
+        elf_len: usize,
+        max_data_len: usize,
+        rent: &Rent,
+        lamports_per_signature: u64,
+    ) -> Result<Self, CostError> {
+        if elf_len > max_data_len {
+            return Err(CostError::ElfLargerThanMaxDataLen {
+                elf_len,
+                max_data_len,
+            });
+        }
+        let buffer_lamports =
+            rent.minimum_balance(buffer_account_size(elf_len).ok_or(CostError::Overflow)?);
+        let program_lamports = rent.minimum_balance(PROGRAM_ACCOUNT_SIZE);
+        let program_data_lamports = rent
+            .minimum_balance(program_data_account_size(max_data_len).ok_or(CostError::Overflow)?);
+        let write_transactions = write_transaction_count(elf_len);
+        let signature_fees = write_transactions
+            .checked_add(CREATE_BUFFER_SIGNATURES)
+            .and_then(|signatures| signatures.checked_add(DEPLOY_SIGNATURES))
+            .and_then(|signatures| signatures.checked_mul(lamports_per_signature))
+            .ok_or(CostError::Overflow)?;
+
+        Ok(Self {
+            buffer_lamports,
+            program_lamports,
+            program_data_lamports,
+            write_transactions,
+            signature_fees,
+            // The loader drains the whole buffer into the spill account and
+            // only keeps what ProgramData needs to stay rent-exempt.
+            upgrade_spill_refund: buffer_lamports,
+        })
+    }
+
+    /// Lamports that remain locked in the Program and ProgramData accounts
+    /// once the program is deployed.
+    ///
+    /// The buffer balance is returned to the payer by
+    /// `DeployWithMaxDataLen`, so it is not included.
+    pub fn locked_lamports(&self) -> u64 {
+        self.program_lamports
+            .saturating_add(self.program_data_lamports)
+    }
+
+    /// Lamports the payer must hold before starting the deploy: the buffer
+    /// rent, the locked rent and all signature fees.
+    pub fn required_balance(&self) -> u64 {
+        self.buffer_lamports
+            .saturating_add(self.locked_lamports())
+            .saturating_add(self.signature_fees)
+    }
+}
+
+/// Number of `Write` transactions needed to upload `elf_len` bytes in
+/// [`WRITE_CHUNK_SIZE`] chunks.
+pub fn write_transaction_count(elf_len: usize) -> u64 {
+    elf_len.div_ceil(WRITE_CHUNK_SIZE) as u64
+}
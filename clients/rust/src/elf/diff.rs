@@ -0,0 +1,235 @@
+//! Section-level comparison of a deployed program with an upgrade candidate.
+
+use {
+    super::{
+        extract::{ExtractError, ExtractedElf},
+        parse::{Elf, ElfError, Symbol},
+        validate::SbpfVersion,
+    },
+    crate::state::{ProgramData, StateError},
+    std::collections::BTreeMap,
+    thiserror::Error,
+};
+
+/// Errors returned when diffing programs.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum DiffError {
+    #[error(transparent)]
+    Elf(#[from] ElfError),
+    #[error(transparent)]
+    Extract(#[from] ExtractError),
+    #[error(transparent)]
+    State(#[from] StateError),
+}
+
+/// Change of a single section between two programs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SectionDelta {
+    pub name: String,
+    /// Size in the deployed program, `None` if the section is new.
+    pub old_size: Option<u64>,
+    /// Size in the candidate, `None` if the section was removed.
+    pub new_size: Option<u64>,
+    /// Whether the section contents differ.
+    pub changed: bool,
+}
+
+impl SectionDelta {
+    /// Size difference in bytes, negative when the section shrinks.
+    pub fn size_delta(&self) -> i128 {
+        i128::from(self.new_size.unwrap_or_default())
+            .saturating_sub(i128::from(self.old_size.unwrap_or_default()))
+    }
+}
+
+/// Change of an exported symbol between two programs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SymbolChange {
+    Added(Symbol),
+    Removed(Symbol),
+    /// The symbol moved or changed size.
+    Modified {
+        old: Symbol,
+        new: Symbol,
+    },
+}
+
+/// A dynamic relocation, identified by its type and the name of the symbol
+/// it resolves, empty for relocations without a symbol.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct RelocationEntry {
+    pub relocation_type: u32,
+    pub symbol: String,
+}
+
+/// Differences between a deployed program and an upgrade candidate.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ElfDiff {
+    pub old_len: usize,
+    pub new_len: usize,
+    /// Every section present in either program, in the order they appear in
+    /// the deployed program followed by new sections.
+    pub sections: Vec<SectionDelta>,
+    /// Changes of dynamic symbols exported by either program.
+    pub exported_symbols: Vec<SymbolChange>,
+    pub old_relocations: usize,
+    pub new_relocations: usize,
+    /// Relocations the candidate has more of, one entry per additional
+    /// relocation. Offsets are ignored, as any code change moves them.
+    pub added_relocations: Vec<RelocationEntry>,
+    /// Relocations the candidate has fewer of, one entry per missing
+    /// relocation.
+    pub removed_relocations: Vec<RelocationEntry>,
+    pub old_sbpf_version: SbpfVersion,
+    pub new_sbpf_version: SbpfVersion,
+    /// Program bytes the ProgramData account can hold.
+    pub capacity: usize,
+}
+
+impl ElfDiff {
+    /// Compares two ELF files, `capacity` being the number of program bytes
+    /// the ProgramData account can currently hold.
+    pub fn new(old: &[u8], new: &[u8], capacity: usize) -> Result<Self, DiffError> {
+        let old_elf = Elf::parse(old)?;
+        let new_elf = Elf::parse(new)?;
+
+        let mut sections = Vec::new();
+        for old_section in old_elf.sections().iter().filter(|s| !s.name.is_empty()) {
+            let new_section = new_elf.section(&old_section.name);
+            sections.push(SectionDelta {
+                name: old_section.name.clone(),
+                old_size: Some(old_section.sh_size),
+                new_size: new_section.map(|section| section.sh_size),
+                changed: new_section.is_none_or(|new_section| {
+                    old_elf.section_data(old_section) != new_elf.section_data(new_section)
+                }),
+            });
+        }
+        for new_section in new_elf.sections().iter().filter(|s| !s.name.is_empty()) {
+            if old_elf.section(&new_section.name).is_none() {
+                sections.push(SectionDelta {
+                    name: new_section.name.clone(),
+                    old_size: None,
+                    new_size: Some(new_section.sh_size),
+                    changed: true,
+                });
+            }
+        }
+
+        let (added_relocations, removed_relocations) = diff_relocations(&old_elf, &new_elf);
+        Ok(Self {
+            old_len: old.len(),
+            new_len: new.len(),
+            sections,
+            exported_symbols: diff_symbols(&old_elf, &new_elf),
+            old_relocations: old_elf.relocations().len(),
+            new_relocations: new_elf.relocations().len(),
+            added_relocations,
+            removed_relocations,
+            old_sbpf_version: SbpfVersion::from_e_flags(old_elf.header().e_flags),
+            new_sbpf_version: SbpfVersion::from_e_flags(new_elf.header().e_flags),
+            capacity,
+        })
+    }
+
+    /// Compares the program deployed in a ProgramData account with a
+    /// candidate ELF.
+    pub fn from_program_data(program_data: &[u8], candidate: &[u8]) -> Result<Self, DiffError> {
+        let capacity = ProgramData::unpack(program_data)?.data.len();
+        let deployed = ExtractedElf::from_program_data(program_data)?;
+        Self::new(deployed.elf, candidate, capacity)
+    }
+
+    /// Compares the program deployed in a ProgramData account with the
+    /// program written to a Buffer account.
+    pub fn from_program_data_and_buffer(
+        program_data: &[u8],
+        buffer: &[u8],
+    ) -> Result<Self, DiffError> {
+        Self::from_program_data(program_data, ExtractedElf::from_buffer(buffer)?.elf)
+    }
+
+    /// Only the sections whose contents or size changed.
+    pub fn changed_sections(&self) -> impl Iterator<Item = &SectionDelta> {
+        self.sections.iter().filter(|section| section.changed)
+    }
+
+    pub fn sbpf_version_changed(&self) -> bool {
+        self.old_sbpf_version != self.new_sbpf_version
+    }
+
+    /// Bytes `ExtendProgram` must add before the candidate can be deployed,
+    /// or `None` if it already fits.
+    pub fn required_extension(&self) -> Option<usize> {
+        self.new_len
+            .checked_sub(self.capacity)
+            .filter(|bytes| *bytes > 0)
+    }
+}
+
+fn diff_symbols(old: &Elf, new: &Elf) -> Vec<SymbolChange> {
+    let exported = |elf: &Elf| {
+        elf.dynamic_symbols()
+            .into_iter()
+            .filter(Symbol::is_exported)
+            .map(|symbol| (symbol.name.clone(), symbol))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let mut old_symbols = exported(old);
+    let new_symbols = exported(new);
+
+    let mut changes = Vec::new();
+    for (name, new_symbol) in new_symbols {
+        match old_symbols.remove(&name) {
+            None => changes.push(SymbolChange::Added(new_symbol)),
+            Some(old_symbol)
+                if old_symbol.st_value != new_symbol.st_value
+                    || old_symbol.st_size != new_symbol.st_size =>
+            {
+                changes.push(SymbolChange::Modified {
+                    old: old_symbol,
+                    new: new_symbol,
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    changes.extend(old_symbols.into_values().map(SymbolChange::Removed));
+    changes
+}
+
+/// Relocations added and removed between two programs, compared as
+/// multisets of [`RelocationEntry`].
+fn diff_relocations(old: &Elf, new: &Elf) -> (Vec<RelocationEntry>, Vec<RelocationEntry>) {
+    let counts = |elf: &Elf| {
+        let symbols = elf.dynamic_symbols();
+        let mut counts = BTreeMap::<_, usize>::new();
+        for relocation in elf.relocations() {
+            let entry = RelocationEntry {
+                relocation_type: relocation.relocation_type(),
+                symbol: symbols
+                    .get(relocation.symbol_index() as usize)
+                    .map(|symbol| symbol.name.clone())
+                    .unwrap_or_default(),
+            };
+            let count = counts.entry(entry).or_default();
+            *count = count.saturating_add(1);
+        }
+        counts
+    };
+    let old_counts = counts(old);
+    let new_counts = counts(new);
+    let surplus = |from: &BTreeMap<RelocationEntry, usize>,
+                   to: &BTreeMap<RelocationEntry, usize>| {
+        from.iter()
+            .flat_map(|(entry, count)| {
+                let surplus = count.saturating_sub(to.get(entry).copied().unwrap_or_default());
+                std::iter::repeat_n(entry.clone(), surplus)
+            })
+            .collect()
+    };
+    (
+        surplus(&new_counts, &old_counts),
+        surplus(&old_counts, &new_counts),
+    )
+}
@@ -0,0 +1,82 @@
+//! Extraction of program binaries from loader-v3 accounts.
+
+use {
+    super::parse::{Elf, ElfError},
+    crate::state::{Buffer, LoaderState, ProgramData, StateError},
+    solana_address::Address,
+    thiserror::Error,
+};
+
+/// Errors returned when extracting an ELF from account data.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum ExtractError {
+    #[error(transparent)]
+    State(#[from] StateError),
+    #[error("account does not hold a program: {0}")]
+    Elf(#[from] ElfError),
+    #[error("ELF headers describe {extent} bytes but the account holds {available}")]
+    Truncated { extent: usize, available: usize },
+}
+
+/// The account an ELF was extracted from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ElfSource {
+    Buffer {
+        authority: Option<Address>,
+    },
+    ProgramData {
+        /// Slot of the last deploy or upgrade.
+        slot: u64,
+        upgrade_authority: Option<Address>,
+    },
+}
+
+/// An ELF embedded in a Buffer or ProgramData account.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ExtractedElf<'a> {
+    /// The ELF bytes, without the account header or trailing padding.
+    pub elf: &'a [u8],
+    pub source: ElfSource,
+}
+
+impl<'a> ExtractedElf<'a> {
+    /// Extracts the ELF from a Buffer or ProgramData account's data.
+    pub fn from_account_data(data: &'a [u8]) -> Result<Self, ExtractError> {
+        match LoaderState::unpack(data)? {
+            LoaderState::Buffer { .. } => Self::from_buffer(data),
+            _ => Self::from_program_data(data),
+        }
+    }
+
+    /// Extracts the ELF from a Buffer account's data.
+    pub fn from_buffer(data: &'a [u8]) -> Result<Self, ExtractError> {
+        let buffer = Buffer::unpack(data)?;
+        Ok(Self {
+            elf: trim(buffer.data)?,
+            source: ElfSource::Buffer {
+                authority: buffer.authority,
+            },
+        })
+    }
+
+    /// Extracts the ELF from a ProgramData account's data.
+    pub fn from_program_data(data: &'a [u8]) -> Result<Self, ExtractError> {
+        let program_data = ProgramData::unpack(data)?;
+        Ok(Self {
+            elf: trim(program_data.data)?,
+            source: ElfSource::ProgramData {
+                slot: program_data.slot,
+                upgrade_authority: program_data.upgrade_authority,
+            },
+        })
+    }
+}
+
+/// Cuts `data` to the length described by its ELF headers.
+fn trim(data: &[u8]) -> Result<&[u8], ExtractError> {
+    let extent = Elf::parse(data)?.extent();
+    data.get(..extent).ok_or(ExtractError::Truncated {
+        extent,
+        available: data.len(),
+    })
+}
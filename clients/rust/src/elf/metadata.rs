@@ -0,0 +1,186 @@
+//! Metadata embedded in program binaries.
+//!
+//! Programs using `solana-security-txt` embed a list of NUL-terminated
+//! key/value strings between begin and end markers, usually in a
+//! `.security.txt` section. Compilers and linkers record their versions in
+//! the `.comment` section.
+
+use {
+    super::{
+        extract::{ExtractError, ExtractedElf},
+        parse::{Elf, ElfError},
+    },
+    thiserror::Error,
+};
+
+const SECURITY_TXT_BEGIN: &[u8] = b"=======BEGIN SECURITY.TXT V1=======\0";
+const SECURITY_TXT_END: &[u8] = b"=======END SECURITY.TXT V1=======\0";
+
+/// Errors returned when reading embedded metadata.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum MetadataError {
+    #[error(transparent)]
+    Elf(#[from] ElfError),
+    #[error(transparent)]
+    Extract(#[from] ExtractError),
+    #[error("security.txt has no end marker")]
+    MissingEndMarker,
+    #[error("security.txt is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("security.txt key `{0}` has no value")]
+    MissingValue(String),
+    #[error("security.txt is missing required field `{0}`")]
+    MissingField(&'static str),
+}
+
+/// A way to reach a program's maintainers, such as `email:` or `discord:`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Contact {
+    /// Contact type before the colon, e.g. `email`.
+    pub kind: String,
+    pub value: String,
+}
+
+/// Parsed `security.txt` of a program.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SecurityTxt {
+    pub name: String,
+    pub project_url: String,
+    pub contacts: Vec<Contact>,
+    pub policy: String,
+    pub preferred_languages: Option<String>,
+    pub encryption: Option<String>,
+    pub source_code: Option<String>,
+    pub source_release: Option<String>,
+    pub source_revision: Option<String>,
+    pub auditors: Vec<String>,
+    pub acknowledgements: Option<String>,
+    pub expiry: Option<String>,
+    /// Keys not defined by the `solana-security-txt` format.
+    pub other: Vec<(String, String)>,
+}
+
+impl SecurityTxt {
+    /// Finds and parses the `security.txt` embedded in `elf`.
+    ///
+    /// The `.security.txt` section is searched first; if there is none the
+    /// whole file is scanned for the begin marker. Returns `None` when no
+    /// marker is found.
+    pub fn find(elf: &[u8]) -> Result<Option<Self>, MetadataError> {
+        let parsed = Elf::parse(elf)?;
+        let haystack = parsed
+            .section(".security.txt")
+            .and_then(|section| parsed.section_data(section))
+            .filter(|data| data.starts_with(SECURITY_TXT_BEGIN))
+            .unwrap_or(elf);
+        let Some(start) = find(haystack, SECURITY_TXT_BEGIN) else {
+            return Ok(None);
+        };
+        let body = &haystack[start.saturating_add(SECURITY_TXT_BEGIN.len())..];
+        let end = find(body, SECURITY_TXT_END).ok_or(MetadataError::MissingEndMarker)?;
+        Self::parse(&body[..end]).map(Some)
+    }
+
+    /// Parses the NUL-terminated key/value strings between the markers.
+    pub fn parse(body: &[u8]) -> Result<Self, MetadataError> {
+        let body = std::str::from_utf8(body).map_err(|_| MetadataError::InvalidUtf8)?;
+        let mut strings = body.split_terminator('\0');
+        let mut security_txt = Self::default();
+        let mut contacts = None;
+        while let Some(key) = strings.next() {
+            let value = strings
+                .next()
+                .ok_or_else(|| MetadataError::MissingValue(key.to_string()))?
+                .to_string();
+            match key {
+                "name" => security_txt.name = value,
+                "project_url" => security_txt.project_url = value,
+                "contacts" => contacts = Some(value),
+                "policy" => security_txt.policy = value,
+                "preferred_languages" => security_txt.preferred_languages = Some(value),
+                "encryption" => security_txt.encryption = Some(value),
+                "source_code" => security_txt.source_code = Some(value),
+                "source_release" => security_txt.source_release = Some(value),
+                "source_revision" => security_txt.source_revision = Some(value),
+                "auditors" => {
+                    security_txt.auditors = split_list(&value).map(String::from).collect()
+                }
+                "acknowledgements" => security_txt.acknowledgements = Some(value),
+                "expiry" => security_txt.expiry = Some(value),
+                _ => security_txt.other.push((key.to_string(), value)),
+            }
+        }
+
+        for (field, value) in [
+            ("name", &security_txt.name),
+            ("project_url", &security_txt.project_url),
+            ("policy", &security_txt.policy),
+        ] {
+            if value.is_empty() {
+                return Err(MetadataError::MissingField(field));
+            }
+        }
+        let contacts = contacts.ok_or(MetadataError::MissingField("contacts"))?;
+        security_txt.contacts = split_list(&contacts)
+            .map(|contact| match contact.split_once(':') {
+                Some((kind, value)) => Contact {
+                    kind: kind.trim().to_string(),
+                    value: value.trim().to_string(),
+                },
+                None => Contact {
+                    kind: "other".to_string(),
+                    value: contact.to_string(),
+                },
+            })
+            .collect();
+        Ok(security_txt)
+    }
+}
+
+/// Metadata embedded in a program binary.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ProgramMetadata {
+    pub security_txt: Option<SecurityTxt>,
+    /// Toolchain identification strings from the `.comment` section.
+    pub build: Vec<String>,
+}
+
+impl ProgramMetadata {
+    /// Reads the metadata of a local ELF.
+    pub fn from_elf(elf: &[u8]) -> Result<Self, MetadataError> {
+        let parsed = Elf::parse(elf)?;
+        let build = parsed
+            .section(".comment")
+            .and_then(|section| parsed.section_data(section))
+            .map(|data| {
+                data.split(|byte| *byte == 0)
+                    .filter_map(|string| std::str::from_utf8(string).ok())
+                    .filter(|string| !string.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            security_txt: SecurityTxt::find(elf)?,
+            build,
+        })
+    }
+
+    /// Reads the metadata of the program held by a Buffer or ProgramData
+    /// account.
+    pub fn from_account_data(data: &[u8]) -> Result<Self, MetadataError> {
+        Self::from_elf(ExtractedElf::from_account_data(data)?.elf)
+    }
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
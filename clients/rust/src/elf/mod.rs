@@ -0,0 +1,9 @@
+//! Inspection of SBF program binaries.
+
+mod diff;
+mod extract;
+mod metadata;
+mod parse;
+mod validate;
+
+pub use self::{diff::*, extract::*, metadata::*, parse::*, validate::*};
@@ -0,0 +1,448 @@
+//! Minimal ELF64 little-endian parser.
+
+use thiserror::Error;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+
+/// Size of the ELF64 file header.
+pub const ELF64_HEADER_SIZE: usize = 64;
+/// Size of an ELF64 section header.
+pub const ELF64_SECTION_HEADER_SIZE: usize = 64;
+/// Size of an ELF64 program header.
+pub const ELF64_PROGRAM_HEADER_SIZE: usize = 56;
+
+/// `e_machine` of programs compiled for the legacy BPF target.
+pub const EM_BPF: u16 = 247;
+/// `e_machine` of programs compiled for the SBF target.
+pub const EM_SBF: u16 = 263;
+
+/// `e_type` of shared objects.
+pub const ET_DYN: u16 = 3;
+
+/// Section holding a symbol table.
+pub const SHT_SYMTAB: u32 = 2;
+/// Section holding a string table.
+pub const SHT_STRTAB: u32 = 3;
+/// Section holding dynamic linking information.
+pub const SHT_DYNAMIC: u32 = 6;
+/// Section occupying no space in the file.
+pub const SHT_NOBITS: u32 = 8;
+/// Section holding relocations without addends.
+pub const SHT_REL: u32 = 9;
+/// Section holding the dynamic symbol table.
+pub const SHT_DYNSYM: u32 = 11;
+
+/// Section containing executable instructions.
+pub const SHF_EXECINSTR: u64 = 0x4;
+
+/// Loadable segment.
+pub const PT_LOAD: u32 = 1;
+
+/// Size of an ELF64 symbol table entry.
+pub const ELF64_SYMBOL_SIZE: usize = 24;
+/// Size of an ELF64 relocation entry without addend.
+pub const ELF64_REL_SIZE: usize = 16;
+
+/// Global symbol binding.
+pub const STB_GLOBAL: u8 = 1;
+/// Weak symbol binding.
+pub const STB_WEAK: u8 = 2;
+
+/// Errors returned when the ELF structure itself cannot be read.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum ElfError {
+    #[error("file is too short to hold an ELF header")]
+    TooShort,
+    #[error("missing ELF magic")]
+    BadMagic,
+    #[error("unsupported ELF class {0}, expected 64-bit")]
+    UnsupportedClass(u8),
+    #[error("unsupported ELF data encoding {0}, expected little-endian")]
+    UnsupportedEncoding(u8),
+    #[error("invalid {what} entry size {size}")]
+    InvalidEntrySize { what: &'static str, size: u16 },
+    #[error("{what} table is out of bounds")]
+    TableOutOfBounds { what: &'static str },
+}
+
+/// ELF64 file header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ElfHeader {
+    pub e_type: u16,
+    pub e_machine: u16,
+    pub e_version: u32,
+    pub e_entry: u64,
+    pub e_phoff: u64,
+    pub e_shoff: u64,
+    pub e_flags: u32,
+    pub e_ehsize: u16,
+    pub e_phentsize: u16,
+    pub e_phnum: u16,
+    pub e_shentsize: u16,
+    pub e_shnum: u16,
+    pub e_shstrndx: u16,
+}
+
+/// ELF64 section header, with its name resolved from the section name
+/// string table.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SectionHeader {
+    pub name: String,
+    pub sh_type: u32,
+    pub sh_flags: u64,
+    pub sh_addr: u64,
+    pub sh_offset: u64,
+    pub sh_size: u64,
+    pub sh_link: u32,
+    pub sh_info: u32,
+    pub sh_entsize: u64,
+}
+
+impl SectionHeader {
+    /// Whether the section contains executable instructions.
+    pub fn is_executable(&self) -> bool {
+        self.sh_flags & SHF_EXECINSTR != 0
+    }
+
+    /// Byte range of the section within the file, or `None` for sections
+    /// occupying no file space or whose range overflows.
+    pub fn file_range(&self) -> Option<std::ops::Range<usize>> {
+        if self.sh_type == SHT_NOBITS {
+            return None;
+        }
+        let start = usize::try_from(self.sh_offset).ok()?;
+        let end = start.checked_add(usize::try_from(self.sh_size).ok()?)?;
+        Some(start..end)
+    }
+}
+
+/// ELF64 program header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub p_flags: u32,
+    pub p_offset: u64,
+    pub p_vaddr: u64,
+    pub p_filesz: u64,
+    pub p_memsz: u64,
+}
+
+/// ELF64 symbol table entry, with its name resolved from the linked string
+/// table.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub st_info: u8,
+    pub st_shndx: u16,
+    pub st_value: u64,
+    pub st_size: u64,
+}
+
+impl Symbol {
+    pub fn binding(&self) -> u8 {
+        self.st_info >> 4
+    }
+
+    /// Whether the symbol is defined in this file and visible to others.
+    pub fn is_exported(&self) -> bool {
+        self.st_shndx != 0 && matches!(self.binding(), STB_GLOBAL | STB_WEAK)
+    }
+}
+
+/// ELF64 relocation entry without addend.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Relocation {
+    pub r_offset: u64,
+    pub r_info: u64,
+}
+
+impl Relocation {
+    pub fn symbol_index(&self) -> u32 {
+        (self.r_info >> 32) as u32
+    }
+
+    pub fn relocation_type(&self) -> u32 {
+        self.r_info as u32
+    }
+}
+
+/// A parsed ELF64 little-endian file borrowing its bytes.
+#[derive(Clone, Debug)]
+pub struct Elf<'a> {
+    bytes: &'a [u8],
+    header: ElfHeader,
+    sections: Vec<SectionHeader>,
+    program_headers: Vec<ProgramHeader>,
+}
+
+impl<'a> Elf<'a> {
+    /// Parses the file header, section headers and program headers.
+    ///
+    /// Only the table layout is checked here; the contents of individual
+    /// sections are not validated.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, ElfError> {
+        let ident = bytes.get(..16).ok_or(ElfError::TooShort)?;
+        if ident[..4] != ELF_MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        if ident[4] != ELFCLASS64 {
+            return Err(ElfError::UnsupportedClass(ident[4]));
+        }
+        if ident[5] != ELFDATA2LSB {
+            return Err(ElfError::UnsupportedEncoding(ident[5]));
+        }
+        if bytes.len() < ELF64_HEADER_SIZE {
+            return Err(ElfError::TooShort);
+        }
+        let header = ElfHeader {
+            e_type: read_u16(bytes, 16).ok_or(ElfError::TooShort)?,
+            e_machine: read_u16(bytes, 18).ok_or(ElfError::TooShort)?,
+            e_version: read_u32(bytes, 20).ok_or(ElfError::TooShort)?,
+            e_entry: read_u64(bytes, 24).ok_or(ElfError::TooShort)?,
+            e_phoff: read_u64(bytes, 32).ok_or(ElfError::TooShort)?,
+            e_shoff: read_u64(bytes, 40).ok_or(ElfError::TooShort)?,
+            e_flags: read_u32(bytes, 48).ok_or(ElfError::TooShort)?,
+            e_ehsize: read_u16(bytes, 52).ok_or(ElfError::TooShort)?,
+            e_phentsize: read_u16(bytes, 54).ok_or(ElfError::TooShort)?,
+            e_phnum: read_u16(bytes, 56).ok_or(ElfError::TooShort)?,
+            e_shentsize: read_u16(bytes, 58).ok_or(ElfError::TooShort)?,
+            e_shnum: read_u16(bytes, 60).ok_or(ElfError::TooShort)?,
+            e_shstrndx: read_u16(bytes, 62).ok_or(ElfError::TooShort)?,
+        };
+
+        let program_headers = table(
+            bytes,
+            "program header",
+            header.e_phoff,
+            header.e_phnum,
+            header.e_phentsize,
+            ELF64_PROGRAM_HEADER_SIZE,
+        )?
+        .map(|entry| ProgramHeader {
+            p_type: read_u32(entry, 0).unwrap_or_default(),
+            p_flags: read_u32(entry, 4).unwrap_or_default(),
+            p_offset: read_u64(entry, 8).unwrap_or_default(),
+            p_vaddr: read_u64(entry, 16).unwrap_or_default(),
+            p_filesz: read_u64(entry, 32).unwrap_or_default(),
+            p_memsz: read_u64(entry, 40).unwrap_or_default(),
+        })
+        .collect();
+
+        let raw_sections = table(
+            bytes,
+            "section header",
+            header.e_shoff,
+            header.e_shnum,
+            header.e_shentsize,
+            ELF64_SECTION_HEADER_SIZE,
+        )?
+        .map(|entry| {
+            (
+                read_u32(entry, 0).unwrap_or_default(),
+                SectionHeader {
+                    name: String::new(),
+                    sh_type: read_u32(entry, 4).unwrap_or_default(),
+                    sh_flags: read_u64(entry, 8).unwrap_or_default(),
+                    sh_addr: read_u64(entry, 16).unwrap_or_default(),
+                    sh_offset: read_u64(entry, 24).unwrap_or_default(),
+                    sh_size: read_u64(entry, 32).unwrap_or_default(),
+                    sh_link: read_u32(entry, 40).unwrap_or_default(),
+                    sh_info: read_u32(entry, 44).unwrap_or_default(),
+                    sh_entsize: read_u64(entry, 56).unwrap_or_default(),
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+        let names = raw_sections
+            .get(usize::from(header.e_shstrndx))
+            .and_then(|(_, section)| section.file_range())
+            .and_then(|range| bytes.get(range));
+        let sections = raw_sections
+            .into_iter()
+            .map(|(sh_name, mut section)| {
+                if let Some(name) = names.and_then(|names| read_str(names, sh_name as usize)) {
+                    section.name = name.to_string();
+                }
+                section
+            })
+            .collect();
+
+        Ok(Self {
+            bytes,
+            header,
+            sections,
+            program_headers,
+        })
+    }
+
+    /// The raw bytes the ELF was parsed from.
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn header(&self) -> &ElfHeader {
+        &self.header
+    }
+
+    pub fn sections(&self) -> &[SectionHeader] {
+        &self.sections
+    }
+
+    pub fn program_headers(&self) -> &[ProgramHeader] {
+        &self.program_headers
+    }
+
+    /// Returns the first section named `name`.
+    pub fn section(&self, name: &str) -> Option<&SectionHeader> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// Returns the file contents of `section`, or `None` if it occupies no
+    /// file space or lies outside the file.
+    pub fn section_data(&self, section: &SectionHeader) -> Option<&'a [u8]> {
+        self.bytes.get(section.file_range()?)
+    }
+
+    /// Entries of the symbol table held by `section`, with names resolved
+    /// from the string table it links to. Entries that do not fit in the file
+    /// are ignored.
+    pub fn symbols(&self, section: &SectionHeader) -> Vec<Symbol> {
+        let names = self
+            .sections
+            .get(section.sh_link as usize)
+            .and_then(|strtab| self.section_data(strtab));
+        self.section_data(section)
+            .unwrap_or_default()
+            .chunks_exact(ELF64_SYMBOL_SIZE)
+            .map(|entry| Symbol {
+                name: names
+                    .and_then(|names| {
+                        read_str(names, read_u32(entry, 0).unwrap_or_default() as usize)
+                    })
+                    .unwrap_or_default()
+                    .to_string(),
+                st_info: entry[4],
+                st_shndx: read_u16(entry, 6).unwrap_or_default(),
+                st_value: read_u64(entry, 8).unwrap_or_default(),
+                st_size: read_u64(entry, 16).unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Entries of the dynamic symbol table, if there is one.
+    pub fn dynamic_symbols(&self) -> Vec<Symbol> {
+        self.sections
+            .iter()
+            .find(|section| section.sh_type == SHT_DYNSYM)
+            .map(|section| self.symbols(section))
+            .unwrap_or_default()
+    }
+
+    /// Entries of all relocation sections without addends.
+    pub fn relocations(&self) -> Vec<Relocation> {
+        self.sections
+            .iter()
+            .filter(|section| section.sh_type == SHT_REL)
+            .filter_map(|section| self.section_data(section))
+            .flat_map(|data| data.chunks_exact(ELF64_REL_SIZE))
+            .map(|entry| Relocation {
+                r_offset: read_u64(entry, 0).unwrap_or_default(),
+                r_info: read_u64(entry, 8).unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Length of the file as described by its headers: the end of the
+    /// furthest of the file header, header tables, sections and segments.
+    ///
+    /// Bytes beyond this length (such as ProgramData padding) are not part
+    /// of the ELF.
+    pub fn extent(&self) -> usize {
+        let tables = [
+            table_end(
+                self.header.e_phoff,
+                self.header.e_phnum,
+                self.header.e_phentsize,
+            ),
+            table_end(
+                self.header.e_shoff,
+                self.header.e_shnum,
+                self.header.e_shentsize,
+            ),
+        ];
+        let sections = self
+            .sections
+            .iter()
+            .filter_map(|section| section.file_range().map(|range| range.end));
+        let segments = self.program_headers.iter().filter_map(|segment| {
+            let start = usize::try_from(segment.p_offset).ok()?;
+            start.checked_add(usize::try_from(segment.p_filesz).ok()?)
+        });
+        tables
+            .into_iter()
+            .flatten()
+            .chain(sections)
+            .chain(segments)
+            .fold(ELF64_HEADER_SIZE, usize::max)
+    }
+}
+
+/// Reads a NUL-terminated UTF-8 string starting at `offset`.
+pub(crate) fn read_str(bytes: &[u8], offset: usize) -> Option<&str> {
+    let tail = bytes.get(offset..)?;
+    let len = tail.iter().position(|byte| *byte == 0)?;
+    std::str::from_utf8(&tail[..len]).ok()
+}
+
+pub(crate) fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset.checked_add(2)?)?.try_into().ok()?,
+    ))
+}
+
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+pub(crate) fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset.checked_add(8)?)?.try_into().ok()?,
+    ))
+}
+
+fn table_end(offset: u64, count: u16, entry_size: u16) -> Option<usize> {
+    if count == 0 {
+        return None;
+    }
+    let len = usize::from(count).checked_mul(usize::from(entry_size))?;
+    usize::try_from(offset).ok()?.checked_add(len)
+}
+
+fn table<'a>(
+    bytes: &'a [u8],
+    what: &'static str,
+    offset: u64,
+    count: u16,
+    entry_size: u16,
+    expected_entry_size: usize,
+) -> Result<std::slice::ChunksExact<'a, u8>, ElfError> {
+    if count == 0 {
+        return Ok(bytes[..0].chunks_exact(expected_entry_size));
+    }
+    if usize::from(entry_size) != expected_entry_size {
+        return Err(ElfError::InvalidEntrySize {
+            what,
+            size: entry_size,
+        });
+    }
+    let start = usize::try_from(offset).map_err(|_| ElfError::TableOutOfBounds { what })?;
+    let end = table_end(offset, count, entry_size).ok_or(ElfError::TableOutOfBounds { what })?;
+    bytes
+        .get(start..end)
+        .map(|table| table.chunks_exact(expected_entry_size))
+        .ok_or(ElfError::TableOutOfBounds { what })
+}
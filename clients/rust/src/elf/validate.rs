@@ -0,0 +1,226 @@
+//! Pre-deploy validation of SBF program binaries.
+
+use {
+    super::parse::{Elf, ElfError, ElfHeader, SectionHeader, EM_BPF, EM_SBF, ET_DYN},
+    std::fmt,
+};
+
+/// SBPF version encoded in the `e_flags` of an SBF program.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SbpfVersion {
+    V0,
+    V1,
+    V2,
+    V3,
+    /// Flags not recognized by this client.
+    Unknown(u32),
+}
+
+impl SbpfVersion {
+    pub fn from_e_flags(e_flags: u32) -> Self {
+        match e_flags {
+            0 => Self::V0,
+            1 => Self::V1,
+            2 => Self::V2,
+            3 => Self::V3,
+            flags => Self::Unknown(flags),
+        }
+    }
+}
+
+impl fmt::Display for SbpfVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::V0 => f.write_str("v0"),
+            Self::V1 => f.write_str("v1"),
+            Self::V2 => f.write_str("v2"),
+            Self::V3 => f.write_str("v3"),
+            Self::Unknown(flags) => write!(f, "unknown (e_flags {flags:#x})"),
+        }
+    }
+}
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// The loader may accept the program, but something looks off.
+    Warning,
+    /// The loader will reject the program.
+    Error,
+}
+
+/// A single finding about a program binary.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Diagnostic {
+    /// The file is not a readable ELF64 little-endian file.
+    Malformed(ElfError),
+    /// `e_machine` is neither BPF nor SBF.
+    UnsupportedMachine(u16),
+    /// `e_flags` do not encode a known SBPF version.
+    UnknownSbpfVersion(u32),
+    /// `e_type` is not a shared object.
+    NotSharedObject(u16),
+    /// `e_entry` is not set.
+    MissingEntrypoint,
+    /// `e_entry` does not point into an executable section.
+    EntrypointOutsideText(u64),
+    /// There is no `.text` section.
+    MissingTextSection,
+    /// A section's contents lie outside the file.
+    SectionOutOfBounds(String),
+    /// An executable section other than `.text` exists.
+    UnexpectedExecutableSection(String),
+    /// The file does not fit into the ProgramData account.
+    TooLarge { len: usize, max_data_len: usize },
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::NotSharedObject(_) | Self::UnexpectedExecutableSection(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Malformed(err) => write!(f, "malformed ELF: {err}"),
+            Self::UnsupportedMachine(machine) => write!(
+                f,
+                "e_machine {machine} is not BPF ({EM_BPF}) or SBF ({EM_SBF})"
+            ),
+            Self::UnknownSbpfVersion(flags) => {
+                write!(f, "e_flags {flags:#x} do not encode a known SBPF version")
+            }
+            Self::NotSharedObject(e_type) => {
+                write!(f, "e_type {e_type} is not a shared object ({ET_DYN})")
+            }
+            Self::MissingEntrypoint => f.write_str("e_entry is not set"),
+            Self::EntrypointOutsideText(entry) => write!(
+                f,
+                "entrypoint {entry:#x} is not inside an executable section"
+            ),
+            Self::MissingTextSection => f.write_str("missing .text section"),
+            Self::SectionOutOfBounds(name) => {
+                write!(f, "section `{name}` extends past the end of the file")
+            }
+            Self::UnexpectedExecutableSection(name) => {
+                write!(f, "unexpected executable section `{name}`")
+            }
+            Self::TooLarge { len, max_data_len } => write!(
+                f,
+                "program is {len} bytes but at most {max_data_len} bytes can be deployed"
+            ),
+        }
+    }
+}
+
+/// Outcome of [`validate`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ElfReport {
+    /// The file header, if it could be parsed.
+    pub header: Option<ElfHeader>,
+    /// SBPF version declared by the file, if it could be parsed.
+    pub sbpf_version: Option<SbpfVersion>,
+    /// Findings, in the order they were detected.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ElfReport {
+    /// Whether no [`Severity::Error`] diagnostic was found.
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity() == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity() == Severity::Warning)
+    }
+}
+
+/// Validates a program binary before it is written into a buffer that will
+/// be deployed into a ProgramData account holding `max_data_len` bytes.
+///
+/// Use [`MAX_PROGRAM_DATA_LEN`](crate::state::MAX_PROGRAM_DATA_LEN) when the
+/// capacity has not been decided yet.
+pub fn validate(bytes: &[u8], max_data_len: usize) -> ElfReport {
+    let mut diagnostics = Vec::new();
+    if bytes.len() > max_data_len {
+        diagnostics.push(Diagnostic::TooLarge {
+            len: bytes.len(),
+            max_data_len,
+        });
+    }
+
+    let elf = match Elf::parse(bytes) {
+        Ok(elf) => elf,
+        Err(err) => {
+            diagnostics.push(Diagnostic::Malformed(err));
+            return ElfReport {
+                header: None,
+                sbpf_version: None,
+                diagnostics,
+            };
+        }
+    };
+    let header = *elf.header();
+
+    if header.e_machine != EM_BPF && header.e_machine != EM_SBF {
+        diagnostics.push(Diagnostic::UnsupportedMachine(header.e_machine));
+    }
+    let sbpf_version = SbpfVersion::from_e_flags(header.e_flags);
+    if let SbpfVersion::Unknown(flags) = sbpf_version {
+        diagnostics.push(Diagnostic::UnknownSbpfVersion(flags));
+    }
+    if sbpf_version == SbpfVersion::V0 && header.e_type != ET_DYN {
+        diagnostics.push(Diagnostic::NotSharedObject(header.e_type));
+    }
+
+    for section in elf.sections() {
+        if section
+            .file_range()
+            .is_some_and(|range| range.end > bytes.len())
+        {
+            diagnostics.push(Diagnostic::SectionOutOfBounds(section.name.clone()));
+        }
+        if section.is_executable() && section.name != ".text" {
+            diagnostics.push(Diagnostic::UnexpectedExecutableSection(
+                section.name.clone(),
+            ));
+        }
+    }
+    if elf.section(".text").is_none() {
+        diagnostics.push(Diagnostic::MissingTextSection);
+    }
+
+    if header.e_entry == 0 {
+        diagnostics.push(Diagnostic::MissingEntrypoint);
+    } else if !elf
+        .sections()
+        .iter()
+        .any(|section| section.is_executable() && contains_address(section, header.e_entry))
+    {
+        diagnostics.push(Diagnostic::EntrypointOutsideText(header.e_entry));
+    }
+
+    ElfReport {
+        header: Some(header),
+        sbpf_version: Some(sbpf_version),
+        diagnostics,
+    }
+}
+
+fn contains_address(section: &SectionHeader, address: u64) -> bool {
+    address
+        .checked_sub(section.sh_addr)
+        .is_some_and(|offset| offset < section.sh_size)
+}
@@ -0,0 +1,360 @@
+//! Parallel execution of deploy and upgrade plans.
+//!
+//! A [`PlanExecutor`] sends the steps of a [`Plan`] in order, except for the
+//! `Write` steps, which are sent concurrently up to
+//! [`ExecutorConfig::concurrency`] at a time. Each transaction is polled
+//! until it is confirmed; if its blockhash expires first, it is signed again
+//! with a fresh blockhash and resent after a backoff that doubles with each
+//! attempt.
+//!
+//! Before the final `Deploy` or `Upgrade` step, the buffer is fetched and
+//! compared with the ELF, so a program is never deployed from a buffer whose
+//! writes did not all land.
+//!
+//! A step using a durable nonce is checked against the nonce account before
+//! it is sent. Its transaction does not expire with a blockhash, so it is
+//! resent after each backoff for as long as the nonce account still holds
+//! its blockhash.
+//!
+//! [`PlanExecutor::execute_journaled`] also records each step in a
+//! [`JournalWriter`] as soon as it is confirmed, so a plan interrupted by a
+//! crash can be resumed with
+//! [`Journal::resume`](crate::journal::Journal::resume).
+
+use {
+    crate::{
+        cost::WRITE_CHUNK_SIZE,
+        journal::{JournalError, JournalWriter},
+        nonce::{DurableNonce, NonceError},
+        plan::{Plan, PlanStep, StepKind},
+        rpc::{AsyncLoaderRpc, RpcError},
+        state::{Buffer, StateError},
+    },
+    futures::{stream, StreamExt, TryFutureExt, TryStreamExt},
+    solana_address::Address,
+    solana_hash::Hash,
+    solana_message::Message,
+    solana_signature::Signature,
+    solana_signer::Signer,
+    solana_transaction::Transaction,
+    std::{cell::Cell, io::Write, time::Duration},
+    thiserror::Error,
+    tokio::time::{sleep, Instant},
+};
+
+/// Errors returned when executing a plan.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum ExecutorError {
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+    #[error(transparent)]
+    State(#[from] StateError),
+    #[error(transparent)]
+    Journal(#[from] JournalError),
+    #[error(transparent)]
+    Nonce(#[from] NonceError),
+    #[error("no signer for {0}")]
+    MissingSigner(Address),
+    #[error("failed to sign: {0}")]
+    Signer(String),
+    #[error("transaction {signature} failed: {error}")]
+    TransactionFailed { signature: Signature, error: String },
+    #[error("transaction not confirmed after {attempts} attempts")]
+    NotConfirmed { attempts: u32 },
+    #[error("buffer differs from the ELF at offsets {0:?}")]
+    BufferMismatch(Vec<u32>),
+}
+
+/// Tuning of a [`PlanExecutor`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ExecutorConfig {
+    /// Maximum number of `Write` transactions in flight.
+    pub concurrency: usize,
+    /// Maximum number of times a transaction is sent.
+    pub max_attempts: u32,
+    /// Delay before the first resend.
+    pub initial_backoff: Duration,
+    /// Delay between confirmation checks.
+    pub poll_interval: Duration,
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 16,
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            poll_interval: Duration::from_millis(400),
+        }
+    }
+}
+
+/// Signatures of an executed plan.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExecutionReport {
+    /// Signature of the confirmed transaction of each step, in plan order.
+    pub signatures: Vec<Signature>,
+    /// Number of times a transaction was resent.
+    pub retries: u32,
+}
+
+/// Sends the transactions of a [`Plan`] through an [`AsyncLoaderRpc`].
+pub struct PlanExecutor<'a, R> {
+    rpc: &'a R,
+    config: ExecutorConfig,
+    /// Blockhash shared by concurrent sends until it expires.
+    blockhash: Cell<Option<(Hash, u64)>>,
+    retries: Cell<u32>,
+}
+
+impl<'a, R: AsyncLoaderRpc> PlanExecutor<'a, R> {
+    pub fn new(rpc: &'a R, config: ExecutorConfig) -> Self {
+        Self {
+            rpc,
+            config,
+            blockhash: Cell::new(None),
+            retries: Cell::new(0),
+        }
+    }
+
+    /// Executes `plan`, which writes `elf`, with `payer` paying the fees.
+    ///
+    /// `signers` must hold every other account signing a step.
+    pub async fn execute(
+        &self,
+        plan: &Plan,
+        elf: &[u8],
+        payer: &dyn Signer,
+        signers: &[&dyn Signer],
+    ) -> Result<ExecutionReport, ExecutorError> {
+        self.run(plan, elf, payer, signers, |_, _| Ok(())).await
+    }
+
+    /// Executes `plan` like [`execute`](Self::execute), recording every
+    /// confirmed step and the final status in `journal`.
+    pub async fn execute_journaled<W: Write>(
+        &self,
+        plan: &Plan,
+        elf: &[u8],
+        payer: &dyn Signer,
+        signers: &[&dyn Signer],
+        journal: &mut JournalWriter<W>,
+    ) -> Result<ExecutionReport, ExecutorError> {
+        let result = self
+            .run(plan, elf, payer, signers, |step, signature| {
+                journal.confirmed(step, signature)
+            })
+            .await;
+        match &result {
+            Ok(_) => journal.succeeded()?,
+            // The journal itself cannot be written to.
+            Err(ExecutorError::Journal(_)) => {}
+            Err(error) => journal.failed(&error.to_string())?,
+        }
+        result
+    }
+
+    async fn run(
+        &self,
+        plan: &Plan,
+        elf: &[u8],
+        payer: &dyn Signer,
+        signers: &[&dyn Signer],
+        mut on_confirmed: impl FnMut(StepKind, Signature) -> Result<(), JournalError>,
+    ) -> Result<ExecutionReport, ExecutorError> {
+        self.retries.set(0);
+        let mut signatures = Vec::with_capacity(plan.steps.len());
+        let mut steps = plan.steps.iter().peekable();
+        while let Some(step) = steps.next() {
+            match step.kind {
+                StepKind::Write { .. } => {
+                    let mut writes = vec![step];
+                    while let Some(write) =
+                        steps.next_if(|step| matches!(step.kind, StepKind::Write { .. }))
+                    {
+                        writes.push(write);
+                    }
+                    // Writes are journaled as they land, then reported in
+                    // plan order.
+                    let mut confirmed = vec![Signature::default(); writes.len()];
+                    let mut landed = stream::iter(writes.iter().enumerate())
+                        .map(|(index, write)| {
+                            self.send(write, payer, signers)
+                                .map_ok(move |signature| (index, write.kind, signature))
+                        })
+                        .buffer_unordered(self.config.concurrency.max(1));
+                    while let Some((index, kind, signature)) = landed.try_next().await? {
+                        on_confirmed(kind, signature)?;
+                        confirmed[index] = signature;
+                    }
+                    signatures.extend(confirmed);
+                }
+                StepKind::Deploy | StepKind::Upgrade => {
+                    self.verify_buffer(plan, elf).await?;
+                    let signature = self.send(step, payer, signers).await?;
+                    on_confirmed(step.kind, signature)?;
+                    signatures.push(signature);
+                }
+                StepKind::CreateBuffer | StepKind::Extend => {
+                    let signature = self.send(step, payer, signers).await?;
+                    on_confirmed(step.kind, signature)?;
+                    signatures.push(signature);
+                }
+            }
+        }
+        Ok(ExecutionReport {
+            signatures,
+            retries: self.retries.get(),
+        })
+    }
+
+    /// Checks that the buffer of `plan` holds `elf`, reporting the offsets
+    /// of the [`WRITE_CHUNK_SIZE`] chunks that differ.
+    ///
+    /// The whole ELF is compared, so a plan resumed without the writes that
+    /// already landed is checked too.
+    pub async fn verify_buffer(&self, plan: &Plan, elf: &[u8]) -> Result<(), ExecutorError> {
+        let account = self
+            .rpc
+            .get_account(&plan.buffer)
+            .await?
+            .ok_or(RpcError::AccountNotFound(plan.buffer))?;
+        let buffer = Buffer::unpack(&account.data)?;
+        let mismatched: Vec<u32> = elf
+            .chunks(WRITE_CHUNK_SIZE)
+            .enumerate()
+            .filter_map(|(index, chunk)| {
+                let offset = index.saturating_mul(WRITE_CHUNK_SIZE);
+                let written = buffer.data.get(offset..offset.saturating_add(chunk.len()));
+                (written != Some(chunk)).then_some(offset as u32)
+            })
+            .collect();
+        if mismatched.is_empty() {
+            Ok(())
+        } else {
+            Err(ExecutorError::BufferMismatch(mismatched))
+        }
+    }
+
+    /// Checks that the nonce account of `nonce` still stores its blockhash,
+    /// so a transaction using it can land.
+    pub async fn verify_nonce(&self, nonce: &DurableNonce) -> Result<(), ExecutorError> {
+        let account = self
+            .rpc
+            .get_account(&nonce.account)
+            .await?
+            .ok_or(RpcError::AccountNotFound(nonce.account))?;
+        nonce.verify(&account.data)?;
+        Ok(())
+    }
+
+    /// Sends the transaction of `step` until it is confirmed.
+    async fn send(
+        &self,
+        step: &PlanStep,
+        payer: &dyn Signer,
+        signers: &[&dyn Signer],
+    ) -> Result<Signature, ExecutorError> {
+        let message = Message::new(&step.instructions, Some(&payer.pubkey()));
+        let keypairs = message
+            .signer_keys()
+            .into_iter()
+            .map(|key| {
+                std::iter::once(payer)
+                    .chain(signers.iter().copied())
+                    .find(|signer| signer.pubkey() == *key)
+                    .ok_or(ExecutorError::MissingSigner(*key))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(nonce) = &step.nonce {
+            self.verify_nonce(nonce).await?;
+        }
+
+        let mut backoff = self.config.initial_backoff;
+        let mut send_error = None;
+        for attempt in 0..self.config.max_attempts {
+            if attempt > 0 {
+                self.retries.set(self.retries.get().saturating_add(1));
+                sleep(backoff).await;
+                backoff = backoff.saturating_mul(2);
+            }
+            // A nonce step is signed with the nonce and never expires.
+            let (blockhash, last_valid_block_height) = match &step.nonce {
+                Some(nonce) => (nonce.blockhash, u64::MAX),
+                None => self.blockhash().await?,
+            };
+            let mut transaction = Transaction::new_unsigned(message.clone());
+            transaction
+                .try_sign(&keypairs, blockhash)
+                .map_err(|error| ExecutorError::Signer(error.to_string()))?;
+            let signature = match self.rpc.send_transaction(&transaction).await {
+                Ok(signature) => signature,
+                Err(error) => {
+                    send_error = Some(error);
+                    continue;
+                }
+            };
+            send_error = None;
+            let sent_at = Instant::now();
+
+            loop {
+                sleep(self.config.poll_interval).await;
+                // Read the height first: a transaction not confirmed once its
+                // blockhash expired can no longer land.
+                let expired = self.rpc.get_block_height().await? > last_valid_block_height;
+                match self.rpc.get_signature_status(&signature).await? {
+                    Some(Ok(())) => return Ok(signature),
+                    Some(Err(error)) => {
+                        return Err(ExecutorError::TransactionFailed { signature, error })
+                    }
+                    None if expired => {
+                        self.expire(&blockhash);
+                        break;
+                    }
+                    None => {}
+                }
+                if let Some(nonce) = &step.nonce {
+                    // Once the nonce is advanced, the transaction either
+                    // landed or never will.
+                    if let Err(error) = self.verify_nonce(nonce).await {
+                        return match self.rpc.get_signature_status(&signature).await? {
+                            Some(Ok(())) => Ok(signature),
+                            Some(Err(error)) => {
+                                Err(ExecutorError::TransactionFailed { signature, error })
+                            }
+                            None => Err(error),
+                        };
+                    }
+                    if sent_at.elapsed() >= backoff {
+                        break;
+                    }
+                }
+            }
+        }
+        Err(send_error.map_or(
+            ExecutorError::NotConfirmed {
+                attempts: self.config.max_attempts,
+            },
+            ExecutorError::from,
+        ))
+    }
+
+    async fn blockhash(&self) -> Result<(Hash, u64), RpcError> {
+        if let Some(blockhash) = self.blockhash.get() {
+            return Ok(blockhash);
+        }
+        let blockhash = self.rpc.get_latest_blockhash().await?;
+        self.blockhash.set(Some(blockhash));
+        Ok(blockhash)
+    }
+
+    fn expire(&self, blockhash: &Hash) {
+        if self
+            .blockhash
+            .get()
+            .is_some_and(|(cached, _)| cached == *blockhash)
+        {
+            self.blockhash.set(None);
+        }
+    }
+}
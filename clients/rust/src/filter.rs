@@ -0,0 +1,146 @@
+//! `getProgramAccounts` filters for loader-v3 accounts.
+//!
+//! The loader owns Buffer, Program and ProgramData accounts alike, so finding
+//! e.g. the buffers of one authority takes a `memcmp` filter on the account
+//! header; see [`state`](crate::state) for the layouts. Filters serialize to
+//! the JSON RPC format with [`AccountFilter::to_json`], using base64 for
+//! `memcmp` bytes.
+
+use {
+    crate::state::{LoaderState, BUFFER_HEADER_SIZE, PROGRAM_DATA_HEADER_SIZE},
+    base64::{prelude::BASE64_STANDARD, Engine},
+    serde_json::{json, Value},
+    solana_address::Address,
+};
+
+/// Offset of the optional authority in a ProgramData account header, after
+/// the enum tag and deploy slot.
+const PROGRAM_DATA_AUTHORITY_OFFSET: usize = 12;
+
+/// A `getProgramAccounts` filter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AccountFilter {
+    /// Matches accounts whose data is exactly this many bytes long.
+    DataSize(u64),
+    /// Matches accounts whose data contains `bytes` at `offset`.
+    Memcmp { offset: usize, bytes: Vec<u8> },
+}
+
+impl AccountFilter {
+    /// Whether account `data` passes the filter, as the RPC node decides it.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        match self {
+            Self::DataSize(size) => data.len() as u64 == *size,
+            Self::Memcmp { offset, bytes } => offset
+                .checked_add(bytes.len())
+                .and_then(|end| data.get(*offset..end))
+                .is_some_and(|slice| slice == bytes.as_slice()),
+        }
+    }
+
+    /// The filter in the JSON RPC format.
+    pub fn to_json(&self) -> Value {
+        match self {
+            Self::DataSize(size) => json!({ "dataSize": size }),
+            Self::Memcmp { offset, bytes } => json!({
+                "memcmp": {
+                    "offset": offset,
+                    "bytes": BASE64_STANDARD.encode(bytes),
+                    "encoding": "base64",
+                }
+            }),
+        }
+    }
+}
+
+/// Builder for the filters selecting Buffer accounts.
+#[derive(Clone, Debug, Default)]
+pub struct BufferFiltersBuilder {
+    authority: Option<Address>,
+    data_len: Option<usize>,
+}
+
+impl BufferFiltersBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Only buffers with this authority.
+    pub fn authority(&mut self, authority: Address) -> &mut Self {
+        self.authority = Some(authority);
+        self
+    }
+    /// Only buffers holding exactly `data_len` program bytes.
+    pub fn data_len(&mut self, data_len: usize) -> &mut Self {
+        self.data_len = Some(data_len);
+        self
+    }
+    pub fn build(&self) -> Vec<AccountFilter> {
+        // The header encodes the tag and the authority together.
+        let header = LoaderState::Buffer {
+            authority: self.authority,
+        }
+        .to_bytes();
+        let prefix_len = if self.authority.is_some() {
+            BUFFER_HEADER_SIZE
+        } else {
+            4
+        };
+        let mut filters = vec![AccountFilter::Memcmp {
+            offset: 0,
+            bytes: header[..prefix_len].to_vec(),
+        }];
+        if let Some(data_len) = self.data_len {
+            filters.push(AccountFilter::DataSize(
+                BUFFER_HEADER_SIZE.saturating_add(data_len) as u64,
+            ));
+        }
+        filters
+    }
+}
+
+/// Builder for the filters selecting ProgramData accounts.
+#[derive(Clone, Debug, Default)]
+pub struct ProgramDataFiltersBuilder {
+    upgrade_authority: Option<Address>,
+    max_data_len: Option<usize>,
+}
+
+impl ProgramDataFiltersBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Only programs upgradeable by this authority.
+    pub fn upgrade_authority(&mut self, upgrade_authority: Address) -> &mut Self {
+        self.upgrade_authority = Some(upgrade_authority);
+        self
+    }
+    /// Only ProgramData accounts able to hold exactly `max_data_len` bytes.
+    pub fn max_data_len(&mut self, max_data_len: usize) -> &mut Self {
+        self.max_data_len = Some(max_data_len);
+        self
+    }
+    pub fn build(&self) -> Vec<AccountFilter> {
+        let header = LoaderState::ProgramData {
+            slot: 0,
+            upgrade_authority: self.upgrade_authority,
+        }
+        .to_bytes();
+        let mut filters = vec![AccountFilter::Memcmp {
+            offset: 0,
+            bytes: header[..4].to_vec(),
+        }];
+        if self.upgrade_authority.is_some() {
+            // The deploy slot in between varies.
+            filters.push(AccountFilter::Memcmp {
+                offset: PROGRAM_DATA_AUTHORITY_OFFSET,
+                bytes: header[PROGRAM_DATA_AUTHORITY_OFFSET..].to_vec(),
+            });
+        }
+        if let Some(max_data_len) = self.max_data_len {
+            filters.push(AccountFilter::DataSize(
+                PROGRAM_DATA_HEADER_SIZE.saturating_add(max_data_len) as u64,
+            ));
+        }
+        filters
+    }
+}
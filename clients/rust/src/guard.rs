@@ -0,0 +1,99 @@
+//! Guards for irreversible loader operations.
+//!
+//! Removing a program's upgrade authority makes it immutable forever, and
+//! closing its ProgramData account permanently retires the program address.
+//! The functions here build those instructions only when handed an
+//! [`Irreversible`] token, so each call site states that it means it.
+//!
+//! [`classify`] flags instructions, however they were built, that perform
+//! one of these operations, for review tools that lint transactions before
+//! they are signed.
+
+use {
+    crate::{
+        instruction,
+        instructions::{CLOSE_DISCRIMINATOR, SET_AUTHORITY_DISCRIMINATOR},
+        state::find_program_data_address,
+    },
+    solana_address::Address,
+    solana_instruction::Instruction,
+};
+
+/// Confirmation that the caller knowingly performs an irreversible
+/// operation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Irreversible(());
+
+impl Irreversible {
+    /// Acknowledges that the operation cannot be undone.
+    pub fn acknowledge() -> Self {
+        Self(())
+    }
+}
+
+/// `SetAuthority` instruction removing the upgrade authority of `program`,
+/// making it immutable.
+pub fn make_immutable(
+    program: &Address,
+    current_authority: &Address,
+    _acknowledged: Irreversible,
+) -> Instruction {
+    instruction::set_authority(&find_program_data_address(program), current_authority, None)
+}
+
+/// `Close` instruction for the ProgramData account of `program`; the
+/// program can never be deployed at that address again.
+pub fn close_program(
+    program: &Address,
+    recipient: &Address,
+    authority: &Address,
+    _acknowledged: Irreversible,
+) -> Instruction {
+    instruction::close_program(program, recipient, authority)
+}
+
+/// An irreversible operation performed by an instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IrreversibleAction {
+    /// `SetAuthority` leaving `account` without a usable authority.
+    ///
+    /// This includes an instruction from the generated builder without a
+    /// new authority, which passes the loader's program ID in its place.
+    RemoveAuthority { account: Address },
+    /// `Close` of a ProgramData account, retiring `program`.
+    CloseProgram { program: Address },
+}
+
+/// Returns the irreversible operation performed by `instruction`, if any.
+pub fn classify(instruction: &Instruction) -> Option<IrreversibleAction> {
+    if instruction.program_id != crate::LOADER_V3_ID {
+        return None;
+    }
+    let discriminator = u32::from_le_bytes(*instruction.data.first_chunk()?);
+    let account = |index: usize| instruction.accounts.get(index).map(|meta| meta.pubkey);
+    match discriminator {
+        SET_AUTHORITY_DISCRIMINATOR => match account(2) {
+            Some(new_authority) if new_authority != crate::LOADER_V3_ID => None,
+            _ => Some(IrreversibleAction::RemoveAuthority {
+                account: account(0)?,
+            }),
+        },
+        CLOSE_DISCRIMINATOR => match account(3) {
+            Some(program) if program != crate::LOADER_V3_ID => {
+                Some(IrreversibleAction::CloseProgram { program })
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Flags every instruction performing an irreversible operation, with its
+/// index in `instructions`.
+pub fn lint(instructions: &[Instruction]) -> Vec<(usize, IrreversibleAction)> {
+    instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, instruction)| Some((index, classify(instruction)?)))
+        .collect()
+}
@@ -0,0 +1,537 @@
+//! Upgrade history of programs, indexed from archived transactions.
+//!
+//! A [`HistoryIndexer`] reads confirmed transactions in the order of the
+//! chain, decodes their top-level loader instructions by discriminator and
+//! appends each deploy, upgrade, authority change and close to the timeline
+//! of its program. It replays the `Write` instructions of each buffer it saw
+//! initialized, so deploys and upgrades record the [`ProgramHash`] of the
+//! ELF they installed.
+//!
+//! Transactions are read from files of `getTransaction` responses in the
+//! `base64` encoding, legacy or v0, one response or an array of them per
+//! file, with [`ConfirmedTransaction::read_file`], or from any iterator of
+//! [`ConfirmedTransaction`]s. The accounts a v0 transaction loaded from
+//! lookup tables are taken from the `loadedAddresses` of its metadata. The
+//! resulting [`History`] is exported with [`History::to_json`] or
+//! [`History::to_csv`]:
+//!
+//! ```text
+//! program,slot,signature,event,signer,old_authority,new_authority,buffer,elf_hash
+//! <base58>,1042,<base58>,deploy,<base58>,,<base58>,<base58>,<hex>
+//! <base58>,2077,<base58>,set-authority,<base58>,<base58>,,,
+//! ```
+//!
+//! A `SetAuthority` only names the ProgramData account, so authority changes
+//! are attributed to programs whose deploy, upgrade or extension was indexed
+//! before, or that were registered with [`HistoryIndexer::track`]. Loader
+//! instructions invoked by other programs, e.g. upgrades executed by a
+//! multisig, are not indexed.
+
+use {
+    crate::{
+        instruction::{decompile, LoaderInstruction},
+        state::find_program_data_address,
+        verify::ProgramHash,
+    },
+    base64::{prelude::BASE64_STANDARD, Engine},
+    serde::{Deserialize, Serialize},
+    solana_address::Address,
+    solana_instruction::{AccountMeta, Instruction},
+    solana_message::{
+        v0::{LoadedAddresses, LoadedMessage},
+        VersionedMessage,
+    },
+    solana_signature::Signature,
+    solana_transaction::versioned::VersionedTransaction,
+    std::{
+        borrow::Borrow,
+        collections::{BTreeMap, HashMap, HashSet},
+        fmt::Write,
+        path::{Path, PathBuf},
+        str::FromStr,
+    },
+    thiserror::Error,
+};
+
+/// Errors returned when reading transaction files.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum HistoryError {
+    #[error("failed to read {path}: {error}")]
+    Io { path: PathBuf, error: String },
+    #[error("invalid transaction file: {0}")]
+    InvalidFile(String),
+    #[error("unsupported transaction encoding {0}, expected base64")]
+    UnsupportedEncoding(String),
+    #[error("unsupported transaction version {0}, expected legacy or 0")]
+    UnsupportedVersion(String),
+}
+
+/// A transaction confirmed in `slot`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConfirmedTransaction {
+    pub slot: u64,
+    pub transaction: VersionedTransaction,
+    /// Addresses a v0 transaction loaded from address lookup tables, from
+    /// the `loadedAddresses` of its metadata. Empty for legacy
+    /// transactions.
+    pub loaded_addresses: LoadedAddresses,
+    /// Whether the transaction failed, leaving every account unchanged.
+    pub failed: bool,
+}
+
+impl ConfirmedTransaction {
+    /// Reads a file holding a `getTransaction` response, or an array of
+    /// them. See [`from_json`](Self::from_json).
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Vec<Self>, HistoryError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|error| HistoryError::Io {
+            path: path.to_path_buf(),
+            error: error.to_string(),
+        })?;
+        Self::from_json(&json)
+    }
+
+    /// Parses a `getTransaction` response, or an array of them.
+    pub fn from_json(json: &str) -> Result<Vec<Self>, HistoryError> {
+        let invalid = |error: &dyn std::fmt::Display| HistoryError::InvalidFile(error.to_string());
+        let file: TransactionFile = serde_json::from_str(json).map_err(|error| invalid(&error))?;
+        let responses = match file {
+            TransactionFile::One(response) => vec![response],
+            TransactionFile::Many(responses) => responses,
+        };
+        responses
+            .into_iter()
+            .map(|response| {
+                if let Some(version) = response.version.filter(|version| {
+                    version.as_str() != Some("legacy") && version.as_u64() != Some(0)
+                }) {
+                    return Err(HistoryError::UnsupportedVersion(version.to_string()));
+                }
+                let (data, encoding) = response.transaction;
+                if encoding != "base64" {
+                    return Err(HistoryError::UnsupportedEncoding(encoding));
+                }
+                let bytes = BASE64_STANDARD
+                    .decode(data)
+                    .map_err(|error| invalid(&error))?;
+                let transaction: VersionedTransaction =
+                    bincode::deserialize(&bytes).map_err(|error| invalid(&error))?;
+                let meta = response.meta.unwrap_or_default();
+                let parse = |addresses: Vec<String>| {
+                    addresses
+                        .iter()
+                        .map(|address| Address::from_str(address).map_err(|error| invalid(&error)))
+                        .collect::<Result<Vec<_>, _>>()
+                };
+                let loaded_addresses = LoadedAddresses {
+                    writable: parse(meta.loaded_addresses.writable)?,
+                    readonly: parse(meta.loaded_addresses.readonly)?,
+                };
+                let looked_up: usize = transaction
+                    .message
+                    .address_table_lookups()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|lookup| {
+                        lookup
+                            .writable_indexes
+                            .len()
+                            .saturating_add(lookup.readonly_indexes.len())
+                    })
+                    .sum();
+                if looked_up != loaded_addresses.len() {
+                    return Err(HistoryError::InvalidFile(format!(
+                        "transaction looks up {looked_up} addresses but loadedAddresses lists {}",
+                        loaded_addresses.len()
+                    )));
+                }
+                Ok(Self {
+                    slot: response.slot,
+                    transaction,
+                    loaded_addresses,
+                    failed: !meta.err.is_null(),
+                })
+            })
+            .collect()
+    }
+
+    /// Rebuilds the top-level instructions of the transaction, resolving
+    /// the accounts a v0 message loaded from lookup tables.
+    fn instructions(&self) -> Vec<Instruction> {
+        match &self.transaction.message {
+            VersionedMessage::Legacy(message) => (0..message.instructions.len())
+                .filter_map(|index| decompile(message, index))
+                .collect(),
+            VersionedMessage::V0(message) => {
+                let loaded =
+                    LoadedMessage::new_borrowed(message, &self.loaded_addresses, &HashSet::new());
+                let keys = loaded.account_keys();
+                let key = |index: u8| keys.get(usize::from(index)).copied();
+                message
+                    .instructions
+                    .iter()
+                    .filter_map(|compiled| {
+                        let accounts = compiled
+                            .accounts
+                            .iter()
+                            .map(|&index| {
+                                Some(AccountMeta {
+                                    pubkey: key(index)?,
+                                    is_signer: loaded.is_signer(usize::from(index)),
+                                    is_writable: loaded.is_writable(usize::from(index)),
+                                })
+                            })
+                            .collect::<Option<_>>()?;
+                        Some(Instruction {
+                            program_id: key(compiled.program_id_index)?,
+                            accounts,
+                            data: compiled.data.clone(),
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Kind of a [`HistoryEntry`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HistoryEvent {
+    Deploy,
+    Upgrade,
+    SetAuthority,
+    Close,
+}
+
+impl HistoryEvent {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Deploy => "deploy",
+            Self::Upgrade => "upgrade",
+            Self::SetAuthority => "set-authority",
+            Self::Close => "close",
+        }
+    }
+}
+
+/// A change to a program, in the instruction that made it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HistoryEntry {
+    pub slot: u64,
+    /// First signature of the transaction.
+    pub signature: Signature,
+    pub event: HistoryEvent,
+    /// The authority that signed the instruction.
+    pub signer: Address,
+    /// Upgrade authority before an authority change.
+    pub old_authority: Option<Address>,
+    /// Upgrade authority set by a deploy or an authority change; `None`
+    /// made the program immutable.
+    pub new_authority: Option<Address>,
+    /// Buffer a deploy or upgrade installed the program from.
+    pub buffer: Option<Address>,
+    /// Hash of the ELF installed by a deploy or upgrade, if the writes to
+    /// its buffer were indexed.
+    pub elf_hash: Option<ProgramHash>,
+}
+
+/// Timelines of the indexed programs.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct History {
+    pub programs: BTreeMap<Address, Vec<HistoryEntry>>,
+}
+
+impl History {
+    /// Entries of `program`, oldest first.
+    pub fn timeline(&self, program: &Address) -> &[HistoryEntry] {
+        self.programs.get(program).map_or(&[], Vec::as_slice)
+    }
+
+    /// Serializes every timeline to JSON, programs in address order.
+    pub fn to_json(&self) -> String {
+        let programs = self
+            .programs
+            .iter()
+            .map(|(program, entries)| TimelineJson {
+                program: program.to_string(),
+                timeline: entries
+                    .iter()
+                    .map(|entry| EntryJson {
+                        slot: entry.slot,
+                        signature: entry.signature.to_string(),
+                        event: entry.event.as_str(),
+                        signer: entry.signer.to_string(),
+                        old_authority: entry.old_authority.map(|address| address.to_string()),
+                        new_authority: entry.new_authority.map(|address| address.to_string()),
+                        buffer: entry.buffer.map(|address| address.to_string()),
+                        elf_hash: entry.elf_hash.map(|hash| hash.to_string()),
+                    })
+                    .collect(),
+            })
+            .collect();
+        serde_json::to_string_pretty(&HistoryJson { programs }).expect("history is serializable")
+    }
+
+    /// Serializes every timeline to CSV with a header row, one entry per
+    /// row and empty fields for missing values.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "program,slot,signature,event,signer,old_authority,new_authority,buffer,elf_hash\n",
+        );
+        let field = |value: Option<String>| value.unwrap_or_default();
+        for (program, entries) in &self.programs {
+            for entry in entries {
+                writeln!(
+                    csv,
+                    "{program},{},{},{},{},{},{},{},{}",
+                    entry.slot,
+                    entry.signature,
+                    entry.event.as_str(),
+                    entry.signer,
+                    field(entry.old_authority.map(|address| address.to_string())),
+                    field(entry.new_authority.map(|address| address.to_string())),
+                    field(entry.buffer.map(|address| address.to_string())),
+                    field(entry.elf_hash.map(|hash| hash.to_string())),
+                )
+                .expect("writing to a string succeeds");
+            }
+        }
+        csv
+    }
+}
+
+/// Builds a [`History`] from confirmed transactions.
+#[derive(Clone, Debug, Default)]
+pub struct HistoryIndexer {
+    history: History,
+    /// Program of each known ProgramData account.
+    programs: HashMap<Address, Address>,
+    /// Contents of the buffers initialized in the indexed transactions.
+    buffers: HashMap<Address, Vec<u8>>,
+}
+
+impl HistoryIndexer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `program`, so authority changes are attributed to it even
+    /// before one of its deploys or upgrades is indexed.
+    pub fn track(&mut self, program: Address) -> &mut Self {
+        self.programs
+            .insert(find_program_data_address(&program), program);
+        self
+    }
+
+    /// Indexes the loader instructions of a transaction. Failed
+    /// transactions are skipped.
+    pub fn index(&mut self, confirmed: &ConfirmedTransaction) {
+        if confirmed.failed {
+            return;
+        }
+        let signature = confirmed
+            .transaction
+            .signatures
+            .first()
+            .copied()
+            .unwrap_or_default();
+        for instruction in confirmed.instructions() {
+            if let Some(decoded) = LoaderInstruction::of(&instruction) {
+                self.apply(confirmed.slot, signature, &instruction, &decoded);
+            }
+        }
+    }
+
+    /// Indexes every transaction of `transactions`, in order.
+    pub fn index_all(
+        &mut self,
+        transactions: impl IntoIterator<Item = impl Borrow<ConfirmedTransaction>>,
+    ) -> &mut Self {
+        transactions
+            .into_iter()
+            .for_each(|confirmed| self.index(confirmed.borrow()));
+        self
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn into_history(self) -> History {
+        self.history
+    }
+
+    fn apply(
+        &mut self,
+        slot: u64,
+        signature: Signature,
+        instruction: &Instruction,
+        decoded: &LoaderInstruction,
+    ) {
+        let account = |index: usize| instruction.accounts.get(index).map(|meta| meta.pubkey);
+        let entry = |event, signer| HistoryEntry {
+            slot,
+            signature,
+            event,
+            signer,
+            old_authority: None,
+            new_authority: None,
+            buffer: None,
+            elf_hash: None,
+        };
+        match decoded {
+            LoaderInstruction::InitializeBuffer => {
+                if let Some(buffer) = account(0) {
+                    self.buffers.insert(buffer, Vec::new());
+                }
+            }
+            LoaderInstruction::Write(args) => {
+                let contents = account(0).and_then(|buffer| self.buffers.get_mut(&buffer));
+                let start = usize::try_from(args.offset).ok();
+                let end = start.and_then(|start| start.checked_add(args.bytes.len()));
+                if let (Some(contents), Some(start), Some(end)) = (contents, start, end) {
+                    if contents.len() < end {
+                        contents.resize(end, 0);
+                    }
+                    contents[start..end].copy_from_slice(&args.bytes);
+                }
+            }
+            LoaderInstruction::DeployWithMaxDataLen(_) => {
+                let (Some(program_data), Some(program), Some(buffer), Some(authority)) =
+                    (account(1), account(2), account(3), account(7))
+                else {
+                    return;
+                };
+                self.programs.insert(program_data, program);
+                let entry = HistoryEntry {
+                    new_authority: Some(authority),
+                    buffer: Some(buffer),
+                    elf_hash: self.take_buffer_hash(&buffer),
+                    ..entry(HistoryEvent::Deploy, authority)
+                };
+                self.push(program, entry);
+            }
+            LoaderInstruction::Upgrade => {
+                let (Some(program_data), Some(program), Some(buffer), Some(authority)) =
+                    (account(0), account(1), account(2), account(6))
+                else {
+                    return;
+                };
+                self.programs.insert(program_data, program);
+                let entry = HistoryEntry {
+                    buffer: Some(buffer),
+                    elf_hash: self.take_buffer_hash(&buffer),
+                    ..entry(HistoryEvent::Upgrade, authority)
+                };
+                self.push(program, entry);
+            }
+            LoaderInstruction::SetAuthority | LoaderInstruction::SetAuthorityChecked => {
+                let (Some(account_address), Some(current_authority)) = (account(0), account(1))
+                else {
+                    return;
+                };
+                let Some(program) = self.programs.get(&account_address).copied() else {
+                    return;
+                };
+                let entry = HistoryEntry {
+                    old_authority: Some(current_authority),
+                    new_authority: account(2),
+                    ..entry(HistoryEvent::SetAuthority, current_authority)
+                };
+                self.push(program, entry);
+            }
+            LoaderInstruction::Close => {
+                let Some(closed) = account(0) else {
+                    return;
+                };
+                if self.buffers.remove(&closed).is_some() {
+                    return;
+                }
+                let program = self.programs.get(&closed).copied().or_else(|| {
+                    account(3).filter(|program| find_program_data_address(program) == closed)
+                });
+                if let (Some(program), Some(authority)) = (program, account(2)) {
+                    self.push(program, entry(HistoryEvent::Close, authority));
+                }
+            }
+            LoaderInstruction::ExtendProgram(_) => {
+                if let (Some(program_data), Some(program)) = (account(0), account(1)) {
+                    self.programs.insert(program_data, program);
+                }
+            }
+        }
+    }
+
+    /// Hashes a buffer consumed by a deploy or upgrade.
+    fn take_buffer_hash(&mut self, buffer: &Address) -> Option<ProgramHash> {
+        self.buffers
+            .remove(buffer)
+            .map(|contents| ProgramHash::of_elf(&contents))
+    }
+
+    fn push(&mut self, program: Address, entry: HistoryEntry) {
+        self.history
+            .programs
+            .entry(program)
+            .or_default()
+            .push(entry);
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TransactionFile {
+    One(TransactionResponse),
+    Many(Vec<TransactionResponse>),
+}
+
+#[derive(Deserialize)]
+struct TransactionResponse {
+    slot: u64,
+    /// The encoded transaction and its encoding.
+    transaction: (String, String),
+    meta: Option<TransactionMeta>,
+    #[serde(default)]
+    version: Option<serde_json::Value>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransactionMeta {
+    #[serde(default)]
+    err: serde_json::Value,
+    #[serde(default)]
+    loaded_addresses: LoadedAddressesJson,
+}
+
+#[derive(Default, Deserialize)]
+struct LoadedAddressesJson {
+    #[serde(default)]
+    writable: Vec<String>,
+    #[serde(default)]
+    readonly: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct HistoryJson {
+    programs: Vec<TimelineJson>,
+}
+
+#[derive(Serialize)]
+struct TimelineJson {
+    program: String,
+    timeline: Vec<EntryJson>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EntryJson {
+    slot: u64,
+    signature: String,
+    event: &'static str,
+    signer: String,
+    old_authority: Option<String>,
+    new_authority: Option<String>,
+    buffer: Option<String>,
+    elf_hash: Option<String>,
+}
@@ -0,0 +1,128 @@
+//! Instructions the generated builders cannot express as-is, and decoding
+//! of loader instruction data.
+//!
+//! The generated builders pass the loader's program ID in place of an
+//! omitted optional account. The loader reads a third `SetAuthority` account
+//! as the new authority, so removing an authority needs that placeholder
+//! dropped, and it requires the Program account to be writable when closing
+//! a ProgramData account, which the builders mark read-only.
+
+use {
+    crate::{
+        instructions::{
+            CloseBuilder, DeployWithMaxDataLenInstructionArgs, ExtendProgramInstructionArgs,
+            SetAuthorityBuilder, WriteInstructionArgs, CLOSE_DISCRIMINATOR,
+            DEPLOY_WITH_MAX_DATA_LEN_DISCRIMINATOR, EXTEND_PROGRAM_DISCRIMINATOR,
+            INITIALIZE_BUFFER_DISCRIMINATOR, SET_AUTHORITY_CHECKED_DISCRIMINATOR,
+            SET_AUTHORITY_DISCRIMINATOR, UPGRADE_DISCRIMINATOR, WRITE_DISCRIMINATOR,
+        },
+        state::find_program_data_address,
+    },
+    borsh::BorshDeserialize,
+    solana_address::Address,
+    solana_instruction::{AccountMeta, Instruction},
+    solana_message::Message,
+};
+
+/// A loader instruction decoded from its data, with the arguments of the
+/// generated instruction structs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LoaderInstruction {
+    InitializeBuffer,
+    Write(WriteInstructionArgs),
+    DeployWithMaxDataLen(DeployWithMaxDataLenInstructionArgs),
+    Upgrade,
+    SetAuthority,
+    Close,
+    ExtendProgram(ExtendProgramInstructionArgs),
+    SetAuthorityChecked,
+}
+
+impl LoaderInstruction {
+    /// Decodes instruction data by its discriminator.
+    ///
+    /// Like the loader, this ignores bytes following the arguments. Returns
+    /// `None` for unknown discriminators and truncated arguments.
+    pub fn unpack(data: &[u8]) -> Option<Self> {
+        let (discriminator, mut args) = data.split_first_chunk()?;
+        let instruction = match u32::from_le_bytes(*discriminator) {
+            INITIALIZE_BUFFER_DISCRIMINATOR => Self::InitializeBuffer,
+            WRITE_DISCRIMINATOR => Self::Write(BorshDeserialize::deserialize(&mut args).ok()?),
+            DEPLOY_WITH_MAX_DATA_LEN_DISCRIMINATOR => {
+                Self::DeployWithMaxDataLen(BorshDeserialize::deserialize(&mut args).ok()?)
+            }
+            UPGRADE_DISCRIMINATOR => Self::Upgrade,
+            SET_AUTHORITY_DISCRIMINATOR => Self::SetAuthority,
+            CLOSE_DISCRIMINATOR => Self::Close,
+            EXTEND_PROGRAM_DISCRIMINATOR => {
+                Self::ExtendProgram(BorshDeserialize::deserialize(&mut args).ok()?)
+            }
+            SET_AUTHORITY_CHECKED_DISCRIMINATOR => Self::SetAuthorityChecked,
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    /// Decodes the data of `instruction` if it targets the loader.
+    pub fn of(instruction: &Instruction) -> Option<Self> {
+        if instruction.program_id != crate::LOADER_V3_ID {
+            return None;
+        }
+        Self::unpack(&instruction.data)
+    }
+}
+
+/// `SetAuthority` instruction for a Buffer or ProgramData account.
+///
+/// A `new_authority` of `None` makes a program immutable; the loader rejects
+/// it for buffers.
+pub fn set_authority(
+    account: &Address,
+    current_authority: &Address,
+    new_authority: Option<&Address>,
+) -> Instruction {
+    let mut instruction = SetAuthorityBuilder::new()
+        .buffer_or_program_data_account(*account)
+        .current_authority(*current_authority)
+        .new_authority(new_authority.copied())
+        .instruction();
+    if new_authority.is_none() {
+        instruction.accounts.truncate(2);
+    }
+    instruction
+}
+
+/// `Close` instruction for the ProgramData account of `program`, sending
+/// its lamports to `recipient`.
+pub fn close_program(program: &Address, recipient: &Address, authority: &Address) -> Instruction {
+    let mut instruction = CloseBuilder::new()
+        .buffer_or_program_data_account(find_program_data_address(program))
+        .destination_account(*recipient)
+        .authority(Some(*authority))
+        .program_account(Some(*program))
+        .instruction();
+    instruction.accounts[3].is_writable = true;
+    instruction
+}
+
+/// Rebuilds the `index`th instruction of a message.
+pub(crate) fn decompile(message: &Message, index: usize) -> Option<Instruction> {
+    let compiled = message.instructions.get(index)?;
+    let key = |index: u8| message.account_keys.get(usize::from(index)).copied();
+    let accounts = compiled
+        .accounts
+        .iter()
+        .map(|index| {
+            Some(AccountMeta {
+                pubkey: key(*index)?,
+                is_signer: message.is_signer(usize::from(*index)),
+                is_writable: message.is_maybe_writable(usize::from(*index), None),
+            })
+        })
+        .collect::<Option<_>>()?;
+    Some(Instruction {
+        program_id: key(compiled.program_id_index)?,
+        accounts,
+        data: compiled.data.clone(),
+    })
+}
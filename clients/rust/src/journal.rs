@@ -0,0 +1,431 @@
+//! Crash-safe journal of deploys and upgrades.
+//!
+//! A [`JournalWriter`] appends the progress of a [`Plan`] to a JSON-lines
+//! file: a header with the buffer address, the hash of the ELF and the
+//! chunk map of its writes, one line per confirmed step with its signature,
+//! and a final status line. Each line is flushed as soon as it is written,
+//! so a journal read back with [`Journal::read`] after a crash tells which
+//! buffer was used and which transactions were known to have landed.
+//!
+//! ```json
+//! {"event":"started","operation":"deploy","buffer":"<base58>","elfHash":"<hex>","chunks":[{"offset":0,"len":1012}]}
+//! {"event":"confirmed","step":"write","offset":0,"len":1012,"signature":"<base58>"}
+//! {"event":"finished","status":"deployed"}
+//! ```
+//!
+//! A write may land without being journaled, or be journaled by a process
+//! that died before the buffer was fetched again, so [`Journal::resume`]
+//! trusts the fetched buffer contents over the journal for `Write` steps.
+
+use {
+    crate::{
+        plan::{Plan, StepKind},
+        state::{Buffer, StateError},
+        verify::ProgramHash,
+    },
+    serde::{Deserialize, Serialize},
+    solana_address::Address,
+    solana_signature::Signature,
+    std::{
+        io::{BufRead, Write},
+        str::FromStr,
+    },
+    thiserror::Error,
+};
+
+/// Errors returned when writing, reading or resuming a journal.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum JournalError {
+    #[error("failed to access the journal: {0}")]
+    Io(String),
+    #[error("invalid journal line {line}: {error}")]
+    InvalidLine { line: usize, error: String },
+    #[error("journal has no header")]
+    MissingHeader,
+    #[error("journal is for ELF {expected} but the ELF hashes to {actual}")]
+    ElfMismatch {
+        expected: ProgramHash,
+        actual: ProgramHash,
+    },
+    #[error("journal is for buffer {expected} but the plan writes to {actual}")]
+    BufferMismatch { expected: Address, actual: Address },
+    #[error("journal does not match the plan's writes")]
+    ChunkMismatch,
+    #[error("buffer {0} was created but no longer exists")]
+    BufferMissing(Address),
+    #[error(transparent)]
+    State(#[from] StateError),
+}
+
+/// What a journaled plan does once the buffer is written.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Operation {
+    /// Only writes the buffer.
+    WriteBuffer,
+    Deploy,
+    Upgrade,
+}
+
+impl Operation {
+    /// The operation of `plan`, given by its final step.
+    pub fn of(plan: &Plan) -> Self {
+        match plan.steps.last().map(|step| step.kind) {
+            Some(StepKind::Deploy) => Self::Deploy,
+            Some(StepKind::Upgrade) => Self::Upgrade,
+            _ => Self::WriteBuffer,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::WriteBuffer => "writeBuffer",
+            Self::Deploy => "deploy",
+            Self::Upgrade => "upgrade",
+        }
+    }
+
+    /// Status line written once the operation succeeded.
+    fn done(&self) -> &'static str {
+        match self {
+            Self::WriteBuffer => "written",
+            Self::Deploy => "deployed",
+            Self::Upgrade => "upgraded",
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Self::WriteBuffer, Self::Deploy, Self::Upgrade]
+            .into_iter()
+            .find(|operation| operation.as_str() == s)
+            .ok_or_else(|| format!("unknown operation {s:?}"))
+    }
+}
+
+/// Range of the ELF written by one `Write` step.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Chunk {
+    pub offset: u32,
+    pub len: usize,
+}
+
+/// Final status of a journaled plan.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Status {
+    InProgress,
+    Succeeded,
+    /// The plan stopped with an error; it may be resumed.
+    Failed(String),
+}
+
+/// Progress of a plan, as recorded in a journal.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Journal {
+    pub operation: Operation,
+    pub buffer: Address,
+    pub elf_hash: ProgramHash,
+    pub chunks: Vec<Chunk>,
+    /// Confirmed steps and their signatures, in journal order.
+    pub confirmed: Vec<(StepKind, Signature)>,
+    pub status: Status,
+}
+
+impl Journal {
+    /// A journal for `plan`, which writes `elf`, with nothing confirmed.
+    pub fn new(plan: &Plan, elf: &[u8]) -> Self {
+        Self {
+            operation: Operation::of(plan),
+            buffer: plan.buffer,
+            elf_hash: ProgramHash::of_elf(elf),
+            chunks: chunks(plan),
+            confirmed: Vec::new(),
+            status: Status::InProgress,
+        }
+    }
+
+    /// Reads a journal written by a [`JournalWriter`].
+    ///
+    /// A last line without a trailing newline is a write torn by a crash
+    /// and is ignored if it does not parse.
+    pub fn read(mut reader: impl BufRead) -> Result<Self, JournalError> {
+        let mut journal: Option<Self> = None;
+        let mut line = String::new();
+        for number in 1.. {
+            line.clear();
+            if reader.read_line(&mut line).map_err(io)? == 0 {
+                break;
+            }
+            let torn = !line.ends_with('\n');
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = |error: &dyn std::fmt::Display| JournalError::InvalidLine {
+                line: number,
+                error: error.to_string(),
+            };
+            let entry = match serde_json::from_str::<Line>(&line) {
+                Ok(entry) => entry,
+                Err(_) if torn => break,
+                Err(error) => return Err(invalid(&error)),
+            };
+            match (entry, journal.as_mut()) {
+                (
+                    Line::Started {
+                        operation,
+                        buffer,
+                        elf_hash,
+                        chunks,
+                    },
+                    None,
+                ) => {
+                    journal = Some(Self {
+                        operation: operation.parse().map_err(|error| invalid(&error))?,
+                        buffer: buffer.parse().map_err(|error| invalid(&error))?,
+                        elf_hash: elf_hash.parse().map_err(|error| invalid(&error))?,
+                        chunks,
+                        confirmed: Vec::new(),
+                        status: Status::InProgress,
+                    })
+                }
+                (Line::Started { .. }, Some(_)) => return Err(invalid(&"duplicate header")),
+                (
+                    Line::Confirmed {
+                        step,
+                        offset,
+                        len,
+                        signature,
+                    },
+                    Some(journal),
+                ) => {
+                    let kind = step_kind(&step, offset, len).map_err(|error| invalid(&error))?;
+                    let signature = signature.parse().map_err(|error| invalid(&error))?;
+                    journal.confirmed.push((kind, signature));
+                    journal.status = Status::InProgress;
+                }
+                (Line::Finished { status, error }, Some(journal)) => {
+                    journal.status = match (status.as_str(), error) {
+                        ("failed", Some(error)) => Status::Failed(error),
+                        (status, None) if status == journal.operation.done() => Status::Succeeded,
+                        _ => return Err(invalid(&format!("unexpected status {status:?}"))),
+                    };
+                }
+                (_, None) => return Err(JournalError::MissingHeader),
+            }
+        }
+        journal.ok_or(JournalError::MissingHeader)
+    }
+
+    /// Whether `step` was journaled as confirmed.
+    pub fn is_confirmed(&self, step: &StepKind) -> bool {
+        self.confirmed.iter().any(|(kind, _)| kind == step)
+    }
+
+    /// The steps of `plan` still to be sent, given the data of the buffer
+    /// account as fetched from the cluster, or `None` if it does not exist.
+    ///
+    /// `plan` must be the journaled plan, rebuilt from the same buffer and
+    /// `elf`. The create-buffer step is dropped once the buffer exists, and
+    /// a `Write` step once the buffer holds its chunk, whether or not it was
+    /// journaled. An `Extend` step is dropped only if it was journaled.
+    pub fn resume(
+        &self,
+        plan: &Plan,
+        elf: &[u8],
+        buffer_data: Option<&[u8]>,
+    ) -> Result<Plan, JournalError> {
+        let elf_hash = ProgramHash::of_elf(elf);
+        if elf_hash != self.elf_hash {
+            return Err(JournalError::ElfMismatch {
+                expected: self.elf_hash,
+                actual: elf_hash,
+            });
+        }
+        if plan.buffer != self.buffer {
+            return Err(JournalError::BufferMismatch {
+                expected: self.buffer,
+                actual: plan.buffer,
+            });
+        }
+        if chunks(plan) != self.chunks {
+            return Err(JournalError::ChunkMismatch);
+        }
+
+        let final_confirmed =
+            self.is_confirmed(&StepKind::Deploy) || self.is_confirmed(&StepKind::Upgrade);
+        let Some(buffer_data) = buffer_data else {
+            // Deploying or upgrading consumes the buffer.
+            if self.status == Status::Succeeded || final_confirmed {
+                return Ok(Plan {
+                    buffer: plan.buffer,
+                    steps: Vec::new(),
+                });
+            }
+            if self.is_confirmed(&StepKind::CreateBuffer) {
+                return Err(JournalError::BufferMissing(self.buffer));
+            }
+            return Ok(plan.clone());
+        };
+        let buffer = Buffer::unpack(buffer_data)?;
+        let steps = plan
+            .steps
+            .iter()
+            .filter(|step| match step.kind {
+                StepKind::CreateBuffer => false,
+                StepKind::Write { offset, len } => {
+                    let range = offset as usize..(offset as usize).saturating_add(len);
+                    buffer.data.get(range.clone()) != elf.get(range)
+                }
+                StepKind::Extend => !self.is_confirmed(&StepKind::Extend),
+                StepKind::Deploy | StepKind::Upgrade => true,
+            })
+            .cloned()
+            .collect();
+        Ok(Plan {
+            buffer: plan.buffer,
+            steps,
+        })
+    }
+}
+
+/// Appends the progress of a plan to a journal, flushing every line.
+pub struct JournalWriter<W> {
+    writer: W,
+    journal: Journal,
+}
+
+impl<W: Write> JournalWriter<W> {
+    /// Starts a journal for `plan`, which writes `elf`, by writing its
+    /// header.
+    pub fn create(writer: W, plan: &Plan, elf: &[u8]) -> Result<Self, JournalError> {
+        let journal = Journal::new(plan, elf);
+        let mut journal_writer = Self { writer, journal };
+        journal_writer.write(&Line::Started {
+            operation: journal_writer.journal.operation.as_str().to_string(),
+            buffer: journal_writer.journal.buffer.to_string(),
+            elf_hash: journal_writer.journal.elf_hash.to_string(),
+            chunks: journal_writer.journal.chunks.clone(),
+        })?;
+        Ok(journal_writer)
+    }
+
+    /// Continues `journal`, read back with [`Journal::read`], by appending
+    /// to `writer`.
+    pub fn append(writer: W, journal: Journal) -> Self {
+        Self { writer, journal }
+    }
+
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// Records that the transaction of a step landed.
+    pub fn confirmed(&mut self, step: StepKind, signature: Signature) -> Result<(), JournalError> {
+        let (offset, len) = match step {
+            StepKind::Write { offset, len } => (Some(offset), Some(len)),
+            _ => (None, None),
+        };
+        self.write(&Line::Confirmed {
+            step: step_name(&step).to_string(),
+            offset,
+            len,
+            signature: signature.to_string(),
+        })?;
+        self.journal.confirmed.push((step, signature));
+        self.journal.status = Status::InProgress;
+        Ok(())
+    }
+
+    /// Records that every step landed.
+    pub fn succeeded(&mut self) -> Result<(), JournalError> {
+        self.write(&Line::Finished {
+            status: self.journal.operation.done().to_string(),
+            error: None,
+        })?;
+        self.journal.status = Status::Succeeded;
+        Ok(())
+    }
+
+    /// Records that the plan stopped with `error`.
+    pub fn failed(&mut self, error: &str) -> Result<(), JournalError> {
+        self.write(&Line::Finished {
+            status: "failed".to_string(),
+            error: Some(error.to_string()),
+        })?;
+        self.journal.status = Status::Failed(error.to_string());
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write(&mut self, line: &Line) -> Result<(), JournalError> {
+        let mut bytes = serde_json::to_vec(line).expect("journal line is serializable");
+        bytes.push(b'\n');
+        self.writer.write_all(&bytes).map_err(io)?;
+        self.writer.flush().map_err(io)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+enum Line {
+    #[serde(rename_all = "camelCase")]
+    Started {
+        operation: String,
+        buffer: String,
+        elf_hash: String,
+        chunks: Vec<Chunk>,
+    },
+    Confirmed {
+        step: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        offset: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        len: Option<usize>,
+        signature: String,
+    },
+    Finished {
+        status: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+/// The chunk map of `plan`'s writes.
+fn chunks(plan: &Plan) -> Vec<Chunk> {
+    plan.writes()
+        .filter_map(|step| match step.kind {
+            StepKind::Write { offset, len } => Some(Chunk { offset, len }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn step_name(step: &StepKind) -> &'static str {
+    match step {
+        StepKind::CreateBuffer => "createBuffer",
+        StepKind::Write { .. } => "write",
+        StepKind::Deploy => "deploy",
+        StepKind::Extend => "extend",
+        StepKind::Upgrade => "upgrade",
+    }
+}
+
+fn step_kind(name: &str, offset: Option<u32>, len: Option<usize>) -> Result<StepKind, String> {
+    match (name, offset, len) {
+        ("createBuffer", None, None) => Ok(StepKind::CreateBuffer),
+        ("write", Some(offset), Some(len)) => Ok(StepKind::Write { offset, len }),
+        ("deploy", None, None) => Ok(StepKind::Deploy),
+        ("extend", None, None) => Ok(StepKind::Extend),
+        ("upgrade", None, None) => Ok(StepKind::Upgrade),
+        _ => Err(format!("invalid step {name:?}")),
+    }
+}
+
+fn io(error: std::io::Error) -> JournalError {
+    JournalError::Io(error.to_string())
+}
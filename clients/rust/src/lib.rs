@@ -1,3 +1,29 @@
+pub mod buffer;
+pub mod capacity;
+pub mod cost;
+pub mod elf;
+pub mod executor;
+pub mod filter;
 mod generated;
+pub mod guard;
+pub mod history;
+pub mod instruction;
+pub mod journal;
+pub mod logs;
+pub mod manifest;
+pub mod nonce;
+pub mod offline;
+pub mod plan;
+pub mod policy;
+pub mod preflight;
+pub mod proposal;
+pub mod reclaim;
+pub mod rotation;
+pub mod rpc;
+pub mod simulate;
+pub mod state;
+#[cfg(feature = "litesvm")]
+pub mod svm;
+pub mod verify;
 
 pub use generated::{programs::LOADER_V3_ID as ID, *};
@@ -0,0 +1,320 @@
+//! Typed events from the logs of loader transactions.
+//!
+//! The runtime frames each instruction in the logs, and the loader logs
+//! what it did in between:
+//!
+//! ```text
+//! Program BPFLoaderUpgradeab1e11111111111111111111111 invoke [1]
+//! Program 11111111111111111111111111111111 invoke [2]
+//! Program 11111111111111111111111111111111 success
+//! Deployed program <base58>
+//! Program BPFLoaderUpgradeab1e11111111111111111111111 success
+//! ```
+//!
+//! [`parse_invocations`] splits the logs into the top-level instructions of
+//! the transaction. [`events`] pairs each loader invocation with the
+//! instruction at the same index of the message and yields a
+//! [`LoaderEvent`], checking that the logged addresses match the accounts
+//! of the instruction. `InitializeBuffer` and `Write` log nothing, so their
+//! events come from the instruction alone. Loader instructions invoked by
+//! other programs are not reported.
+
+use {
+    crate::instruction::{decompile, LoaderInstruction},
+    solana_address::Address,
+    solana_instruction::Instruction,
+    solana_message::Message,
+    std::str::FromStr,
+    thiserror::Error,
+};
+
+/// Errors returned when logs do not fit the transaction.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum LogError {
+    #[error("unexpected log line outside of an instruction: {0}")]
+    UnexpectedLine(String),
+    #[error("instruction {index} of the message does not invoke {program}")]
+    ProgramMismatch { index: usize, program: Address },
+    #[error("logs of instruction {0} do not match its accounts")]
+    AccountMismatch(usize),
+}
+
+/// A top-level instruction as recorded in the logs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Invocation {
+    pub program: Address,
+    /// Lines logged by the program itself, without those of the programs it
+    /// invoked.
+    pub messages: Vec<String>,
+    /// The logged result, with the error message of a failure. `None` when
+    /// the logs end first, e.g. because they were truncated.
+    pub result: Option<Result<(), String>>,
+}
+
+/// Kind of account closed by `Close`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClosedAccount {
+    Uninitialized,
+    Buffer,
+    /// The ProgramData account of `program`, retiring it.
+    ProgramData {
+        program: Address,
+    },
+}
+
+/// What a loader instruction did, according to the logs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LoaderEvent {
+    BufferInitialized {
+        buffer: Address,
+        authority: Option<Address>,
+    },
+    BufferWritten {
+        buffer: Address,
+        offset: u32,
+        len: usize,
+    },
+    ProgramDeployed {
+        program: Address,
+        buffer: Address,
+        authority: Address,
+        max_data_len: u64,
+    },
+    ProgramUpgraded {
+        program: Address,
+        buffer: Address,
+        authority: Address,
+    },
+    /// The authority of a Buffer or ProgramData account changed; `None`
+    /// made it immutable.
+    AuthorityChanged {
+        account: Address,
+        new_authority: Option<Address>,
+    },
+    AccountClosed {
+        account: Address,
+        kind: ClosedAccount,
+        recipient: Address,
+    },
+    ProgramExtended {
+        program: Address,
+        additional_bytes: u32,
+    },
+    /// The instruction failed with `error`. `reason` is the last line the
+    /// loader logged before, which explains most failures.
+    Failed {
+        error: String,
+        reason: Option<String>,
+    },
+}
+
+/// A [`LoaderEvent`] with the instruction that produced it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InstructionEvent {
+    /// Index of the instruction in the message.
+    pub index: usize,
+    /// The decoded instruction, `None` if its data is not a loader
+    /// instruction.
+    pub instruction: Option<LoaderInstruction>,
+    pub event: LoaderEvent,
+}
+
+/// A line framing an instruction.
+enum Frame {
+    Invoke(usize),
+    Success,
+    Failed(String),
+}
+
+/// Splits transaction logs into the top-level instructions they record, in
+/// order. Logs ending with "Log truncated" end with an invocation without
+/// result.
+pub fn parse_invocations(logs: &[String]) -> Result<Vec<Invocation>, LogError> {
+    let mut invocations: Vec<Invocation> = Vec::new();
+    let mut depth: usize = 0;
+    for line in logs {
+        let current = invocations.last_mut().filter(|_| depth > 0);
+        match (parse_frame(line), current) {
+            (Some((program, Frame::Invoke(1))), None) => {
+                invocations.push(Invocation {
+                    program,
+                    messages: Vec::new(),
+                    result: None,
+                });
+                depth = 1;
+            }
+            (Some((_, Frame::Invoke(next))), Some(_)) if Some(next) == depth.checked_add(1) => {
+                depth = next;
+            }
+            (Some((program, frame @ (Frame::Success | Frame::Failed(_)))), Some(current)) => {
+                if depth == 1 {
+                    if program != current.program {
+                        return Err(LogError::UnexpectedLine(line.clone()));
+                    }
+                    current.result = Some(match frame {
+                        Frame::Failed(error) => Err(error),
+                        _ => Ok(()),
+                    });
+                }
+                depth = depth.saturating_sub(1);
+            }
+            (Some(_), _) => return Err(LogError::UnexpectedLine(line.clone())),
+            (None, Some(current)) => {
+                if depth == 1 {
+                    current.messages.push(line.clone());
+                }
+            }
+            (None, None) if line == "Log truncated" => {}
+            (None, None) => return Err(LogError::UnexpectedLine(line.clone())),
+        }
+    }
+    Ok(invocations)
+}
+
+/// Returns the events of the loader instructions of `message`, given the
+/// logs of the transaction.
+///
+/// Instructions after a failed one did not run and have no events, nor does
+/// an instruction whose logs were truncated.
+pub fn events(message: &Message, logs: &[String]) -> Result<Vec<InstructionEvent>, LogError> {
+    let mut events = Vec::new();
+    for (index, invocation) in parse_invocations(logs)?.into_iter().enumerate() {
+        let instruction = decompile(message, index)
+            .filter(|instruction| instruction.program_id == invocation.program)
+            .ok_or(LogError::ProgramMismatch {
+                index,
+                program: invocation.program,
+            })?;
+        if invocation.program != crate::LOADER_V3_ID {
+            continue;
+        }
+        let Some(result) = invocation.result else {
+            break;
+        };
+        let decoded = LoaderInstruction::of(&instruction);
+        let event = match result {
+            Ok(()) => decoded
+                .as_ref()
+                .and_then(|decoded| event(&instruction, decoded, &invocation.messages))
+                .ok_or(LogError::AccountMismatch(index))?,
+            Err(error) => LoaderEvent::Failed {
+                error,
+                reason: invocation.messages.last().cloned(),
+            },
+        };
+        events.push(InstructionEvent {
+            index,
+            instruction: decoded,
+            event,
+        });
+    }
+    Ok(events)
+}
+
+fn parse_frame(line: &str) -> Option<(Address, Frame)> {
+    let (program, rest) = line.strip_prefix("Program ")?.split_once(' ')?;
+    let program = Address::from_str(program).ok()?;
+    let frame = if let Some(depth) = rest
+        .strip_prefix("invoke [")
+        .and_then(|rest| rest.strip_suffix(']'))
+    {
+        Frame::Invoke(depth.parse().ok()?)
+    } else if rest == "success" {
+        Frame::Success
+    } else {
+        Frame::Failed(rest.strip_prefix("failed: ")?.to_string())
+    };
+    Some((program, frame))
+}
+
+/// Builds the event of a successful instruction, or `None` if the logs do
+/// not match it.
+fn event(
+    instruction: &Instruction,
+    decoded: &LoaderInstruction,
+    messages: &[String],
+) -> Option<LoaderEvent> {
+    let account = |index: usize| instruction.accounts.get(index).map(|meta| meta.pubkey);
+    let logged = |prefix: &str| {
+        messages
+            .iter()
+            .find_map(|message| message.strip_prefix(prefix))
+    };
+    let logged_address = |prefix: &str| Address::from_str(logged(prefix)?).ok();
+
+    let event = match decoded {
+        LoaderInstruction::InitializeBuffer => LoaderEvent::BufferInitialized {
+            buffer: account(0)?,
+            authority: account(1),
+        },
+        LoaderInstruction::Write(args) => LoaderEvent::BufferWritten {
+            buffer: account(0)?,
+            offset: args.offset,
+            len: args.bytes.len(),
+        },
+        LoaderInstruction::DeployWithMaxDataLen(args) => LoaderEvent::ProgramDeployed {
+            program: logged_address("Deployed program ")
+                .filter(|program| Some(*program) == account(2))?,
+            buffer: account(3)?,
+            authority: account(7)?,
+            max_data_len: args.max_data_len,
+        },
+        LoaderInstruction::Upgrade => LoaderEvent::ProgramUpgraded {
+            program: logged_address("Upgraded program ")
+                .filter(|program| Some(*program) == account(1))?,
+            buffer: account(2)?,
+            authority: account(6)?,
+        },
+        // The loader logs the optional new authority with its `Debug`
+        // format.
+        LoaderInstruction::SetAuthority => {
+            let new_authority = match logged("New authority ")? {
+                "None" => None,
+                some => {
+                    Some(Address::from_str(some.strip_prefix("Some(")?.strip_suffix(')')?).ok()?)
+                }
+            };
+            if new_authority != account(2) {
+                return None;
+            }
+            LoaderEvent::AuthorityChanged {
+                account: account(0)?,
+                new_authority,
+            }
+        }
+        LoaderInstruction::SetAuthorityChecked => LoaderEvent::AuthorityChanged {
+            account: account(0)?,
+            new_authority: Some(
+                logged_address("New authority ").filter(|new| Some(*new) == account(2))?,
+            ),
+        },
+        LoaderInstruction::Close => {
+            let kind = if let Some(program) = logged_address("Closed Program ") {
+                (Some(program) == account(3)).then_some(ClosedAccount::ProgramData { program })?
+            } else if logged_address("Closed Buffer ") == account(0) {
+                ClosedAccount::Buffer
+            } else if logged_address("Closed Uninitialized ") == account(0) {
+                ClosedAccount::Uninitialized
+            } else {
+                return None;
+            };
+            LoaderEvent::AccountClosed {
+                account: account(0)?,
+                kind,
+                recipient: account(1)?,
+            }
+        }
+        LoaderInstruction::ExtendProgram(args) => {
+            let additional_bytes = logged("Extended ProgramData account by ")?
+                .strip_suffix(" bytes")?
+                .parse()
+                .ok()
+                .filter(|logged| logged == &args.additional_bytes)?;
+            LoaderEvent::ProgramExtended {
+                program: account(1)?,
+                additional_bytes,
+            }
+        }
+    };
+    Some(event)
+}
@@ -0,0 +1,503 @@
+//! Declarative program manifests and their reconciliation.
+//!
+//! A [`Manifest`] lists the programs a team manages and the state each
+//! should be in on chain: its ELF, its upgrade authority (or whether it is
+//! immutable or closed) and how much room its ProgramData account keeps for
+//! growth.
+//!
+//! ```toml
+//! [[program]]
+//! name = "memo"
+//! program_id = "<base58>"
+//! elf = "target/deploy/memo.so"  # or elf_hash = "<hex>", or both
+//! upgrade_authority = "<base58>" # or "none" for an immutable program
+//! max_data_len = { headroom_percent = 20 } # or "exact", { headroom_bytes = 4096 }
+//!
+//! [[program]]
+//! program_id = "<base58>"
+//! closed = true
+//! ```
+//!
+//! [`reconcile`] compares an entry with the decoded ProgramData account and
+//! returns a [`Reconciliation`]: the `ExtendProgram`, `Upgrade`,
+//! `SetAuthorityChecked`, `SetAuthority` and `Close` actions converging the
+//! program, to be reviewed before their instructions are built. Upgrades
+//! read the ELF from the buffer at
+//! [`find_buffer_address`](crate::buffer::find_buffer_address), which must
+//! be written beforehand with the program's current authority as buffer
+//! authority, e.g. with
+//! [`WriteBufferPlanBuilder::seed_buffer`](crate::plan::WriteBufferPlanBuilder::seed_buffer).
+//! Deploying programs that do not exist yet is out of scope.
+
+use {
+    crate::{
+        buffer::find_buffer_address,
+        capacity::{
+            plan_capacity, CapacityError, CapacityPolicy, Exact, FixedHeadroom, PercentageGrowth,
+        },
+        guard::{close_program, make_immutable, Irreversible},
+        instructions::{ExtendProgramBuilder, SetAuthorityCheckedBuilder, UpgradeBuilder},
+        rpc::{LoaderRpc, RpcError},
+        state::{find_program_data_address, ProgramData, StateError},
+        verify::ProgramHash,
+    },
+    serde::Deserialize,
+    solana_address::Address,
+    solana_instruction::Instruction,
+    std::{
+        fmt,
+        path::{Path, PathBuf},
+    },
+    thiserror::Error,
+};
+
+/// Errors returned when parsing or reconciling a manifest.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum ManifestError {
+    #[error("invalid manifest: {0}")]
+    InvalidToml(String),
+    #[error("invalid manifest entry {index}: {error}")]
+    InvalidEntry { index: usize, error: String },
+    #[error("failed to read {path}: {error}")]
+    Io { path: PathBuf, error: String },
+    #[error("program {0} is not deployed")]
+    NotDeployed(Address),
+    #[error("program {0} is immutable and differs from the manifest")]
+    Immutable(Address),
+    #[error("no ELF available to upgrade program {0}")]
+    ElfUnavailable(Address),
+    #[error("ELF of program {program} hashes to {actual}, not {expected}")]
+    ElfHashMismatch {
+        program: Address,
+        expected: ProgramHash,
+        actual: ProgramHash,
+    },
+    #[error("reconciling program {0} requires acknowledging an irreversible action")]
+    Unacknowledged(Address),
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+    #[error(transparent)]
+    State(#[from] StateError),
+    #[error(transparent)]
+    Capacity(#[from] CapacityError),
+}
+
+/// Programs and their desired on-chain state.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Manifest {
+    pub programs: Vec<ProgramManifest>,
+}
+
+/// Desired state of one program.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProgramManifest {
+    pub name: Option<String>,
+    pub program_id: Address,
+    /// Path of the ELF, relative to the manifest.
+    pub elf: Option<PathBuf>,
+    /// Expected hash of the ELF, checked against the file when both are set.
+    pub elf_hash: Option<ProgramHash>,
+    pub state: DesiredState,
+    pub max_data_len: MaxDataLenPolicy,
+}
+
+/// Whether a program should remain upgradeable.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DesiredState {
+    Upgradeable { authority: Address },
+    Immutable,
+    Closed,
+}
+
+/// Capacity of the ProgramData account relative to the ELF length.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum MaxDataLenPolicy {
+    #[default]
+    Exact,
+    HeadroomBytes(usize),
+    HeadroomPercent(u16),
+}
+
+impl CapacityPolicy for MaxDataLenPolicy {
+    fn capacity(&self, elf_len: usize) -> usize {
+        match *self {
+            Self::Exact => Exact.capacity(elf_len),
+            Self::HeadroomBytes(bytes) => FixedHeadroom { bytes }.capacity(elf_len),
+            Self::HeadroomPercent(percent) => PercentageGrowth { percent }.capacity(elf_len),
+        }
+    }
+}
+
+impl Manifest {
+    pub fn from_toml(toml: &str) -> Result<Self, ManifestError> {
+        let raw: RawManifest =
+            toml::from_str(toml).map_err(|error| ManifestError::InvalidToml(error.to_string()))?;
+        let programs = raw
+            .program
+            .into_iter()
+            .enumerate()
+            .map(|(index, program)| {
+                program
+                    .parse()
+                    .map_err(|error| ManifestError::InvalidEntry { index, error })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { programs })
+    }
+
+    /// Reconciles every program with its accounts fetched from `rpc`,
+    /// reading ELF paths relative to `base_dir`.
+    ///
+    /// `payer` funds extensions, receives the lamports of upgrade buffers
+    /// and closed programs, and is the base of the buffer addresses.
+    pub fn reconcile(
+        &self,
+        rpc: &impl LoaderRpc,
+        payer: &Address,
+        base_dir: &Path,
+    ) -> Result<Vec<Reconciliation>, ManifestError> {
+        self.programs
+            .iter()
+            .map(|program| {
+                let program_data =
+                    rpc.get_account(&find_program_data_address(&program.program_id))?;
+                let elf = program
+                    .elf
+                    .as_ref()
+                    .map(|path| {
+                        let path = base_dir.join(path);
+                        std::fs::read(&path).map_err(|error| ManifestError::Io {
+                            path,
+                            error: error.to_string(),
+                        })
+                    })
+                    .transpose()?;
+                reconcile(
+                    program,
+                    program_data.as_ref().map(|account| account.data.as_slice()),
+                    elf.as_deref(),
+                    payer,
+                )
+            })
+            .collect()
+    }
+}
+
+/// A step converging a program to its manifest entry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// Extends the ProgramData account, paid by `payer`.
+    Extend {
+        additional_bytes: u32,
+        payer: Address,
+    },
+    /// Upgrades the program from `buffer`, which must hold the ELF hashing
+    /// to `elf_hash`.
+    Upgrade {
+        buffer: Address,
+        elf_hash: ProgramHash,
+        authority: Address,
+        spill: Address,
+    },
+    /// Hands the upgrade authority over with `SetAuthorityChecked`, signed
+    /// by both authorities.
+    SetAuthority { current: Address, new: Address },
+    /// Removes the upgrade authority; irreversible.
+    MakeImmutable { current: Address },
+    /// Closes the ProgramData account into `recipient`; irreversible.
+    Close {
+        authority: Address,
+        recipient: Address,
+    },
+}
+
+impl Action {
+    pub fn is_irreversible(&self) -> bool {
+        matches!(self, Self::MakeImmutable { .. } | Self::Close { .. })
+    }
+
+    /// The instruction performing the action on `program`.
+    ///
+    /// Irreversible actions are only built with an `acknowledged` token.
+    pub fn instruction(
+        &self,
+        program: &Address,
+        acknowledged: Option<Irreversible>,
+    ) -> Result<Instruction, ManifestError> {
+        let program_data = find_program_data_address(program);
+        Ok(match *self {
+            Self::Extend {
+                additional_bytes,
+                payer,
+            } => ExtendProgramBuilder::new()
+                .program_data_account(program_data)
+                .program_account(*program)
+                .system_program(Some(solana_system_interface::program::ID))
+                .payer(Some(payer))
+                .additional_bytes(additional_bytes)
+                .instruction(),
+            Self::Upgrade {
+                buffer,
+                authority,
+                spill,
+                ..
+            } => UpgradeBuilder::new()
+                .program_data_account(program_data)
+                .program_account(*program)
+                .buffer_account(buffer)
+                .spill_account(spill)
+                .authority(authority)
+                .instruction(),
+            Self::SetAuthority { current, new } => SetAuthorityCheckedBuilder::new()
+                .buffer_or_program_data_account(program_data)
+                .current_authority(current)
+                .new_authority(new)
+                .instruction(),
+            Self::MakeImmutable { current } => make_immutable(
+                program,
+                &current,
+                acknowledged.ok_or(ManifestError::Unacknowledged(*program))?,
+            ),
+            Self::Close {
+                authority,
+                recipient,
+            } => close_program(
+                program,
+                &recipient,
+                &authority,
+                acknowledged.ok_or(ManifestError::Unacknowledged(*program))?,
+            ),
+        })
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Extend {
+                additional_bytes, ..
+            } => write!(f, "extend ProgramData by {additional_bytes} bytes"),
+            Self::Upgrade {
+                buffer, elf_hash, ..
+            } => write!(f, "upgrade to ELF {elf_hash} from buffer {buffer}"),
+            Self::SetAuthority { current, new } => {
+                write!(f, "set upgrade authority from {current} to {new}")
+            }
+            Self::MakeImmutable { current } => {
+                write!(f, "IRREVERSIBLE: remove upgrade authority {current}")
+            }
+            Self::Close { recipient, .. } => {
+                write!(
+                    f,
+                    "IRREVERSIBLE: close program, sending lamports to {recipient}"
+                )
+            }
+        }
+    }
+}
+
+/// Actions converging one program, each sent in its own transaction in
+/// order.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reconciliation {
+    pub program: Address,
+    pub actions: Vec<Action>,
+}
+
+impl Reconciliation {
+    /// Whether the program already matches its manifest entry.
+    pub fn is_converged(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// The instruction of every action, see [`Action::instruction`].
+    pub fn instructions(
+        &self,
+        acknowledged: Option<Irreversible>,
+    ) -> Result<Vec<Instruction>, ManifestError> {
+        self.actions
+            .iter()
+            .map(|action| action.instruction(&self.program, acknowledged))
+            .collect()
+    }
+}
+
+impl fmt::Display for Reconciliation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_converged() {
+            return write!(f, "{}: up to date", self.program);
+        }
+        write!(f, "{}:", self.program)?;
+        self.actions
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, action)| write!(f, "\n  {}. {action}", index.saturating_add(1)))
+    }
+}
+
+/// Compares `desired` with the data of the program's ProgramData account,
+/// `None` if it does not exist, and plans the actions converging them.
+///
+/// `elf` holds the desired ELF when available; it is only needed when the
+/// program must be upgraded. See [`Manifest::reconcile`] for `payer`.
+pub fn reconcile(
+    desired: &ProgramManifest,
+    program_data: Option<&[u8]>,
+    elf: Option<&[u8]>,
+    payer: &Address,
+) -> Result<Reconciliation, ManifestError> {
+    let program = desired.program_id;
+    let mut reconciliation = Reconciliation {
+        program,
+        actions: Vec::new(),
+    };
+    let Some(program_data) = program_data else {
+        return match desired.state {
+            DesiredState::Closed => Ok(reconciliation),
+            _ => Err(ManifestError::NotDeployed(program)),
+        };
+    };
+    let program_data = ProgramData::unpack(program_data)?;
+    let Some(current) = program_data.upgrade_authority else {
+        // Nothing can change an immutable program.
+        let unchanged = desired.state == DesiredState::Immutable
+            && desired
+                .elf_hash
+                .or(elf.map(ProgramHash::of_elf))
+                .is_none_or(|hash| hash == ProgramHash::of_elf(program_data.data));
+        return if unchanged {
+            Ok(reconciliation)
+        } else {
+            Err(ManifestError::Immutable(program))
+        };
+    };
+    if desired.state == DesiredState::Closed {
+        reconciliation.actions.push(Action::Close {
+            authority: current,
+            recipient: *payer,
+        });
+        return Ok(reconciliation);
+    }
+
+    let elf_hash = match (desired.elf_hash, elf) {
+        (Some(expected), Some(elf)) => {
+            let actual = ProgramHash::of_elf(elf);
+            if actual != expected {
+                return Err(ManifestError::ElfHashMismatch {
+                    program,
+                    expected,
+                    actual,
+                });
+            }
+            expected
+        }
+        (Some(expected), None) => expected,
+        (None, Some(elf)) => ProgramHash::of_elf(elf),
+        (None, None) => return Err(ManifestError::ElfUnavailable(program)),
+    };
+    let upgrade = elf_hash != ProgramHash::of_elf(program_data.data);
+    let elf_len = match elf {
+        Some(elf) => elf.len(),
+        None if upgrade => return Err(ManifestError::ElfUnavailable(program)),
+        None => crate::verify::trim_padding(program_data.data).len(),
+    };
+
+    let capacity = program_data.data.len();
+    let additional_bytes = plan_capacity(&desired.max_data_len, elf_len)?.saturating_sub(capacity);
+    if additional_bytes > 0 {
+        reconciliation.actions.push(Action::Extend {
+            additional_bytes: u32::try_from(additional_bytes)
+                .map_err(|_| CapacityError::ExtensionTooLarge { additional_bytes })?,
+            payer: *payer,
+        });
+    }
+    if let (true, Some(elf)) = (upgrade, elf) {
+        reconciliation.actions.push(Action::Upgrade {
+            buffer: find_buffer_address(payer, elf),
+            elf_hash,
+            authority: current,
+            spill: *payer,
+        });
+    }
+    match desired.state {
+        DesiredState::Upgradeable { authority } if authority != current => {
+            reconciliation.actions.push(Action::SetAuthority {
+                current,
+                new: authority,
+            })
+        }
+        DesiredState::Immutable => reconciliation
+            .actions
+            .push(Action::MakeImmutable { current }),
+        _ => {}
+    }
+    Ok(reconciliation)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawManifest {
+    #[serde(default)]
+    program: Vec<RawProgram>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawProgram {
+    name: Option<String>,
+    program_id: String,
+    elf: Option<PathBuf>,
+    elf_hash: Option<String>,
+    upgrade_authority: Option<String>,
+    #[serde(default)]
+    max_data_len: RawMaxDataLen,
+    #[serde(default)]
+    closed: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RawMaxDataLen {
+    #[default]
+    Exact,
+    HeadroomBytes(usize),
+    HeadroomPercent(u16),
+}
+
+impl RawProgram {
+    fn parse(self) -> Result<ProgramManifest, String> {
+        let state = match (self.closed, self.upgrade_authority.as_deref()) {
+            (true, None) => DesiredState::Closed,
+            (true, Some(_)) => return Err("a closed program has no upgrade authority".into()),
+            (false, None) => return Err("missing upgrade_authority".into()),
+            (false, Some("none")) => DesiredState::Immutable,
+            (false, Some(authority)) => DesiredState::Upgradeable {
+                authority: authority.parse().map_err(|error| format!("{error}"))?,
+            },
+        };
+        if state != DesiredState::Closed && self.elf.is_none() && self.elf_hash.is_none() {
+            return Err("missing elf or elf_hash".into());
+        }
+        Ok(ProgramManifest {
+            name: self.name,
+            program_id: self
+                .program_id
+                .parse()
+                .map_err(|error| format!("{error}"))?,
+            elf: self.elf,
+            elf_hash: self
+                .elf_hash
+                .map(|hash| hash.parse())
+                .transpose()
+                .map_err(|error| format!("{error}"))?,
+            state,
+            max_data_len: match self.max_data_len {
+                RawMaxDataLen::Exact => MaxDataLenPolicy::Exact,
+                RawMaxDataLen::HeadroomBytes(bytes) => MaxDataLenPolicy::HeadroomBytes(bytes),
+                RawMaxDataLen::HeadroomPercent(percent) => {
+                    MaxDataLenPolicy::HeadroomPercent(percent)
+                }
+            },
+        })
+    }
+}
@@ -0,0 +1,83 @@
+//! Durable transaction nonces.
+
+use {
+    solana_address::Address,
+    solana_hash::Hash,
+    solana_instruction::Instruction,
+    solana_nonce::{state::State, versions::Versions},
+    solana_system_interface::instruction::advance_nonce_account,
+    thiserror::Error,
+};
+
+/// Errors returned when decoding or checking a nonce account.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum NonceError {
+    #[error("invalid nonce account data: {0}")]
+    InvalidData(String),
+    #[error("nonce account is not initialized")]
+    Uninitialized,
+    #[error("legacy nonce accounts cannot be used in transactions")]
+    Legacy,
+    #[error("nonce authority is {actual}, expected {expected}")]
+    AuthorityMismatch { expected: Address, actual: Address },
+    #[error("nonce account stores blockhash {actual}, expected {expected}")]
+    BlockhashMismatch { expected: Hash, actual: Hash },
+}
+
+/// A durable nonce used in place of a recent blockhash, so a transaction can
+/// be signed long before it is submitted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DurableNonce {
+    /// The nonce account.
+    pub account: Address,
+    /// Authority allowed to advance the nonce, which must sign.
+    pub authority: Address,
+    /// Blockhash stored in the nonce account.
+    pub blockhash: Hash,
+}
+
+impl DurableNonce {
+    /// Decodes the authority and stored blockhash of the nonce account at
+    /// `account`.
+    pub fn from_account_data(account: Address, data: &[u8]) -> Result<Self, NonceError> {
+        let versions: Versions = bincode::deserialize(data)
+            .map_err(|error| NonceError::InvalidData(error.to_string()))?;
+        let state = match versions {
+            Versions::Legacy(_) => return Err(NonceError::Legacy),
+            Versions::Current(state) => *state,
+        };
+        match state {
+            State::Uninitialized => Err(NonceError::Uninitialized),
+            State::Initialized(data) => Ok(Self {
+                account,
+                authority: data.authority,
+                blockhash: data.blockhash(),
+            }),
+        }
+    }
+
+    /// Checks that the nonce account, given its current `data`, still stores
+    /// this blockhash and is advanced by this authority.
+    pub fn verify(&self, data: &[u8]) -> Result<(), NonceError> {
+        let stored = Self::from_account_data(self.account, data)?;
+        if stored.authority != self.authority {
+            return Err(NonceError::AuthorityMismatch {
+                expected: self.authority,
+                actual: stored.authority,
+            });
+        }
+        if stored.blockhash != self.blockhash {
+            return Err(NonceError::BlockhashMismatch {
+                expected: self.blockhash,
+                actual: stored.blockhash,
+            });
+        }
+        Ok(())
+    }
+
+    /// `AdvanceNonceAccount` instruction, which must be the first
+    /// instruction of a transaction using this nonce.
+    pub fn advance_instruction(&self) -> Instruction {
+        advance_nonce_account(&self.account, &self.authority)
+    }
+}
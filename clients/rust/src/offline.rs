@@ -0,0 +1,312 @@
+//! Offline signing of loader transactions.
+//!
+//! An [`OfflineTransaction`] holds a durable-nonce transaction for an
+//! `Upgrade`, `SetAuthorityChecked` or `Close` instruction together with the
+//! signatures collected so far. It is exchanged as a JSON file holding the
+//! base64 message and the required signers, so each authority can sign on
+//! an air-gapped machine and the partial files can be merged afterwards.
+//!
+//! ```json
+//! {
+//!   "message": "<base64 legacy message>",
+//!   "signers": [
+//!     { "address": "<base58>", "signature": "<base58>" },
+//!     { "address": "<base58>" }
+//!   ]
+//! }
+//! ```
+
+use {
+    crate::{
+        instruction::decompile,
+        instructions::{
+            CLOSE_DISCRIMINATOR, SET_AUTHORITY_CHECKED_DISCRIMINATOR, UPGRADE_DISCRIMINATOR,
+        },
+        nonce::DurableNonce,
+    },
+    base64::{prelude::BASE64_STANDARD, Engine},
+    serde::{Deserialize, Serialize},
+    solana_address::Address,
+    solana_instruction::Instruction,
+    solana_message::Message,
+    solana_signature::Signature,
+    solana_signer::Signer,
+    solana_transaction::Transaction,
+    std::str::FromStr,
+    thiserror::Error,
+};
+
+/// Errors returned when building, signing or merging offline transactions.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum OfflineError {
+    #[error("only Upgrade, SetAuthorityChecked and Close can be signed offline")]
+    UnsupportedInstruction,
+    #[error("{0} is not a required signer")]
+    NotRequiredSigner(Address),
+    #[error("signature of {0} does not match the message")]
+    InvalidSignature(Address),
+    #[error("{0} signed twice with different signatures")]
+    ConflictingSignature(Address),
+    #[error("failed to sign for {0}: {1}")]
+    Signer(Address, String),
+    #[error("the transactions have different messages")]
+    MessageMismatch,
+    #[error("the message does not match the expected instruction")]
+    UnexpectedMessage,
+    #[error("missing signatures from {0:?}")]
+    MissingSignatures(Vec<Address>),
+    #[error("invalid offline transaction file: {0}")]
+    InvalidFile(String),
+}
+
+/// Loader instructions that can be signed offline.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OfflineOperation {
+    Upgrade,
+    SetAuthorityChecked,
+    Close,
+}
+
+impl OfflineOperation {
+    /// Identifies the operation performed by a loader instruction.
+    pub fn of(instruction: &Instruction) -> Result<Self, OfflineError> {
+        if instruction.program_id != crate::LOADER_V3_ID {
+            return Err(OfflineError::UnsupportedInstruction);
+        }
+        let discriminator = instruction
+            .data
+            .first_chunk()
+            .map(|bytes| u32::from_le_bytes(*bytes));
+        match discriminator {
+            Some(UPGRADE_DISCRIMINATOR) => Ok(Self::Upgrade),
+            Some(SET_AUTHORITY_CHECKED_DISCRIMINATOR) => Ok(Self::SetAuthorityChecked),
+            Some(CLOSE_DISCRIMINATOR) => Ok(Self::Close),
+            _ => Err(OfflineError::UnsupportedInstruction),
+        }
+    }
+}
+
+/// A durable-nonce loader transaction and the signatures collected for it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OfflineTransaction {
+    message: Message,
+    /// One entry per required signer, in message order.
+    signatures: Vec<Option<Signature>>,
+}
+
+impl OfflineTransaction {
+    /// Builds an unsigned transaction advancing `nonce` and then running
+    /// `instruction`, with `payer` paying the fees.
+    pub fn new(
+        instruction: Instruction,
+        payer: &Address,
+        nonce: &DurableNonce,
+    ) -> Result<Self, OfflineError> {
+        OfflineOperation::of(&instruction)?;
+        let message = Message::new_with_blockhash(
+            &[nonce.advance_instruction(), instruction],
+            Some(payer),
+            &nonce.blockhash,
+        );
+        let signatures = vec![None; usize::from(message.header.num_required_signatures)];
+        Ok(Self {
+            message,
+            signatures,
+        })
+    }
+
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
+    /// Accounts that must sign, the fee payer first.
+    pub fn signers(&self) -> &[Address] {
+        &self.message.account_keys[..self.signatures.len()]
+    }
+
+    /// The signature collected for each required signer.
+    pub fn signatures(&self) -> impl Iterator<Item = (&Address, Option<&Signature>)> {
+        self.signers()
+            .iter()
+            .zip(self.signatures.iter().map(Option::as_ref))
+    }
+
+    /// Required signers that have not signed yet.
+    pub fn missing_signers(&self) -> Vec<Address> {
+        self.signatures()
+            .filter(|(_, signature)| signature.is_none())
+            .map(|(signer, _)| *signer)
+            .collect()
+    }
+
+    pub fn is_fully_signed(&self) -> bool {
+        self.signatures.iter().all(Option::is_some)
+    }
+
+    /// Signs the message with `signer`, which must be a required signer.
+    pub fn sign(&mut self, signer: &impl Signer) -> Result<(), OfflineError> {
+        let address = signer.pubkey();
+        let signature = signer
+            .try_sign_message(&self.message.serialize())
+            .map_err(|error| OfflineError::Signer(address, error.to_string()))?;
+        self.add_signature(address, signature)
+    }
+
+    /// Adds a signature produced elsewhere after checking it against the
+    /// message.
+    pub fn add_signature(
+        &mut self,
+        signer: Address,
+        signature: Signature,
+    ) -> Result<(), OfflineError> {
+        let index = self
+            .signers()
+            .iter()
+            .position(|address| *address == signer)
+            .ok_or(OfflineError::NotRequiredSigner(signer))?;
+        if !signature.verify(signer.as_ref(), &self.message.serialize()) {
+            return Err(OfflineError::InvalidSignature(signer));
+        }
+        match self.signatures[index] {
+            Some(existing) if existing != signature => {
+                Err(OfflineError::ConflictingSignature(signer))
+            }
+            _ => {
+                self.signatures[index] = Some(signature);
+                Ok(())
+            }
+        }
+    }
+
+    /// Adds the signatures of another copy of the same transaction.
+    pub fn merge(&mut self, other: &Self) -> Result<(), OfflineError> {
+        if self.message != other.message {
+            return Err(OfflineError::MessageMismatch);
+        }
+        for (signer, signature) in other.signatures() {
+            if let Some(signature) = signature {
+                self.add_signature(*signer, *signature)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the message advances `nonce` and then runs exactly
+    /// `expected`.
+    ///
+    /// Accounts may carry more privileges than `expected` asks for when
+    /// they also sign or are written by the nonce advance or pay the fees.
+    pub fn validate(
+        &self,
+        expected: &Instruction,
+        nonce: &DurableNonce,
+    ) -> Result<(), OfflineError> {
+        let matches = self.message.recent_blockhash == nonce.blockhash
+            && self.message.instructions.len() == 2
+            && [nonce.advance_instruction(), expected.clone()]
+                .iter()
+                .enumerate()
+                .all(|(index, expected)| {
+                    decompile(&self.message, index)
+                        .is_some_and(|actual| satisfies(&actual, expected))
+                });
+        if matches {
+            Ok(())
+        } else {
+            Err(OfflineError::UnexpectedMessage)
+        }
+    }
+
+    /// Validates the message and returns the signed transaction, ready to
+    /// broadcast.
+    pub fn into_transaction(
+        self,
+        expected: &Instruction,
+        nonce: &DurableNonce,
+    ) -> Result<Transaction, OfflineError> {
+        self.validate(expected, nonce)?;
+        let missing = self.missing_signers();
+        if !missing.is_empty() {
+            return Err(OfflineError::MissingSignatures(missing));
+        }
+        Ok(Transaction {
+            signatures: self.signatures.into_iter().flatten().collect(),
+            message: self.message,
+        })
+    }
+
+    /// Serializes to the portable JSON file format.
+    pub fn to_json(&self) -> String {
+        let file = OfflineFile {
+            message: BASE64_STANDARD.encode(self.message.serialize()),
+            signers: self
+                .signatures()
+                .map(|(address, signature)| SignerEntry {
+                    address: address.to_string(),
+                    signature: signature.map(Signature::to_string),
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&file).expect("offline file is serializable")
+    }
+
+    /// Parses the portable JSON file format, verifying every signature it
+    /// contains.
+    pub fn from_json(json: &str) -> Result<Self, OfflineError> {
+        let invalid = |error: &dyn std::fmt::Display| OfflineError::InvalidFile(error.to_string());
+        let file: OfflineFile = serde_json::from_str(json).map_err(|error| invalid(&error))?;
+        let bytes = BASE64_STANDARD
+            .decode(&file.message)
+            .map_err(|error| invalid(&error))?;
+        let message: Message = bincode::deserialize(&bytes).map_err(|error| invalid(&error))?;
+        let num_signers = usize::from(message.header.num_required_signatures);
+        if message.account_keys.len() < num_signers || file.signers.len() != num_signers {
+            return Err(invalid(&"signers do not match the message"));
+        }
+
+        let mut transaction = Self {
+            message,
+            signatures: vec![None; num_signers],
+        };
+        for (index, entry) in file.signers.iter().enumerate() {
+            let address = Address::from_str(&entry.address).map_err(|error| invalid(&error))?;
+            if transaction.signers()[index] != address {
+                return Err(invalid(&"signers do not match the message"));
+            }
+            if let Some(signature) = &entry.signature {
+                let signature = Signature::from_str(signature).map_err(|error| invalid(&error))?;
+                transaction.add_signature(address, signature)?;
+            }
+        }
+        Ok(transaction)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct OfflineFile {
+    message: String,
+    signers: Vec<SignerEntry>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SignerEntry {
+    address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+}
+
+/// Whether `actual` is `expected` with possibly more account privileges.
+fn satisfies(actual: &Instruction, expected: &Instruction) -> bool {
+    actual.program_id == expected.program_id
+        && actual.data == expected.data
+        && actual.accounts.len() == expected.accounts.len()
+        && actual
+            .accounts
+            .iter()
+            .zip(&expected.accounts)
+            .all(|(actual, expected)| {
+                actual.pubkey == expected.pubkey
+                    && actual.is_signer >= expected.is_signer
+                    && actual.is_writable >= expected.is_writable
+            })
+}
@@ -0,0 +1,408 @@
+//! Transaction plans for deploying and upgrading programs.
+//!
+//! A [`Plan`] lists the transactions needed to upload an ELF into a Buffer
+//! account and deploy or upgrade a program from it, each described by a
+//! [`PlanStep`]. The create-buffer step must land first and the final step
+//! last; the `Write` steps in between are independent of each other.
+//!
+//! `ExtendProgram` records the current slot as the program's deployment
+//! slot, and the loader rejects upgrading a program deployed in the same
+//! slot, so an `Extend` step must land in an earlier slot than the upgrade.
+//!
+//! With `seed_buffer`, the buffer address is derived from the payer and the
+//! ELF hash, see [`create_buffer_with_seed`], so the create-buffer step
+//! needs no buffer keypair and an interrupted upload can find its buffer.
+//!
+//! The final `Deploy` or `Upgrade` step can use a [`DurableNonce`] instead of
+//! a recent blockhash, so it can be signed long before the buffer is written
+//! and submitted once it is.
+
+use {
+    crate::{
+        buffer::{create_buffer, create_buffer_with_seed, find_buffer_address},
+        cost::WRITE_CHUNK_SIZE,
+        instructions::{
+            DeployWithMaxDataLenBuilder, ExtendProgramBuilder, UpgradeBuilder, WriteBuilder,
+        },
+        nonce::DurableNonce,
+        state::{find_program_data_address, PROGRAM_ACCOUNT_SIZE},
+    },
+    solana_address::Address,
+    solana_instruction::Instruction,
+    solana_rent::Rent,
+    solana_system_interface::instruction::create_account,
+};
+
+/// What a [`PlanStep`] does.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StepKind {
+    /// Creates and initializes the Buffer account.
+    CreateBuffer,
+    /// Writes `len` ELF bytes at `offset` into the buffer.
+    Write { offset: u32, len: usize },
+    /// Creates the Program account and deploys the buffer into it.
+    Deploy,
+    /// Extends the ProgramData account so the new ELF fits.
+    Extend,
+    /// Upgrades the program from the buffer.
+    Upgrade,
+}
+
+/// Instructions sent together in a single transaction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PlanStep {
+    pub kind: StepKind,
+    pub instructions: Vec<Instruction>,
+    /// Durable nonce whose blockhash the step's transaction must use in
+    /// place of a recent blockhash.
+    pub nonce: Option<DurableNonce>,
+}
+
+impl PlanStep {
+    pub fn new(kind: StepKind, instructions: Vec<Instruction>) -> Self {
+        Self {
+            kind,
+            instructions,
+            nonce: None,
+        }
+    }
+
+    /// Prepends the `AdvanceNonceAccount` instruction of `nonce`, making its
+    /// authority a signer of the step.
+    pub fn with_nonce(mut self, nonce: DurableNonce) -> Self {
+        self.instructions.insert(0, nonce.advance_instruction());
+        self.nonce = Some(nonce);
+        self
+    }
+
+    /// Accounts that must sign the step's transaction, in order of first
+    /// appearance. The fee payer is not included unless an instruction
+    /// requires its signature.
+    pub fn signers(&self) -> Vec<Address> {
+        let mut signers = Vec::new();
+        for meta in self.instructions.iter().flat_map(|ix| &ix.accounts) {
+            if meta.is_signer && !signers.contains(&meta.pubkey) {
+                signers.push(meta.pubkey);
+            }
+        }
+        signers
+    }
+}
+
+/// Ordered transactions deploying or upgrading a program.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Plan {
+    /// Buffer account the ELF is written to.
+    pub buffer: Address,
+    pub steps: Vec<PlanStep>,
+}
+
+impl Plan {
+    /// The `Write` steps, which may be sent in any order once the buffer
+    /// has been created.
+    pub fn writes(&self) -> impl Iterator<Item = &PlanStep> {
+        self.steps
+            .iter()
+            .filter(|step| matches!(step.kind, StepKind::Write { .. }))
+    }
+
+    /// Accounts that must sign at least one step.
+    pub fn signers(&self) -> Vec<Address> {
+        let mut signers = Vec::new();
+        for signer in self.steps.iter().flat_map(PlanStep::signers) {
+            if !signers.contains(&signer) {
+                signers.push(signer);
+            }
+        }
+        signers
+    }
+}
+
+/// One `Write` step per [`WRITE_CHUNK_SIZE`] chunk of `elf`.
+pub fn write_steps(buffer: &Address, authority: &Address, elf: &[u8]) -> Vec<PlanStep> {
+    elf.chunks(WRITE_CHUNK_SIZE)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let offset = u32::try_from(index.saturating_mul(WRITE_CHUNK_SIZE))
+                .expect("ELF offset overflows u32");
+            PlanStep::new(
+                StepKind::Write {
+                    offset,
+                    len: chunk.len(),
+                },
+                vec![WriteBuilder::new()
+                    .buffer_account(*buffer)
+                    .buffer_authority(*authority)
+                    .offset(offset)
+                    .bytes(chunk.into())
+                    .instruction()],
+            )
+        })
+        .collect()
+}
+
+/// Builder for a [`Plan`] writing an ELF into a new Buffer account without
+/// deploying it.
+///
+/// The buffer authority defaults to the payer.
+#[derive(Clone, Debug, Default)]
+pub struct WriteBufferPlanBuilder {
+    payer: Option<Address>,
+    buffer: Option<Address>,
+    seed_buffer: bool,
+    buffer_authority: Option<Address>,
+    rent: Option<Rent>,
+}
+
+impl WriteBufferPlanBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Account paying for the buffer and the transactions.
+    pub fn payer(&mut self, payer: Address) -> &mut Self {
+        self.payer = Some(payer);
+        self
+    }
+    /// New Buffer account, which must sign the create-buffer step.
+    pub fn buffer(&mut self, buffer: Address) -> &mut Self {
+        self.buffer = Some(buffer);
+        self
+    }
+    /// Derives the buffer address from the payer and the ELF hash instead,
+    /// see [`create_buffer_with_seed`].
+    pub fn seed_buffer(&mut self) -> &mut Self {
+        self.seed_buffer = true;
+        self
+    }
+    pub fn buffer_authority(&mut self, buffer_authority: Address) -> &mut Self {
+        self.buffer_authority = Some(buffer_authority);
+        self
+    }
+    /// Rent used to fund the buffer, defaults to [`Rent::default`].
+    pub fn rent(&mut self, rent: Rent) -> &mut Self {
+        self.rent = Some(rent);
+        self
+    }
+    pub fn build(&self, elf: &[u8]) -> Plan {
+        let payer = self.payer.expect("payer is not set");
+        let authority = self.buffer_authority.unwrap_or(payer);
+        let rent = self.rent.clone().unwrap_or_default();
+
+        let (buffer, create) = if self.seed_buffer {
+            (
+                find_buffer_address(&payer, elf),
+                create_buffer_with_seed(&payer, &authority, elf, &rent),
+            )
+        } else {
+            let buffer = self.buffer.expect("buffer is not set");
+            (
+                buffer,
+                create_buffer(&payer, &buffer, &authority, elf.len(), &rent),
+            )
+        };
+        let mut steps = vec![PlanStep::new(StepKind::CreateBuffer, create)];
+        steps.extend(write_steps(&buffer, &authority, elf));
+        Plan { buffer, steps }
+    }
+}
+
+/// Builder for a [`Plan`] deploying a new program.
+///
+/// The upgrade authority defaults to the payer and `max_data_len` to the
+/// ELF length.
+#[derive(Clone, Debug, Default)]
+pub struct DeployPlanBuilder {
+    buffer_plan: WriteBufferPlanBuilder,
+    payer: Option<Address>,
+    program: Option<Address>,
+    upgrade_authority: Option<Address>,
+    max_data_len: Option<u64>,
+    rent: Option<Rent>,
+    nonce: Option<DurableNonce>,
+}
+
+impl DeployPlanBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Account paying for the accounts and the transactions.
+    pub fn payer(&mut self, payer: Address) -> &mut Self {
+        self.buffer_plan.payer(payer);
+        self.payer = Some(payer);
+        self
+    }
+    /// New Program account, which must sign the deploy step.
+    pub fn program(&mut self, program: Address) -> &mut Self {
+        self.program = Some(program);
+        self
+    }
+    /// New Buffer account, which must sign the create-buffer step.
+    pub fn buffer(&mut self, buffer: Address) -> &mut Self {
+        self.buffer_plan.buffer(buffer);
+        self
+    }
+    /// Derives the buffer address from the payer and the ELF hash instead,
+    /// see [`create_buffer_with_seed`].
+    pub fn seed_buffer(&mut self) -> &mut Self {
+        self.buffer_plan.seed_buffer();
+        self
+    }
+    /// Upgrade authority of the program, also used as buffer authority.
+    pub fn upgrade_authority(&mut self, upgrade_authority: Address) -> &mut Self {
+        self.buffer_plan.buffer_authority(upgrade_authority);
+        self.upgrade_authority = Some(upgrade_authority);
+        self
+    }
+    pub fn max_data_len(&mut self, max_data_len: u64) -> &mut Self {
+        self.max_data_len = Some(max_data_len);
+        self
+    }
+    /// Durable nonce used by the deploy step, see [`PlanStep::with_nonce`].
+    pub fn nonce(&mut self, nonce: DurableNonce) -> &mut Self {
+        self.nonce = Some(nonce);
+        self
+    }
+    /// Rent used to fund new accounts, defaults to [`Rent::default`].
+    pub fn rent(&mut self, rent: Rent) -> &mut Self {
+        self.buffer_plan.rent(rent.clone());
+        self.rent = Some(rent);
+        self
+    }
+    pub fn build(&self, elf: &[u8]) -> Plan {
+        let payer = self.payer.expect("payer is not set");
+        let program = self.program.expect("program is not set");
+        let authority = self.upgrade_authority.unwrap_or(payer);
+        let rent = self.rent.clone().unwrap_or_default();
+
+        let mut plan = self.buffer_plan.build(elf);
+        let deploy = PlanStep::new(
+            StepKind::Deploy,
+            vec![
+                create_account(
+                    &payer,
+                    &program,
+                    rent.minimum_balance(PROGRAM_ACCOUNT_SIZE),
+                    PROGRAM_ACCOUNT_SIZE as u64,
+                    &crate::LOADER_V3_ID,
+                ),
+                DeployWithMaxDataLenBuilder::new()
+                    .payer_account(payer)
+                    .program_data_account(find_program_data_address(&program))
+                    .program_account(program)
+                    .buffer_account(plan.buffer)
+                    .authority(authority)
+                    .max_data_len(self.max_data_len.unwrap_or(elf.len() as u64))
+                    .instruction(),
+            ],
+        );
+        plan.steps.push(match self.nonce {
+            Some(nonce) => deploy.with_nonce(nonce),
+            None => deploy,
+        });
+        plan
+    }
+}
+
+/// Builder for a [`Plan`] upgrading an existing program.
+///
+/// The upgrade authority defaults to the payer and the spill account, which
+/// receives the buffer's lamports, to the payer.
+#[derive(Clone, Debug, Default)]
+pub struct UpgradePlanBuilder {
+    buffer_plan: WriteBufferPlanBuilder,
+    payer: Option<Address>,
+    program: Option<Address>,
+    upgrade_authority: Option<Address>,
+    spill: Option<Address>,
+    additional_bytes: Option<u32>,
+    nonce: Option<DurableNonce>,
+}
+
+impl UpgradePlanBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Account paying for the buffer and the transactions.
+    pub fn payer(&mut self, payer: Address) -> &mut Self {
+        self.buffer_plan.payer(payer);
+        self.payer = Some(payer);
+        self
+    }
+    pub fn program(&mut self, program: Address) -> &mut Self {
+        self.program = Some(program);
+        self
+    }
+    /// New Buffer account, which must sign the create-buffer step.
+    pub fn buffer(&mut self, buffer: Address) -> &mut Self {
+        self.buffer_plan.buffer(buffer);
+        self
+    }
+    /// Derives the buffer address from the payer and the ELF hash instead,
+    /// see [`create_buffer_with_seed`].
+    pub fn seed_buffer(&mut self) -> &mut Self {
+        self.buffer_plan.seed_buffer();
+        self
+    }
+    /// Current upgrade authority of the program, also used as buffer
+    /// authority.
+    pub fn upgrade_authority(&mut self, upgrade_authority: Address) -> &mut Self {
+        self.buffer_plan.buffer_authority(upgrade_authority);
+        self.upgrade_authority = Some(upgrade_authority);
+        self
+    }
+    pub fn spill(&mut self, spill: Address) -> &mut Self {
+        self.spill = Some(spill);
+        self
+    }
+    /// Extends the ProgramData account by `additional_bytes` in a step
+    /// before the upgrade, see [`plan_extend`](crate::capacity::plan_extend).
+    pub fn additional_bytes(&mut self, additional_bytes: u32) -> &mut Self {
+        self.additional_bytes = Some(additional_bytes);
+        self
+    }
+    /// Rent used to fund the buffer, defaults to [`Rent::default`].
+    pub fn rent(&mut self, rent: Rent) -> &mut Self {
+        self.buffer_plan.rent(rent);
+        self
+    }
+    /// Durable nonce used by the upgrade step, see [`PlanStep::with_nonce`].
+    pub fn nonce(&mut self, nonce: DurableNonce) -> &mut Self {
+        self.nonce = Some(nonce);
+        self
+    }
+    pub fn build(&self, elf: &[u8]) -> Plan {
+        let payer = self.payer.expect("payer is not set");
+        let program = self.program.expect("program is not set");
+        let authority = self.upgrade_authority.unwrap_or(payer);
+        let program_data = find_program_data_address(&program);
+
+        let mut plan = self.buffer_plan.build(elf);
+        if let Some(additional_bytes) = self.additional_bytes {
+            plan.steps.push(PlanStep::new(
+                StepKind::Extend,
+                vec![ExtendProgramBuilder::new()
+                    .program_data_account(program_data)
+                    .program_account(program)
+                    .system_program(Some(solana_system_interface::program::ID))
+                    .payer(Some(payer))
+                    .additional_bytes(additional_bytes)
+                    .instruction()],
+            ));
+        }
+        let upgrade = PlanStep::new(
+            StepKind::Upgrade,
+            vec![UpgradeBuilder::new()
+                .program_data_account(program_data)
+                .program_account(program)
+                .buffer_account(plan.buffer)
+                .spill_account(self.spill.unwrap_or(payer))
+                .authority(authority)
+                .instruction()],
+        );
+        plan.steps.push(match self.nonce {
+            Some(nonce) => upgrade.with_nonce(nonce),
+            None => upgrade,
+        });
+        plan
+    }
+}
@@ -0,0 +1,260 @@
+//! Upgrade-authority policies over program inventories.
+//!
+//! An [`Inventory`] holds the decoded ProgramData and Buffer accounts of a
+//! set of programs, e.g. fetched nightly for every mainnet program. A
+//! [`Policy`] evaluates its [`Rule`]s over it and reports each
+//! [`Violation`], which [`Report::to_json`] serializes for jobs gating on
+//! the result:
+//!
+//! ```json
+//! {
+//!   "compliant": false,
+//!   "programs": 2,
+//!   "buffers": 1,
+//!   "violations": [
+//!     { "rule": "authorityInSet", "account": "<base58>", "detail": "..." }
+//!   ]
+//! }
+//! ```
+
+use {
+    crate::state::{Buffer, ProgramData, StateError},
+    serde::Serialize,
+    solana_address::Address,
+};
+
+/// Upgrade state of a program, decoded from its ProgramData account.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ProgramState {
+    pub program: Address,
+    /// Slot of the last deploy or upgrade.
+    pub slot: u64,
+    pub upgrade_authority: Option<Address>,
+}
+
+impl ProgramState {
+    /// Decodes the data of the ProgramData account of `program`.
+    pub fn from_program_data(program: Address, data: &[u8]) -> Result<Self, StateError> {
+        let program_data = ProgramData::unpack(data)?;
+        Ok(Self {
+            program,
+            slot: program_data.slot,
+            upgrade_authority: program_data.upgrade_authority,
+        })
+    }
+}
+
+/// Authority of a Buffer account.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BufferState {
+    pub address: Address,
+    pub authority: Option<Address>,
+}
+
+impl BufferState {
+    /// Decodes the data of the Buffer account at `address`.
+    pub fn from_account_data(address: Address, data: &[u8]) -> Result<Self, StateError> {
+        Ok(Self {
+            address,
+            authority: Buffer::unpack(data)?.authority,
+        })
+    }
+}
+
+/// Programs and buffers evaluated by a [`Policy`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Inventory {
+    /// Slot at which the accounts were fetched.
+    pub current_slot: u64,
+    pub programs: Vec<ProgramState>,
+    pub buffers: Vec<BufferState>,
+}
+
+/// A requirement on the programs or buffers of an [`Inventory`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Rule {
+    /// Upgrade authorities must be one of `allowed`, such as known multisig
+    /// vaults. Immutable programs are left to [`Rule::AuthorityRequired`].
+    AuthorityInSet { allowed: Vec<Address> },
+    /// Programs must have an upgrade authority, unless listed in
+    /// `immutable_allowlist`.
+    AuthorityRequired { immutable_allowlist: Vec<Address> },
+    /// Upgradeable programs must have been deployed or upgraded within the
+    /// last `max_slots` slots.
+    MaxDeployAge { max_slots: u64 },
+    /// Buffers must be controlled by the upgrade authority of a program in
+    /// the inventory, the only authority able to deploy from them.
+    BufferAuthorityMatches,
+}
+
+impl Rule {
+    /// Identifier of the rule in violation reports.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::AuthorityInSet { .. } => "authorityInSet",
+            Self::AuthorityRequired { .. } => "authorityRequired",
+            Self::MaxDeployAge { .. } => "maxDeployAge",
+            Self::BufferAuthorityMatches => "bufferAuthorityMatches",
+        }
+    }
+
+    /// The violations of the rule in `inventory`.
+    pub fn evaluate(&self, inventory: &Inventory) -> Vec<Violation> {
+        let violation = |account: Address, detail: String| Violation {
+            rule: self.name(),
+            account,
+            detail,
+        };
+        match self {
+            Self::AuthorityInSet { allowed } => inventory
+                .programs
+                .iter()
+                .filter_map(|program| {
+                    let authority = program.upgrade_authority?;
+                    (!allowed.contains(&authority)).then(|| {
+                        violation(
+                            program.program,
+                            format!("upgrade authority {authority} is not allowed"),
+                        )
+                    })
+                })
+                .collect(),
+            Self::AuthorityRequired {
+                immutable_allowlist,
+            } => inventory
+                .programs
+                .iter()
+                .filter(|program| {
+                    program.upgrade_authority.is_none()
+                        && !immutable_allowlist.contains(&program.program)
+                })
+                .map(|program| {
+                    violation(
+                        program.program,
+                        "program is immutable but not allowlisted".to_string(),
+                    )
+                })
+                .collect(),
+            Self::MaxDeployAge { max_slots } => inventory
+                .programs
+                .iter()
+                .filter(|program| program.upgrade_authority.is_some())
+                .filter_map(|program| {
+                    let age = inventory.current_slot.saturating_sub(program.slot);
+                    (age > *max_slots).then(|| {
+                        violation(
+                            program.program,
+                            format!(
+                                "deployed at slot {}, {age} slots ago, over the maximum of \
+                                 {max_slots}",
+                                program.slot
+                            ),
+                        )
+                    })
+                })
+                .collect(),
+            Self::BufferAuthorityMatches => inventory
+                .buffers
+                .iter()
+                .filter_map(|buffer| {
+                    let detail = match buffer.authority {
+                        None => "buffer has no authority".to_string(),
+                        Some(authority)
+                            if inventory
+                                .programs
+                                .iter()
+                                .any(|program| program.upgrade_authority == Some(authority)) =>
+                        {
+                            return None
+                        }
+                        Some(authority) => {
+                            format!("buffer authority {authority} upgrades no program")
+                        }
+                    };
+                    Some(violation(buffer.address, detail))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// An account breaking a [`Rule`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Violation {
+    /// [`Rule::name`] of the broken rule.
+    pub rule: &'static str,
+    /// Program or buffer breaking the rule.
+    pub account: Address,
+    pub detail: String,
+}
+
+/// Rules every inventory must satisfy.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Policy {
+    pub rules: Vec<Rule>,
+}
+
+impl Policy {
+    /// Evaluates every rule over `inventory`, listing violations rule by
+    /// rule.
+    pub fn evaluate(&self, inventory: &Inventory) -> Report {
+        Report {
+            programs: inventory.programs.len(),
+            buffers: inventory.buffers.len(),
+            violations: self
+                .rules
+                .iter()
+                .flat_map(|rule| rule.evaluate(inventory))
+                .collect(),
+        }
+    }
+}
+
+/// Outcome of evaluating a [`Policy`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Report {
+    /// Number of programs evaluated.
+    pub programs: usize,
+    /// Number of buffers evaluated.
+    pub buffers: usize,
+    pub violations: Vec<Violation>,
+}
+
+impl Report {
+    pub fn is_compliant(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        let report = ReportJson {
+            compliant: self.is_compliant(),
+            programs: self.programs,
+            buffers: self.buffers,
+            violations: self
+                .violations
+                .iter()
+                .map(|violation| ViolationJson {
+                    rule: violation.rule,
+                    account: violation.account.to_string(),
+                    detail: &violation.detail,
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&report).expect("report is serializable")
+    }
+}
+
+#[derive(Serialize)]
+struct ReportJson<'a> {
+    compliant: bool,
+    programs: usize,
+    buffers: usize,
+    violations: Vec<ViolationJson<'a>>,
+}
+
+#[derive(Serialize)]
+struct ViolationJson<'a> {
+    rule: &'static str,
+    account: String,
+    detail: &'a str,
+}
@@ -0,0 +1,258 @@
+//! Preflight checks for `Upgrade` instructions.
+//!
+//! The loader rejects an upgrade at the first failed check, and the fee is
+//! spent by then. [`check_upgrade`] runs the loader's checks against the
+//! accounts of an [`Upgrade`] fetched beforehand and reports every
+//! [`UpgradeViolation`] at once, each explaining what to fix:
+//!
+//! ```text
+//! buffer authority <base58> is not the signing authority <base58>
+//! buffer holds 120000 bytes but ProgramData has room for 80000; extend the program by 40000 bytes
+//! ```
+
+use {
+    crate::{
+        elf::{Elf, ElfError},
+        instructions::Upgrade,
+        rpc::{LoaderRpc, RpcError},
+        state::{
+            find_program_data_address, LoaderState, BUFFER_HEADER_SIZE, CLOCK_SYSVAR_ID,
+            PROGRAM_DATA_HEADER_SIZE, RENT_SYSVAR_ID,
+        },
+    },
+    solana_account::Account,
+    solana_address::Address,
+    solana_rent::Rent,
+    thiserror::Error,
+};
+
+/// A precondition of `Upgrade` that does not hold.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum UpgradeViolation {
+    #[error("rent sysvar is {0}, expected {RENT_SYSVAR_ID}")]
+    RentSysvar(Address),
+    #[error("clock sysvar is {0}, expected {CLOCK_SYSVAR_ID}")]
+    ClockSysvar(Address),
+    #[error("account {0} does not exist")]
+    AccountNotFound(Address),
+    #[error("account {account} is owned by {owner}, not the loader")]
+    NotOwnedByLoader { account: Address, owner: Address },
+    #[error("account {account} is not a {expected} account")]
+    UnexpectedState {
+        account: Address,
+        expected: &'static str,
+    },
+    #[error("ProgramData account of the program is {expected}, not {actual}")]
+    ProgramDataMismatch { expected: Address, actual: Address },
+    #[error("program is immutable")]
+    Immutable,
+    #[error("upgrade authority {upgrade_authority} is not the signing authority {authority}")]
+    UpgradeAuthorityMismatch {
+        upgrade_authority: Address,
+        authority: Address,
+    },
+    #[error("buffer has no authority and cannot be deployed")]
+    BufferImmutable,
+    #[error("buffer authority {buffer_authority} is not the signing authority {authority}")]
+    BufferAuthorityMismatch {
+        buffer_authority: Address,
+        authority: Address,
+    },
+    #[error("buffer is empty")]
+    EmptyBuffer,
+    #[error(
+        "buffer holds {buffer_len} bytes but ProgramData has room for {capacity}; extend the \
+         program by {} bytes",
+        buffer_len.saturating_sub(*capacity)
+    )]
+    ExceedsCapacity { buffer_len: usize, capacity: usize },
+    #[error("buffer and ProgramData hold {available} lamports, {required} are needed for rent")]
+    InsufficientFunds { available: u64, required: u64 },
+    #[error("buffer does not hold a valid ELF: {0}")]
+    InvalidElf(ElfError),
+    #[error("spill account must not be the buffer")]
+    SpillIsBuffer,
+    #[error("spill account must not be the ProgramData account")]
+    SpillIsProgramData,
+}
+
+/// The accounts an `Upgrade` reads, `None` where an account does not exist.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UpgradeAccounts {
+    pub program: Option<Account>,
+    pub program_data: Option<Account>,
+    pub buffer: Option<Account>,
+}
+
+impl UpgradeAccounts {
+    /// Fetches the Program, ProgramData and Buffer accounts of `upgrade` in
+    /// one request.
+    pub fn fetch(rpc: &impl LoaderRpc, upgrade: &Upgrade) -> Result<Self, RpcError> {
+        let [program, program_data, buffer] = rpc
+            .get_multiple_accounts(&[
+                upgrade.program_account,
+                upgrade.program_data_account,
+                upgrade.buffer_account,
+            ])?
+            .try_into()
+            .map_err(|_| RpcError::Request("unexpected number of accounts".to_string()))?;
+        Ok(Self {
+            program,
+            program_data,
+            buffer,
+        })
+    }
+}
+
+/// Returns every precondition of `upgrade` that `accounts` violate, in the
+/// order the loader checks them. An empty list means the upgrade is expected
+/// to succeed.
+///
+/// The loader also rejects an upgrade in the slot the program was last
+/// deployed or upgraded in, which depends on when the transaction lands and
+/// is not checked here.
+pub fn check_upgrade(
+    upgrade: &Upgrade,
+    accounts: &UpgradeAccounts,
+    rent: &Rent,
+) -> Vec<UpgradeViolation> {
+    let mut violations = Vec::new();
+    if upgrade.rent_sysvar != RENT_SYSVAR_ID {
+        violations.push(UpgradeViolation::RentSysvar(upgrade.rent_sysvar));
+    }
+    if upgrade.clock_sysvar != CLOCK_SYSVAR_ID {
+        violations.push(UpgradeViolation::ClockSysvar(upgrade.clock_sysvar));
+    }
+
+    let program = loader_state(&mut violations, upgrade.program_account, &accounts.program);
+    let expected_program_data = match program {
+        Some(LoaderState::Program { program_data }) => program_data,
+        Some(_) => {
+            violations.push(UpgradeViolation::UnexpectedState {
+                account: upgrade.program_account,
+                expected: "Program",
+            });
+            find_program_data_address(&upgrade.program_account)
+        }
+        None => find_program_data_address(&upgrade.program_account),
+    };
+    if expected_program_data != upgrade.program_data_account {
+        violations.push(UpgradeViolation::ProgramDataMismatch {
+            expected: expected_program_data,
+            actual: upgrade.program_data_account,
+        });
+    }
+
+    match loader_state(&mut violations, upgrade.buffer_account, &accounts.buffer) {
+        Some(LoaderState::Buffer { authority: None }) => {
+            violations.push(UpgradeViolation::BufferImmutable)
+        }
+        Some(LoaderState::Buffer {
+            authority: Some(buffer_authority),
+        }) if buffer_authority != upgrade.authority => {
+            violations.push(UpgradeViolation::BufferAuthorityMismatch {
+                buffer_authority,
+                authority: upgrade.authority,
+            })
+        }
+        Some(LoaderState::Buffer { .. }) | None => {}
+        Some(_) => violations.push(UpgradeViolation::UnexpectedState {
+            account: upgrade.buffer_account,
+            expected: "Buffer",
+        }),
+    }
+    let elf = accounts
+        .buffer
+        .as_ref()
+        .and_then(|buffer| buffer.data.get(BUFFER_HEADER_SIZE..))
+        .unwrap_or_default();
+    if accounts.buffer.is_some() && elf.is_empty() {
+        violations.push(UpgradeViolation::EmptyBuffer);
+    }
+
+    match loader_state(
+        &mut violations,
+        upgrade.program_data_account,
+        &accounts.program_data,
+    ) {
+        Some(LoaderState::ProgramData {
+            upgrade_authority: None,
+            ..
+        }) => violations.push(UpgradeViolation::Immutable),
+        Some(LoaderState::ProgramData {
+            upgrade_authority: Some(upgrade_authority),
+            ..
+        }) if upgrade_authority != upgrade.authority => {
+            violations.push(UpgradeViolation::UpgradeAuthorityMismatch {
+                upgrade_authority,
+                authority: upgrade.authority,
+            })
+        }
+        Some(LoaderState::ProgramData { .. }) | None => {}
+        Some(_) => violations.push(UpgradeViolation::UnexpectedState {
+            account: upgrade.program_data_account,
+            expected: "ProgramData",
+        }),
+    }
+    if let Some(program_data) = &accounts.program_data {
+        let capacity = program_data
+            .data
+            .len()
+            .saturating_sub(PROGRAM_DATA_HEADER_SIZE);
+        if elf.len() > capacity {
+            violations.push(UpgradeViolation::ExceedsCapacity {
+                buffer_len: elf.len(),
+                capacity,
+            });
+        }
+        let available = program_data
+            .lamports
+            .saturating_add(accounts.buffer.as_ref().map_or(0, |buffer| buffer.lamports));
+        let required = rent.minimum_balance(program_data.data.len()).max(1);
+        if available < required {
+            violations.push(UpgradeViolation::InsufficientFunds {
+                available,
+                required,
+            });
+        }
+    }
+
+    if !elf.is_empty() {
+        if let Err(error) = Elf::parse(elf) {
+            violations.push(UpgradeViolation::InvalidElf(error));
+        }
+    }
+    if upgrade.spill_account == upgrade.buffer_account {
+        violations.push(UpgradeViolation::SpillIsBuffer);
+    }
+    if upgrade.spill_account == upgrade.program_data_account {
+        violations.push(UpgradeViolation::SpillIsProgramData);
+    }
+    violations
+}
+
+/// Decodes a loader account, recording why it cannot be.
+fn loader_state(
+    violations: &mut Vec<UpgradeViolation>,
+    address: Address,
+    account: &Option<Account>,
+) -> Option<LoaderState> {
+    let Some(account) = account else {
+        violations.push(UpgradeViolation::AccountNotFound(address));
+        return None;
+    };
+    if account.owner != crate::LOADER_V3_ID {
+        violations.push(UpgradeViolation::NotOwnedByLoader {
+            account: address,
+            owner: account.owner,
+        });
+        return None;
+    }
+    LoaderState::unpack(&account.data).ok().or_else(|| {
+        violations.push(UpgradeViolation::UnexpectedState {
+            account: address,
+            expected: "loader",
+        });
+        None
+    })
+}
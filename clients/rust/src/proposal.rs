@@ -0,0 +1,195 @@
+//! Export of loader instructions as multisig proposals.
+//!
+//! When an upgrade or buffer authority is a multisig vault, the loader
+//! instruction is not signed directly but proposed to the multisig, which
+//! executes it with the vault as signer. A [`Proposal`] serializes the
+//! instruction in the two forms proposal tools import:
+//!
+//! - a base64 versioned (v0) message paid for and signed by the vault, with the
+//!   default blockhash since the multisig program supplies its own;
+//! - a raw instruction JSON with base58 accounts and base64 data:
+//!
+//! ```json
+//! {
+//!   "programId": "<base58>",
+//!   "accounts": [
+//!     { "pubkey": "<base58>", "isSigner": true, "isWritable": false }
+//!   ],
+//!   "data": "<base64>"
+//! }
+//! ```
+//!
+//! Both are deterministic, so every member can rebuild and compare them.
+
+use {
+    base64::{prelude::BASE64_STANDARD, Engine},
+    serde::{Deserialize, Serialize},
+    solana_address::Address,
+    solana_hash::Hash,
+    solana_instruction::{AccountMeta, Instruction},
+    solana_message::{v0, VersionedMessage},
+    std::str::FromStr,
+    thiserror::Error,
+};
+
+/// Errors returned when exporting or parsing proposals.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum ProposalError {
+    #[error("instruction is not for the loader-v3 program")]
+    NotLoaderInstruction,
+    #[error("{0} must sign but is not the vault")]
+    ForeignSigner(Address),
+    #[error("invalid proposal message: {0}")]
+    InvalidMessage(String),
+    #[error("invalid instruction JSON: {0}")]
+    InvalidJson(String),
+}
+
+/// A loader instruction to be executed by a multisig vault.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Proposal {
+    pub vault: Address,
+    pub instruction: Instruction,
+}
+
+impl Proposal {
+    /// Checks that `instruction` targets the loader and requires no
+    /// signature other than the vault's.
+    pub fn new(instruction: Instruction, vault: Address) -> Result<Self, ProposalError> {
+        if instruction.program_id != crate::LOADER_V3_ID {
+            return Err(ProposalError::NotLoaderInstruction);
+        }
+        if let Some(meta) = instruction
+            .accounts
+            .iter()
+            .find(|meta| meta.is_signer && meta.pubkey != vault)
+        {
+            return Err(ProposalError::ForeignSigner(meta.pubkey));
+        }
+        Ok(Self { vault, instruction })
+    }
+
+    /// The v0 message running the instruction with the vault as fee payer
+    /// and signer.
+    pub fn to_message(&self) -> VersionedMessage {
+        let message = v0::Message::try_compile(
+            &self.vault,
+            std::slice::from_ref(&self.instruction),
+            &[],
+            Hash::default(),
+        )
+        .expect("a single instruction without lookup tables compiles");
+        VersionedMessage::V0(message)
+    }
+
+    /// The serialized [`to_message`](Self::to_message), base64 encoded.
+    pub fn to_base64_message(&self) -> String {
+        BASE64_STANDARD.encode(self.to_message().serialize())
+    }
+
+    /// Parses a message produced by
+    /// [`to_base64_message`](Self::to_base64_message).
+    ///
+    /// Account privileges are those of the message, so the vault comes back
+    /// as a writable signer wherever it appears.
+    pub fn from_base64_message(encoded: &str) -> Result<Self, ProposalError> {
+        let invalid =
+            |error: &dyn std::fmt::Display| ProposalError::InvalidMessage(error.to_string());
+        let bytes = BASE64_STANDARD
+            .decode(encoded)
+            .map_err(|error| invalid(&error))?;
+        let message: VersionedMessage =
+            bincode::deserialize(&bytes).map_err(|error| invalid(&error))?;
+        message.sanitize().map_err(|error| invalid(&error))?;
+        if !matches!(message, VersionedMessage::V0(_))
+            || message
+                .address_table_lookups()
+                .is_some_and(|lookups| !lookups.is_empty())
+        {
+            return Err(invalid(&"expected a v0 message without lookup tables"));
+        }
+        let [compiled] = message.instructions() else {
+            return Err(invalid(&"expected a single instruction"));
+        };
+
+        let keys = message.static_account_keys();
+        let accounts = compiled
+            .accounts
+            .iter()
+            .map(|index| {
+                let index = usize::from(*index);
+                AccountMeta {
+                    pubkey: keys[index],
+                    is_signer: message.is_signer(index),
+                    is_writable: message.is_maybe_writable(index, None),
+                }
+            })
+            .collect();
+        let instruction = Instruction {
+            program_id: keys[usize::from(compiled.program_id_index)],
+            accounts,
+            data: compiled.data.clone(),
+        };
+        Self::new(instruction, keys[0])
+    }
+
+    /// The instruction as raw instruction JSON.
+    pub fn to_instruction_json(&self) -> String {
+        let json = InstructionJson {
+            program_id: self.instruction.program_id.to_string(),
+            accounts: self
+                .instruction
+                .accounts
+                .iter()
+                .map(|meta| AccountJson {
+                    pubkey: meta.pubkey.to_string(),
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                })
+                .collect(),
+            data: BASE64_STANDARD.encode(&self.instruction.data),
+        };
+        serde_json::to_string_pretty(&json).expect("instruction JSON is serializable")
+    }
+
+    /// Parses raw instruction JSON to be executed by `vault`.
+    pub fn from_instruction_json(json: &str, vault: Address) -> Result<Self, ProposalError> {
+        let invalid = |error: &dyn std::fmt::Display| ProposalError::InvalidJson(error.to_string());
+        let json: InstructionJson = serde_json::from_str(json).map_err(|error| invalid(&error))?;
+        let accounts = json
+            .accounts
+            .iter()
+            .map(|account| {
+                Ok(AccountMeta {
+                    pubkey: Address::from_str(&account.pubkey).map_err(|error| invalid(&error))?,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+            })
+            .collect::<Result<_, ProposalError>>()?;
+        let instruction = Instruction {
+            program_id: Address::from_str(&json.program_id).map_err(|error| invalid(&error))?,
+            accounts,
+            data: BASE64_STANDARD
+                .decode(&json.data)
+                .map_err(|error| invalid(&error))?,
+        };
+        Self::new(instruction, vault)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InstructionJson {
+    program_id: String,
+    accounts: Vec<AccountJson>,
+    data: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AccountJson {
+    pubkey: String,
+    is_signer: bool,
+    is_writable: bool,
+}
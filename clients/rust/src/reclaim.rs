@@ -0,0 +1,106 @@
+//! Reclaiming rent from stale Buffer accounts.
+//!
+//! Failed or abandoned deploys leave Buffer accounts holding rent. Once found
+//! with [`BufferFiltersBuilder`](crate::filter::BufferFiltersBuilder), a
+//! [`ReclaimPlan`] closes them into a destination account, packing as many
+//! `Close` instructions per transaction as fit. Buffers are grouped by
+//! authority so each authority signs as few transactions as possible.
+
+use {
+    crate::{
+        instructions::CloseBuilder,
+        state::{Buffer, StateError},
+    },
+    solana_address::Address,
+    solana_instruction::Instruction,
+    solana_message::Message,
+    solana_transaction::Transaction,
+};
+
+/// Maximum size of a serialized transaction.
+const MAX_TRANSACTION_SIZE: u64 = 1232;
+
+/// A Buffer account that may be closed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StaleBuffer {
+    pub address: Address,
+    /// `None` for buffers whose authority was removed, which cannot be
+    /// closed.
+    pub authority: Option<Address>,
+    pub lamports: u64,
+}
+
+impl StaleBuffer {
+    /// Decodes the Buffer account at `address`.
+    pub fn from_account_data(
+        address: Address,
+        lamports: u64,
+        data: &[u8],
+    ) -> Result<Self, StateError> {
+        Ok(Self {
+            address,
+            authority: Buffer::unpack(data)?.authority,
+            lamports,
+        })
+    }
+}
+
+/// Transactions closing stale buffers and what they recover.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReclaimPlan {
+    /// `Close` instructions, one inner vector per transaction.
+    pub transactions: Vec<Vec<Instruction>>,
+    /// Buffers closed by the transactions.
+    pub closed: Vec<Address>,
+    /// Buffers without an authority, which cannot be closed.
+    pub skipped: Vec<Address>,
+    /// Lamports sent to the destination once every transaction landed.
+    pub recovered_lamports: u64,
+}
+
+impl ReclaimPlan {
+    /// Plans closing `buffers` into `destination`, with `payer` paying the
+    /// transaction fees.
+    pub fn new(buffers: &[StaleBuffer], payer: &Address, destination: &Address) -> Self {
+        let mut closable: Vec<_> = buffers
+            .iter()
+            .filter_map(|buffer| Some((buffer.authority?, buffer)))
+            .collect();
+        closable.sort_by_key(|(authority, _)| *authority);
+
+        let mut plan = Self {
+            skipped: buffers
+                .iter()
+                .filter(|buffer| buffer.authority.is_none())
+                .map(|buffer| buffer.address)
+                .collect(),
+            ..Self::default()
+        };
+        let mut current: Vec<Instruction> = Vec::new();
+        for (authority, buffer) in closable {
+            let close = CloseBuilder::new()
+                .buffer_or_program_data_account(buffer.address)
+                .destination_account(*destination)
+                .authority(Some(authority))
+                .instruction();
+            current.push(close);
+            if current.len() > 1 && transaction_size(&current, payer) > MAX_TRANSACTION_SIZE {
+                let close = current.pop().expect("just pushed");
+                plan.transactions
+                    .push(std::mem::replace(&mut current, vec![close]));
+            }
+            plan.closed.push(buffer.address);
+            plan.recovered_lamports = plan.recovered_lamports.saturating_add(buffer.lamports);
+        }
+        if !current.is_empty() {
+            plan.transactions.push(current);
+        }
+        plan
+    }
+}
+
+/// Size of the signed legacy transaction carrying `instructions`.
+fn transaction_size(instructions: &[Instruction], payer: &Address) -> u64 {
+    let transaction = Transaction::new_unsigned(Message::new(instructions, Some(payer)));
+    bincode::serialized_size(&transaction).expect("transaction is serializable")
+}
@@ -0,0 +1,204 @@
+//! Two-party authority rotation with `SetAuthorityChecked`.
+//!
+//! `SetAuthorityChecked` requires both the current and the new authority to
+//! sign, which usually means two teams signing the same transaction. An
+//! [`AuthorityRotation`] builds that transaction on a durable nonce, so each
+//! side can sign when it is ready, tracks which authorities have signed and
+//! verifies the account once the transaction has landed.
+//!
+//! A ProgramData rotation never falls back to the unchecked `SetAuthority`,
+//! which would let a mistyped new authority lock the program forever.
+
+use {
+    crate::{
+        instruction::set_authority,
+        instructions::SetAuthorityCheckedBuilder,
+        nonce::DurableNonce,
+        offline::{OfflineError, OfflineTransaction},
+        state::{find_program_data_address, Buffer, ProgramData, StateError},
+    },
+    solana_address::Address,
+    solana_instruction::Instruction,
+    solana_signature::Signature,
+    solana_signer::Signer,
+    solana_transaction::Transaction,
+    thiserror::Error,
+};
+
+/// Errors returned by an [`AuthorityRotation`].
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum RotationError {
+    #[error("the new authority is the current authority")]
+    SameAuthority,
+    #[error("ProgramData authority changes must use SetAuthorityChecked")]
+    UncheckedProgramData,
+    #[error("authority is {actual:?}, expected {expected}")]
+    UnexpectedAuthority {
+        expected: Address,
+        actual: Option<Address>,
+    },
+    #[error(transparent)]
+    Offline(#[from] OfflineError),
+    #[error(transparent)]
+    State(#[from] StateError),
+}
+
+/// Account whose authority is rotated.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RotationTarget {
+    /// The upgrade authority of a program, stored in its ProgramData account.
+    Program(Address),
+    /// The authority of a Buffer account.
+    Buffer(Address),
+}
+
+impl RotationTarget {
+    /// The Buffer or ProgramData account holding the authority.
+    pub fn account(&self) -> Address {
+        match self {
+            Self::Program(program) => find_program_data_address(program),
+            Self::Buffer(buffer) => *buffer,
+        }
+    }
+}
+
+/// A `SetAuthorityChecked` transaction and the signatures collected for it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuthorityRotation {
+    target: RotationTarget,
+    current_authority: Address,
+    new_authority: Address,
+    transaction: OfflineTransaction,
+}
+
+impl AuthorityRotation {
+    /// Builds the unsigned rotation of `target` from `current_authority` to
+    /// `new_authority`, paid for by `payer`.
+    pub fn new(
+        target: RotationTarget,
+        current_authority: Address,
+        new_authority: Address,
+        payer: &Address,
+        nonce: &DurableNonce,
+    ) -> Result<Self, RotationError> {
+        if current_authority == new_authority {
+            return Err(RotationError::SameAuthority);
+        }
+        let instruction = checked_instruction(&target, &current_authority, &new_authority);
+        Ok(Self {
+            target,
+            current_authority,
+            new_authority,
+            transaction: OfflineTransaction::new(instruction, payer, nonce)?,
+        })
+    }
+
+    pub fn target(&self) -> RotationTarget {
+        self.target
+    }
+
+    pub fn current_authority(&self) -> Address {
+        self.current_authority
+    }
+
+    pub fn new_authority(&self) -> Address {
+        self.new_authority
+    }
+
+    /// The `SetAuthorityChecked` instruction.
+    pub fn instruction(&self) -> Instruction {
+        checked_instruction(&self.target, &self.current_authority, &self.new_authority)
+    }
+
+    /// The transaction, to be exchanged with the other party.
+    pub fn transaction(&self) -> &OfflineTransaction {
+        &self.transaction
+    }
+
+    pub fn current_authority_signed(&self) -> bool {
+        self.has_signed(&self.current_authority)
+    }
+
+    pub fn new_authority_signed(&self) -> bool {
+        self.has_signed(&self.new_authority)
+    }
+
+    /// Required signers that have not signed yet, including the fee payer
+    /// and nonce authority.
+    pub fn missing_signers(&self) -> Vec<Address> {
+        self.transaction.missing_signers()
+    }
+
+    pub fn sign(&mut self, signer: &impl Signer) -> Result<(), RotationError> {
+        Ok(self.transaction.sign(signer)?)
+    }
+
+    pub fn add_signature(
+        &mut self,
+        signer: Address,
+        signature: Signature,
+    ) -> Result<(), RotationError> {
+        Ok(self.transaction.add_signature(signer, signature)?)
+    }
+
+    /// Adds the signatures collected in another copy of the transaction,
+    /// such as one read back with [`OfflineTransaction::from_json`].
+    pub fn merge(&mut self, other: &OfflineTransaction) -> Result<(), RotationError> {
+        Ok(self.transaction.merge(other)?)
+    }
+
+    /// Returns the signed transaction once every signature is collected.
+    pub fn into_transaction(self, nonce: &DurableNonce) -> Result<Transaction, RotationError> {
+        let instruction = self.instruction();
+        Ok(self.transaction.into_transaction(&instruction, nonce)?)
+    }
+
+    /// Unchecked `SetAuthority` for when the new authority cannot sign.
+    ///
+    /// Only Buffer authorities may be rotated this way.
+    pub fn unchecked_fallback(&self) -> Result<Instruction, RotationError> {
+        match self.target {
+            RotationTarget::Program(_) => Err(RotationError::UncheckedProgramData),
+            RotationTarget::Buffer(buffer) => Ok(set_authority(
+                &buffer,
+                &self.current_authority,
+                Some(&self.new_authority),
+            )),
+        }
+    }
+
+    /// Checks, from the target account's data after the transaction landed,
+    /// that its authority is now the new authority.
+    pub fn verify(&self, data: &[u8]) -> Result<(), RotationError> {
+        let actual = match self.target {
+            RotationTarget::Program(_) => ProgramData::unpack(data)?.upgrade_authority,
+            RotationTarget::Buffer(_) => Buffer::unpack(data)?.authority,
+        };
+        if actual == Some(self.new_authority) {
+            Ok(())
+        } else {
+            Err(RotationError::UnexpectedAuthority {
+                expected: self.new_authority,
+                actual,
+            })
+        }
+    }
+
+    fn has_signed(&self, authority: &Address) -> bool {
+        self.transaction
+            .signatures()
+            .any(|(signer, signature)| signer == authority && signature.is_some())
+    }
+}
+
+fn checked_instruction(
+    target: &RotationTarget,
+    current_authority: &Address,
+    new_authority: &Address,
+) -> Instruction {
+    SetAuthorityCheckedBuilder::new()
+        .buffer_or_program_data_account(target.account())
+        .current_authority(*current_authority)
+        .new_authority(*new_authority)
+        .instruction()
+}
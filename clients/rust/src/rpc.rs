@@ -0,0 +1,223 @@
+//! Access to a cluster holding loader accounts.
+//!
+//! [`LoaderRpc`] covers what deploy and upgrade flows need from a cluster:
+//! fetching accounts, the rent sysvar and a blockhash, and sending
+//! transactions. With the `rpc` feature it is implemented for
+//! `solana_rpc_client::rpc_client::RpcClient`, and with the `litesvm`
+//! feature for [`SvmRpc`](crate::svm::SvmRpc), which runs an in-process
+//! runtime instead.
+//!
+//! [`AsyncLoaderRpc`] is the non-blocking counterpart used by the
+//! [`executor`](crate::executor), which sends transactions without waiting
+//! and polls for their confirmation itself. With the `rpc` feature it is
+//! implemented for `solana_rpc_client::nonblocking::rpc_client::RpcClient`.
+
+use {
+    crate::state::{find_program_data_address, LoaderState, StateError},
+    solana_account::Account,
+    solana_address::Address,
+    solana_hash::Hash,
+    solana_rent::Rent,
+    solana_signature::Signature,
+    solana_transaction::Transaction,
+    std::future::Future,
+    thiserror::Error,
+};
+
+/// Errors returned by [`LoaderRpc`] implementations and helpers.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum RpcError {
+    #[error("RPC request failed: {0}")]
+    Request(String),
+    #[error("transaction failed: {0}")]
+    Transaction(String),
+    #[error("account {0} not found")]
+    AccountNotFound(Address),
+    #[error("account {0} is not owned by the loader-v3 program")]
+    NotOwnedByLoader(Address),
+    #[error(transparent)]
+    State(#[from] StateError),
+}
+
+/// A Program account together with its ProgramData account.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProgramAccounts {
+    pub program: Account,
+    pub program_data_address: Address,
+    pub program_data: Account,
+}
+
+/// Cluster operations used by loader flows.
+pub trait LoaderRpc {
+    /// Fetches an account, returning `None` if it does not exist.
+    fn get_account(&self, address: &Address) -> Result<Option<Account>, RpcError>;
+
+    /// Fetches several accounts at once, in the order of `addresses`.
+    fn get_multiple_accounts(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Option<Account>>, RpcError> {
+        addresses
+            .iter()
+            .map(|address| self.get_account(address))
+            .collect()
+    }
+
+    fn get_rent(&self) -> Result<Rent, RpcError>;
+
+    fn get_latest_blockhash(&self) -> Result<Hash, RpcError>;
+
+    /// Sends a signed transaction and waits for it to be confirmed.
+    fn send_and_confirm_transaction(
+        &mut self,
+        transaction: &Transaction,
+    ) -> Result<Signature, RpcError>;
+
+    /// Fetches an existing account owned by the loader.
+    fn get_loader_account(&self, address: &Address) -> Result<Account, RpcError> {
+        let account = self
+            .get_account(address)?
+            .ok_or(RpcError::AccountNotFound(*address))?;
+        if account.owner != crate::LOADER_V3_ID {
+            return Err(RpcError::NotOwnedByLoader(*address));
+        }
+        Ok(account)
+    }
+
+    /// Fetches a Program account and its ProgramData account in one request.
+    fn get_program(&self, program: &Address) -> Result<ProgramAccounts, RpcError> {
+        let program_data_address = find_program_data_address(program);
+        let [program_account, program_data] = self
+            .get_multiple_accounts(&[*program, program_data_address])?
+            .try_into()
+            .map_err(|_| RpcError::Request("unexpected number of accounts".to_string()))?;
+        let loader_account = |account: Option<Account>, address: &Address| {
+            let account = account.ok_or(RpcError::AccountNotFound(*address))?;
+            if account.owner != crate::LOADER_V3_ID {
+                return Err(RpcError::NotOwnedByLoader(*address));
+            }
+            Ok(account)
+        };
+        let program_account = loader_account(program_account, program)?;
+        match LoaderState::unpack(&program_account.data)? {
+            LoaderState::Program {
+                program_data: address,
+            } if address == program_data_address => {}
+            _ => {
+                return Err(StateError::UnexpectedState {
+                    expected: "Program",
+                }
+                .into())
+            }
+        }
+        Ok(ProgramAccounts {
+            program: program_account,
+            program_data_address,
+            program_data: loader_account(program_data, &program_data_address)?,
+        })
+    }
+}
+
+/// Non-blocking cluster operations used by the
+/// [`executor`](crate::executor).
+pub trait AsyncLoaderRpc {
+    /// Fetches an account, returning `None` if it does not exist.
+    fn get_account(
+        &self,
+        address: &Address,
+    ) -> impl Future<Output = Result<Option<Account>, RpcError>>;
+
+    /// Returns a recent blockhash and the last block height at which
+    /// transactions using it are accepted.
+    fn get_latest_blockhash(&self) -> impl Future<Output = Result<(Hash, u64), RpcError>>;
+
+    fn get_block_height(&self) -> impl Future<Output = Result<u64, RpcError>>;
+
+    /// Submits a signed transaction without waiting for it to land.
+    fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> impl Future<Output = Result<Signature, RpcError>>;
+
+    /// Returns `None` while the transaction is not confirmed, then whether
+    /// it succeeded.
+    fn get_signature_status(
+        &self,
+        signature: &Signature,
+    ) -> impl Future<Output = Result<Option<Result<(), String>>, RpcError>>;
+}
+
+#[cfg(feature = "rpc")]
+impl LoaderRpc for solana_rpc_client::rpc_client::RpcClient {
+    fn get_account(&self, address: &Address) -> Result<Option<Account>, RpcError> {
+        self.get_account_with_commitment(address, self.commitment())
+            .map(|response| response.value)
+            .map_err(|error| RpcError::Request(error.to_string()))
+    }
+
+    fn get_multiple_accounts(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Option<Account>>, RpcError> {
+        self.get_multiple_accounts_with_commitment(addresses, self.commitment())
+            .map(|response| response.value)
+            .map_err(|error| RpcError::Request(error.to_string()))
+    }
+
+    fn get_rent(&self) -> Result<Rent, RpcError> {
+        let account = LoaderRpc::get_account(self, &solana_rent::sysvar::ID)?
+            .ok_or(RpcError::AccountNotFound(solana_rent::sysvar::ID))?;
+        bincode::deserialize(&account.data).map_err(|error| RpcError::Request(error.to_string()))
+    }
+
+    fn get_latest_blockhash(&self) -> Result<Hash, RpcError> {
+        solana_rpc_client::rpc_client::RpcClient::get_latest_blockhash(self)
+            .map_err(|error| RpcError::Request(error.to_string()))
+    }
+
+    fn send_and_confirm_transaction(
+        &mut self,
+        transaction: &Transaction,
+    ) -> Result<Signature, RpcError> {
+        solana_rpc_client::rpc_client::RpcClient::send_and_confirm_transaction(self, transaction)
+            .map_err(|error| RpcError::Transaction(error.to_string()))
+    }
+}
+
+#[cfg(feature = "rpc")]
+impl AsyncLoaderRpc for solana_rpc_client::nonblocking::rpc_client::RpcClient {
+    async fn get_account(&self, address: &Address) -> Result<Option<Account>, RpcError> {
+        self.get_account_with_commitment(address, self.commitment())
+            .await
+            .map(|response| response.value)
+            .map_err(|error| RpcError::Request(error.to_string()))
+    }
+
+    async fn get_latest_blockhash(&self) -> Result<(Hash, u64), RpcError> {
+        self.get_latest_blockhash_with_commitment(self.commitment())
+            .await
+            .map_err(|error| RpcError::Request(error.to_string()))
+    }
+
+    async fn get_block_height(&self) -> Result<u64, RpcError> {
+        solana_rpc_client::nonblocking::rpc_client::RpcClient::get_block_height(self)
+            .await
+            .map_err(|error| RpcError::Request(error.to_string()))
+    }
+
+    async fn send_transaction(&self, transaction: &Transaction) -> Result<Signature, RpcError> {
+        solana_rpc_client::nonblocking::rpc_client::RpcClient::send_transaction(self, transaction)
+            .await
+            .map_err(|error| RpcError::Transaction(error.to_string()))
+    }
+
+    async fn get_signature_status(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<Result<(), String>>, RpcError> {
+        self.get_signature_status_with_commitment(signature, self.commitment())
+            .await
+            .map(|status| status.map(|result| result.map_err(|error| error.to_string())))
+            .map_err(|error| RpcError::Request(error.to_string()))
+    }
+}
@@ -0,0 +1,1026 @@
+//! In-memory model of the loader for dry-running transactions.
+//!
+//! A [`Simulator`] holds a set of accounts and executes messages against
+//! them without a VM, applying the account checks and state transitions of
+//! the loader's instruction processor in the loader's own order. A failing
+//! message reports the [`TransactionError`] the cluster would return, so a
+//! whole deploy or upgrade sequence can be rehearsed before paying for it:
+//!
+//! ```text
+//! InitializeBuffer, Write.., DeployWithMaxDataLen, ExtendProgram, Upgrade,
+//! SetAuthority, SetAuthorityChecked, Close
+//! ```
+//!
+//! Besides the loader, the model covers the system program's
+//! `CreateAccount`, `CreateAccountWithSeed` and `Transfer`, fees and the
+//! rent-state checks of the runtime. ELF verification is approximated by
+//! parsing the ELF, so programs the verifier would reject may pass.
+
+use {
+    crate::{
+        elf::Elf,
+        instruction::LoaderInstruction,
+        state::{
+            find_program_data_address, LoaderState, BUFFER_HEADER_SIZE, CLOCK_SYSVAR_ID,
+            MAX_PERMITTED_DATA_LENGTH, PROGRAM_ACCOUNT_SIZE, PROGRAM_DATA_HEADER_SIZE,
+            RENT_SYSVAR_ID,
+        },
+    },
+    solana_account::Account,
+    solana_address::Address,
+    solana_instruction::{error::InstructionError, AccountMeta, Instruction},
+    solana_message::Message,
+    solana_rent::Rent,
+    solana_system_interface::{error::SystemError, instruction::SystemInstruction},
+    solana_transaction::TransactionError,
+    std::collections::HashMap,
+    thiserror::Error,
+};
+
+const SYSTEM_PROGRAM_ID: Address = solana_system_interface::program::ID;
+/// Lamports sent here are burnt, so the runtime skips its rent-state check.
+const INCINERATOR_ID: Address =
+    Address::from_str_const("1nc1nerator11111111111111111111111111111111");
+
+/// The runtime still reports missing instruction accounts with this
+/// deprecated variant.
+#[allow(deprecated)]
+const NOT_ENOUGH_ACCOUNT_KEYS: InstructionError = InstructionError::NotEnoughAccountKeys;
+
+/// Errors returned when simulating a message.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum SimulationError {
+    /// The message failed as it would on the cluster. Fees are charged.
+    #[error("transaction failed: {0}")]
+    Transaction(#[from] TransactionError),
+    /// The message calls an instruction the model does not cover. Nothing
+    /// is charged.
+    #[error("instruction {index} of program {program} is not modeled")]
+    Unsupported { index: usize, program: Address },
+}
+
+/// Accounts and cluster state a [`Simulator`] executes messages against.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Simulator {
+    /// Accounts by address. Missing accounts are empty system accounts.
+    pub accounts: HashMap<Address, Account>,
+    /// Slot of the Clock sysvar.
+    pub slot: u64,
+    pub rent: Rent,
+    pub lamports_per_signature: u64,
+    /// Whether the cluster has activated `enable_extend_program_checked`,
+    /// which makes the loader reject `ExtendProgram`.
+    pub extend_program_checked: bool,
+}
+
+impl Simulator {
+    /// Empty simulator at slot 0 charging 5000 lamports per signature.
+    pub fn new(rent: Rent) -> Self {
+        Self {
+            accounts: HashMap::new(),
+            slot: 0,
+            rent,
+            lamports_per_signature: 5000,
+            extend_program_checked: false,
+        }
+    }
+
+    /// Executes `message` as one transaction.
+    ///
+    /// Account changes are kept only if every instruction succeeds, while
+    /// the fee is charged to the fee payer either way, as on the cluster.
+    /// Signatures and the blockhash are not checked.
+    pub fn process_message(&mut self, message: &Message) -> Result<(), SimulationError> {
+        for (index, instruction) in message.instructions.iter().enumerate() {
+            let program = message.account_keys[usize::from(instruction.program_id_index)];
+            if !is_modeled(&program, &instruction.data) {
+                return Err(SimulationError::Unsupported { index, program });
+            }
+        }
+
+        let keys = &message.account_keys;
+        let fee = self
+            .lamports_per_signature
+            .saturating_mul(u64::from(message.header.num_required_signatures));
+        let mut payer = self.account(&keys[0]);
+        if payer.lamports == 0 {
+            return Err(TransactionError::AccountNotFound.into());
+        }
+        if payer.owner != SYSTEM_PROGRAM_ID || !payer.data.is_empty() {
+            return Err(TransactionError::InvalidAccountForFee.into());
+        }
+        let pre_payer = payer.clone();
+        payer.lamports = payer
+            .lamports
+            .checked_sub(fee)
+            .ok_or(TransactionError::InsufficientFundsForFee)?;
+        self.check_rent_state(&pre_payer, &payer, 0)?;
+
+        let mut accounts: Vec<_> = keys.iter().map(|key| self.account(key)).collect();
+        accounts[0] = payer.clone();
+        let result = self.execute(message, &mut accounts);
+        match result {
+            Ok(()) => {
+                for (index, key) in keys.iter().enumerate() {
+                    if message.is_maybe_writable(index, None) {
+                        self.store(*key, accounts[index].clone());
+                    }
+                }
+                Ok(())
+            }
+            Err(error) => {
+                self.store(keys[0], payer);
+                Err(error.into())
+            }
+        }
+    }
+
+    fn account(&self, address: &Address) -> Account {
+        self.accounts.get(address).cloned().unwrap_or(Account {
+            lamports: 0,
+            data: Vec::new(),
+            owner: SYSTEM_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        })
+    }
+
+    /// Stores `account`, dropping it once it holds no lamports.
+    fn store(&mut self, address: Address, account: Account) {
+        if account.lamports == 0 {
+            self.accounts.remove(&address);
+        } else {
+            self.accounts.insert(address, account);
+        }
+    }
+
+    fn execute(&self, message: &Message, accounts: &mut [Account]) -> Result<(), TransactionError> {
+        let pre = accounts.to_vec();
+        for (index, instruction) in message.instructions.iter().enumerate() {
+            let mut context = Context {
+                simulator: self,
+                program_id: message.account_keys[usize::from(instruction.program_id_index)],
+                keys: &message.account_keys,
+                accounts,
+                instruction_accounts: instruction
+                    .accounts
+                    .iter()
+                    .map(|&index| {
+                        let index = usize::from(index);
+                        InstructionAccount {
+                            index,
+                            is_signer: message.is_signer(index),
+                            is_writable: message.is_maybe_writable(index, None),
+                        }
+                    })
+                    .collect(),
+                data: &instruction.data,
+            };
+            context.process().map_err(|error| {
+                TransactionError::InstructionError(u8::try_from(index).unwrap_or(u8::MAX), error)
+            })?;
+        }
+        for (index, key) in message.account_keys.iter().enumerate() {
+            if message.is_maybe_writable(index, None) && *key != INCINERATOR_ID {
+                self.check_rent_state(&pre[index], &accounts[index], index)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects a writable account left rent-paying, unless it already was
+    /// with the same size and no more lamports.
+    fn check_rent_state(
+        &self,
+        pre: &Account,
+        post: &Account,
+        index: usize,
+    ) -> Result<(), TransactionError> {
+        let is_rent_paying = |account: &Account| {
+            account.lamports > 0 && !self.rent.is_exempt(account.lamports, account.data.len())
+        };
+        let allowed = !is_rent_paying(post)
+            || (is_rent_paying(pre)
+                && pre.data.len() == post.data.len()
+                && post.lamports <= pre.lamports);
+        if allowed {
+            Ok(())
+        } else {
+            Err(TransactionError::InsufficientFundsForRent {
+                account_index: u8::try_from(index).unwrap_or(u8::MAX),
+            })
+        }
+    }
+}
+
+/// Whether the model covers an instruction of `program` with `data`.
+///
+/// Loader data the client cannot decode is covered, since the loader
+/// rejects it too, except for the instructions the client predates.
+fn is_modeled(program: &Address, data: &[u8]) -> bool {
+    if *program == crate::LOADER_V3_ID {
+        return LoaderInstruction::unpack(data).is_some()
+            || !matches!(
+                data.first_chunk().map(|bytes| u32::from_le_bytes(*bytes)),
+                Some(8 | 9)
+            );
+    }
+    *program == SYSTEM_PROGRAM_ID
+        && !matches!(
+            bincode::deserialize(data),
+            Ok(SystemInstruction::Assign { .. }
+                | SystemInstruction::TransferWithSeed { .. }
+                | SystemInstruction::AdvanceNonceAccount
+                | SystemInstruction::WithdrawNonceAccount(_)
+                | SystemInstruction::InitializeNonceAccount(_)
+                | SystemInstruction::AuthorizeNonceAccount(_)
+                | SystemInstruction::Allocate { .. }
+                | SystemInstruction::AllocateWithSeed { .. }
+                | SystemInstruction::AssignWithSeed { .. }
+                | SystemInstruction::UpgradeNonceAccount)
+        )
+}
+
+#[derive(Clone, Copy, Debug)]
+struct InstructionAccount {
+    /// Index of the account in the message.
+    index: usize,
+    is_signer: bool,
+    is_writable: bool,
+}
+
+/// An instruction being executed, with the accessors of the runtime's
+/// borrowed accounts.
+struct Context<'a> {
+    simulator: &'a Simulator,
+    program_id: Address,
+    keys: &'a [Address],
+    accounts: &'a mut [Account],
+    instruction_accounts: Vec<InstructionAccount>,
+    data: &'a [u8],
+}
+
+impl Context<'_> {
+    fn process(&mut self) -> Result<(), InstructionError> {
+        if self.program_id == SYSTEM_PROGRAM_ID {
+            self.process_system()
+        } else {
+            self.process_loader()
+        }
+    }
+
+    fn check_accounts(&self, count: usize) -> Result<(), InstructionError> {
+        if self.instruction_accounts.len() < count {
+            Err(NOT_ENOUGH_ACCOUNT_KEYS)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn instruction_account(&self, index: usize) -> Result<InstructionAccount, InstructionError> {
+        self.instruction_accounts
+            .get(index)
+            .copied()
+            .ok_or(NOT_ENOUGH_ACCOUNT_KEYS)
+    }
+
+    fn key(&self, index: usize) -> Result<Address, InstructionError> {
+        Ok(self.keys[self.instruction_account(index)?.index])
+    }
+
+    fn is_signer(&self, index: usize) -> Result<bool, InstructionError> {
+        self.instruction_accounts
+            .get(index)
+            .map(|account| account.is_signer)
+            .ok_or(InstructionError::MissingAccount)
+    }
+
+    fn is_writable(&self, index: usize) -> Result<bool, InstructionError> {
+        Ok(self.instruction_account(index)?.is_writable)
+    }
+
+    fn account(&self, index: usize) -> Result<&Account, InstructionError> {
+        Ok(&self.accounts[self.instruction_account(index)?.index])
+    }
+
+    /// Fails as the runtime does when borrowing one account twice.
+    fn check_distinct(&self, first: usize, second: usize) -> Result<(), InstructionError> {
+        if self.instruction_account(first)?.index == self.instruction_account(second)?.index {
+            Err(InstructionError::AccountBorrowFailed)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn state(&self, index: usize) -> Result<LoaderState, InstructionError> {
+        LoaderState::unpack(&self.account(index)?.data)
+            .map_err(|_| InstructionError::InvalidAccountData)
+    }
+
+    fn is_owned(&self, index: usize) -> Result<bool, InstructionError> {
+        Ok(self.account(index)?.owner == self.program_id)
+    }
+
+    fn set_lamports(&mut self, index: usize, lamports: u64) -> Result<(), InstructionError> {
+        if !self.is_owned(index)? && lamports < self.account(index)?.lamports {
+            return Err(InstructionError::ExternalAccountLamportSpend);
+        }
+        if !self.is_writable(index)? {
+            return Err(InstructionError::ReadonlyLamportChange);
+        }
+        let account = self.instruction_account(index)?.index;
+        self.accounts[account].lamports = lamports;
+        Ok(())
+    }
+
+    fn add_lamports(&mut self, index: usize, lamports: u64) -> Result<(), InstructionError> {
+        let balance = self.account(index)?.lamports;
+        self.set_lamports(
+            index,
+            balance
+                .checked_add(lamports)
+                .ok_or(InstructionError::ArithmeticOverflow)?,
+        )
+    }
+
+    fn sub_lamports(&mut self, index: usize, lamports: u64) -> Result<(), InstructionError> {
+        let balance = self.account(index)?.lamports;
+        self.set_lamports(
+            index,
+            balance
+                .checked_sub(lamports)
+                .ok_or(InstructionError::ArithmeticOverflow)?,
+        )
+    }
+
+    fn data_mut(&mut self, index: usize) -> Result<&mut Vec<u8>, InstructionError> {
+        if !self.is_writable(index)? {
+            return Err(InstructionError::ReadonlyDataModified);
+        }
+        if !self.is_owned(index)? {
+            return Err(InstructionError::ExternalAccountDataModified);
+        }
+        let account = self.instruction_account(index)?.index;
+        Ok(&mut self.accounts[account].data)
+    }
+
+    fn set_data_length(&mut self, index: usize, len: usize) -> Result<(), InstructionError> {
+        if len != self.account(index)?.data.len() && !self.is_owned(index)? {
+            return Err(InstructionError::AccountDataSizeChanged);
+        }
+        if len > MAX_PERMITTED_DATA_LENGTH {
+            return Err(InstructionError::InvalidRealloc);
+        }
+        self.data_mut(index)?.resize(len, 0);
+        Ok(())
+    }
+
+    /// Writes the bincode encoding of `state`, which leaves the bytes of an
+    /// unset authority untouched.
+    fn set_state(&mut self, index: usize, state: LoaderState) -> Result<(), InstructionError> {
+        let data = self.data_mut(index)?;
+        let len = encoded_len(&state);
+        let bytes = state.to_bytes();
+        data.get_mut(..len)
+            .ok_or(InstructionError::AccountDataTooSmall)?
+            .copy_from_slice(&bytes[..len]);
+        Ok(())
+    }
+
+    fn set_owner(&mut self, index: usize, owner: Address) -> Result<(), InstructionError> {
+        let account = self.account(index)?;
+        if !self.is_owned(index)?
+            || !self.is_writable(index)?
+            || account.data.iter().any(|byte| *byte != 0)
+        {
+            return Err(InstructionError::ModifiedProgramId);
+        }
+        let account = self.instruction_account(index)?.index;
+        self.accounts[account].owner = owner;
+        Ok(())
+    }
+
+    fn set_executable(&mut self, index: usize) -> Result<(), InstructionError> {
+        let account = self.account(index)?;
+        if !self
+            .simulator
+            .rent
+            .is_exempt(account.lamports, account.data.len())
+        {
+            return Err(InstructionError::ExecutableAccountNotRentExempt);
+        }
+        if !self.is_owned(index)? || !self.is_writable(index)? {
+            return Err(InstructionError::ExecutableModified);
+        }
+        let account = self.instruction_account(index)?.index;
+        self.accounts[account].executable = true;
+        Ok(())
+    }
+
+    /// Checks that instruction account `index` is the sysvar `id`.
+    fn check_sysvar(&self, index: usize, id: &Address) -> Result<(), InstructionError> {
+        if self.key(index)? != *id {
+            return Err(InstructionError::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    /// Invokes a system program instruction, with `signers` signed for by
+    /// the loader.
+    fn invoke(
+        &mut self,
+        instruction: Instruction,
+        signers: &[Address],
+    ) -> Result<(), InstructionError> {
+        let mut instruction_accounts: Vec<InstructionAccount> = Vec::new();
+        for meta in &instruction.accounts {
+            let index = self
+                .keys
+                .iter()
+                .position(|key| *key == meta.pubkey)
+                .ok_or(InstructionError::MissingAccount)?;
+            let caller = self
+                .instruction_accounts
+                .iter()
+                .find(|account| account.index == index)
+                .copied()
+                .ok_or(InstructionError::MissingAccount)?;
+            if meta.is_writable && !caller.is_writable {
+                return Err(InstructionError::PrivilegeEscalation);
+            }
+            if meta.is_signer && !(caller.is_signer || signers.contains(&meta.pubkey)) {
+                return Err(InstructionError::PrivilegeEscalation);
+            }
+            instruction_accounts.push(InstructionAccount {
+                index,
+                is_signer: meta.is_signer,
+                is_writable: meta.is_writable,
+            });
+        }
+        if !self
+            .instruction_accounts
+            .iter()
+            .any(|account| self.keys[account.index] == instruction.program_id)
+        {
+            return Err(InstructionError::MissingAccount);
+        }
+        Context {
+            simulator: self.simulator,
+            program_id: instruction.program_id,
+            keys: self.keys,
+            accounts: self.accounts,
+            instruction_accounts,
+            data: &instruction.data,
+        }
+        .process()
+    }
+
+    fn verify_program(&self, bytes: &[u8]) -> Result<(), InstructionError> {
+        Elf::parse(bytes)
+            .map(|_| ())
+            .map_err(|_| InstructionError::InvalidAccountData)
+    }
+
+    fn process_loader(&mut self) -> Result<(), InstructionError> {
+        let instruction =
+            LoaderInstruction::unpack(self.data).ok_or(InstructionError::InvalidInstructionData)?;
+        match instruction {
+            LoaderInstruction::InitializeBuffer => {
+                self.check_accounts(2)?;
+                if self.state(0)? != LoaderState::Uninitialized {
+                    return Err(InstructionError::AccountAlreadyInitialized);
+                }
+                let authority = self.key(1)?;
+                self.set_state(
+                    0,
+                    LoaderState::Buffer {
+                        authority: Some(authority),
+                    },
+                )
+            }
+            LoaderInstruction::Write(args) => {
+                self.check_accounts(2)?;
+                match self.state(0)? {
+                    LoaderState::Buffer { authority: None } => {
+                        return Err(InstructionError::Immutable)
+                    }
+                    LoaderState::Buffer { authority } => {
+                        if authority != Some(self.key(1)?) {
+                            return Err(InstructionError::IncorrectAuthority);
+                        }
+                        if !self.is_signer(1)? {
+                            return Err(InstructionError::MissingRequiredSignature);
+                        }
+                    }
+                    _ => return Err(InstructionError::InvalidAccountData),
+                }
+                let start = BUFFER_HEADER_SIZE.saturating_add(args.offset as usize);
+                let end = start.saturating_add(args.bytes.len());
+                self.data_mut(0)?
+                    .get_mut(start..end)
+                    .ok_or(InstructionError::AccountDataTooSmall)?
+                    .copy_from_slice(&args.bytes);
+                Ok(())
+            }
+            LoaderInstruction::DeployWithMaxDataLen(args) => self.deploy(args.max_data_len),
+            LoaderInstruction::Upgrade => self.upgrade(),
+            LoaderInstruction::SetAuthority => {
+                self.check_accounts(2)?;
+                let new_authority = self.key(2).ok();
+                self.set_authority(new_authority, false)
+            }
+            LoaderInstruction::SetAuthorityChecked => {
+                self.check_accounts(3)?;
+                let new_authority = self.key(2)?;
+                self.set_authority(Some(new_authority), true)
+            }
+            LoaderInstruction::Close => self.close(),
+            LoaderInstruction::ExtendProgram(args) => {
+                if self.simulator.extend_program_checked {
+                    return Err(InstructionError::InvalidInstructionData);
+                }
+                self.extend(args.additional_bytes)
+            }
+        }
+    }
+
+    fn deploy(&mut self, max_data_len: u64) -> Result<(), InstructionError> {
+        self.check_accounts(4)?;
+        let payer = self.key(0)?;
+        let program_data = self.key(1)?;
+        self.check_sysvar(4, &RENT_SYSVAR_ID)?;
+        self.check_sysvar(5, &CLOCK_SYSVAR_ID)?;
+        self.check_accounts(8)?;
+        let authority = self.key(7)?;
+        let rent = &self.simulator.rent;
+        let slot = self.simulator.slot;
+
+        if self.state(2)? != LoaderState::Uninitialized {
+            return Err(InstructionError::AccountAlreadyInitialized);
+        }
+        let program = self.account(2)?;
+        if program.data.len() < PROGRAM_ACCOUNT_SIZE {
+            return Err(InstructionError::AccountDataTooSmall);
+        }
+        if program.lamports < rent.minimum_balance(program.data.len()) {
+            return Err(InstructionError::ExecutableAccountNotRentExempt);
+        }
+        let program = self.key(2)?;
+
+        match self.state(3)? {
+            LoaderState::Buffer {
+                authority: buffer_authority,
+            } => {
+                if buffer_authority != Some(authority) {
+                    return Err(InstructionError::IncorrectAuthority);
+                }
+                if !self.is_signer(7)? {
+                    return Err(InstructionError::MissingRequiredSignature);
+                }
+            }
+            _ => return Err(InstructionError::InvalidArgument),
+        }
+        let buffer = self.account(3)?;
+        let buffer_data_len = buffer.data.len().saturating_sub(BUFFER_HEADER_SIZE);
+        if buffer_data_len == 0 {
+            return Err(InstructionError::InvalidAccountData);
+        }
+        if max_data_len < buffer_data_len as u64 {
+            return Err(InstructionError::AccountDataTooSmall);
+        }
+        let program_data_len = PROGRAM_DATA_HEADER_SIZE.saturating_add(max_data_len as usize);
+        if program_data_len > MAX_PERMITTED_DATA_LENGTH {
+            return Err(InstructionError::InvalidArgument);
+        }
+        if find_program_data_address(&program) != program_data {
+            return Err(InstructionError::InvalidArgument);
+        }
+
+        // The buffer pays for the ProgramData account through the payer.
+        self.check_distinct(3, 0)?;
+        let buffer_lamports = buffer.lamports;
+        self.add_lamports(0, buffer_lamports)?;
+        self.set_lamports(3, 0)?;
+        let mut create = solana_system_interface::instruction::create_account(
+            &payer,
+            &program_data,
+            rent.minimum_balance(program_data_len).max(1),
+            program_data_len as u64,
+            &crate::LOADER_V3_ID,
+        );
+        create.accounts.push(AccountMeta::new(self.key(3)?, false));
+        self.invoke(create, &[program_data])?;
+
+        let elf = self.account(3)?.data[BUFFER_HEADER_SIZE..].to_vec();
+        self.verify_program(&elf)?;
+        self.set_state(
+            1,
+            LoaderState::ProgramData {
+                slot,
+                upgrade_authority: Some(authority),
+            },
+        )?;
+        self.check_distinct(1, 3)?;
+        self.data_mut(1)?[PROGRAM_DATA_HEADER_SIZE..]
+            .get_mut(..elf.len())
+            .ok_or(InstructionError::AccountDataTooSmall)?
+            .copy_from_slice(&elf);
+        self.set_data_length(3, BUFFER_HEADER_SIZE)?;
+
+        self.set_state(2, LoaderState::Program { program_data })?;
+        self.set_executable(2)
+    }
+
+    fn upgrade(&mut self) -> Result<(), InstructionError> {
+        self.check_accounts(3)?;
+        let program_data = self.key(0)?;
+        self.check_sysvar(4, &RENT_SYSVAR_ID)?;
+        self.check_sysvar(5, &CLOCK_SYSVAR_ID)?;
+        self.check_accounts(7)?;
+        let authority = Some(self.key(6)?);
+
+        if !self.is_writable(1)? {
+            return Err(InstructionError::InvalidArgument);
+        }
+        if !self.is_owned(1)? {
+            return Err(InstructionError::IncorrectProgramId);
+        }
+        match self.state(1)? {
+            LoaderState::Program {
+                program_data: address,
+            } if address != program_data => return Err(InstructionError::InvalidArgument),
+            LoaderState::Program { .. } => {}
+            _ => return Err(InstructionError::InvalidAccountData),
+        }
+
+        match self.state(2)? {
+            LoaderState::Buffer {
+                authority: buffer_authority,
+            } => {
+                if buffer_authority != authority {
+                    return Err(InstructionError::IncorrectAuthority);
+                }
+                if !self.is_signer(6)? {
+                    return Err(InstructionError::MissingRequiredSignature);
+                }
+            }
+            _ => return Err(InstructionError::InvalidArgument),
+        }
+        let buffer = self.account(2)?;
+        let buffer_lamports = buffer.lamports;
+        let buffer_data_len = buffer.data.len().saturating_sub(BUFFER_HEADER_SIZE);
+        if buffer_data_len == 0 {
+            return Err(InstructionError::InvalidAccountData);
+        }
+
+        let account = self.account(0)?;
+        let balance_required = self
+            .simulator
+            .rent
+            .minimum_balance(account.data.len())
+            .max(1);
+        if account.data.len() < PROGRAM_DATA_HEADER_SIZE.saturating_add(buffer_data_len) {
+            return Err(InstructionError::AccountDataTooSmall);
+        }
+        let program_data_lamports = account.lamports;
+        if program_data_lamports.saturating_add(buffer_lamports) < balance_required {
+            return Err(InstructionError::InsufficientFunds);
+        }
+        match self.state(0)? {
+            LoaderState::ProgramData {
+                slot,
+                upgrade_authority,
+            } => {
+                if slot == self.simulator.slot {
+                    return Err(InstructionError::InvalidArgument);
+                }
+                if upgrade_authority.is_none() {
+                    return Err(InstructionError::Immutable);
+                }
+                if upgrade_authority != authority {
+                    return Err(InstructionError::IncorrectAuthority);
+                }
+                if !self.is_signer(6)? {
+                    return Err(InstructionError::MissingRequiredSignature);
+                }
+            }
+            _ => return Err(InstructionError::InvalidAccountData),
+        }
+
+        let elf = self.account(2)?.data[BUFFER_HEADER_SIZE..].to_vec();
+        self.verify_program(&elf)?;
+        self.set_state(
+            0,
+            LoaderState::ProgramData {
+                slot: self.simulator.slot,
+                upgrade_authority: authority,
+            },
+        )?;
+        let data = &mut self.data_mut(0)?[PROGRAM_DATA_HEADER_SIZE..];
+        data[..elf.len()].copy_from_slice(&elf);
+        data[elf.len()..].fill(0);
+
+        // The buffer funds the ProgramData account, the rest is spilled.
+        self.check_distinct(3, 0)?;
+        self.check_distinct(3, 2)?;
+        self.add_lamports(
+            3,
+            program_data_lamports
+                .saturating_add(buffer_lamports)
+                .saturating_sub(balance_required),
+        )?;
+        self.set_lamports(2, 0)?;
+        self.set_lamports(0, balance_required)?;
+        self.set_data_length(2, BUFFER_HEADER_SIZE)
+    }
+
+    fn set_authority(
+        &mut self,
+        new_authority: Option<Address>,
+        checked: bool,
+    ) -> Result<(), InstructionError> {
+        let current = Some(self.key(1)?);
+        let state = match self.state(0)? {
+            LoaderState::Buffer { authority } => {
+                if !checked && new_authority.is_none() {
+                    return Err(InstructionError::IncorrectAuthority);
+                }
+                if authority.is_none() {
+                    return Err(InstructionError::Immutable);
+                }
+                if authority != current {
+                    return Err(InstructionError::IncorrectAuthority);
+                }
+                LoaderState::Buffer {
+                    authority: new_authority,
+                }
+            }
+            LoaderState::ProgramData {
+                slot,
+                upgrade_authority,
+            } => {
+                if upgrade_authority.is_none() {
+                    return Err(InstructionError::Immutable);
+                }
+                if upgrade_authority != current {
+                    return Err(InstructionError::IncorrectAuthority);
+                }
+                LoaderState::ProgramData {
+                    slot,
+                    upgrade_authority: new_authority,
+                }
+            }
+            _ => return Err(InstructionError::InvalidArgument),
+        };
+        if !self.is_signer(1)? || (checked && !self.is_signer(2)?) {
+            return Err(InstructionError::MissingRequiredSignature);
+        }
+        self.set_state(0, state)
+    }
+
+    fn close(&mut self) -> Result<(), InstructionError> {
+        self.check_accounts(2)?;
+        if self.instruction_account(0)?.index == self.instruction_account(1)?.index {
+            return Err(InstructionError::InvalidArgument);
+        }
+        let state = self.state(0)?;
+        self.set_data_length(0, encoded_len(&LoaderState::Uninitialized))?;
+        match state {
+            LoaderState::Uninitialized => {
+                let lamports = self.account(0)?.lamports;
+                self.add_lamports(1, lamports)?;
+                self.set_lamports(0, 0)
+            }
+            LoaderState::Buffer { authority } => {
+                self.check_accounts(3)?;
+                self.close_account(authority)
+            }
+            LoaderState::ProgramData {
+                slot,
+                upgrade_authority,
+            } => {
+                self.check_accounts(4)?;
+                let program = self.account(3)?;
+                if !self.is_writable(3)? {
+                    return Err(InstructionError::InvalidArgument);
+                }
+                if program.owner != self.program_id {
+                    return Err(InstructionError::IncorrectProgramId);
+                }
+                if slot == self.simulator.slot {
+                    return Err(InstructionError::InvalidArgument);
+                }
+                match self.state(3)? {
+                    LoaderState::Program { program_data } if program_data == self.key(0)? => {
+                        self.close_account(upgrade_authority)
+                    }
+                    _ => Err(InstructionError::InvalidArgument),
+                }
+            }
+            LoaderState::Program { .. } => Err(InstructionError::InvalidArgument),
+        }
+    }
+
+    /// Moves the lamports of a Buffer or ProgramData account to the
+    /// recipient once its authority signed.
+    fn close_account(&mut self, authority: Option<Address>) -> Result<(), InstructionError> {
+        if authority.is_none() {
+            return Err(InstructionError::Immutable);
+        }
+        if authority != Some(self.key(2)?) {
+            return Err(InstructionError::IncorrectAuthority);
+        }
+        if !self.is_signer(2)? {
+            return Err(InstructionError::MissingRequiredSignature);
+        }
+        let lamports = self.account(0)?.lamports;
+        self.add_lamports(1, lamports)?;
+        self.set_lamports(0, 0)?;
+        self.set_state(0, LoaderState::Uninitialized)
+    }
+
+    fn extend(&mut self, additional_bytes: u32) -> Result<(), InstructionError> {
+        if additional_bytes == 0 {
+            return Err(InstructionError::InvalidInstructionData);
+        }
+        let program_data = self.key(0)?;
+        if !self.is_owned(0)? {
+            return Err(InstructionError::InvalidAccountOwner);
+        }
+        if !self.is_writable(0)? {
+            return Err(InstructionError::InvalidArgument);
+        }
+        self.check_distinct(1, 0)?;
+        if !self.is_writable(1)? {
+            return Err(InstructionError::InvalidArgument);
+        }
+        if !self.is_owned(1)? {
+            return Err(InstructionError::InvalidAccountOwner);
+        }
+        match self.state(1)? {
+            LoaderState::Program {
+                program_data: address,
+            } if address != program_data => return Err(InstructionError::InvalidArgument),
+            LoaderState::Program { .. } => {}
+            _ => return Err(InstructionError::InvalidAccountData),
+        }
+
+        let account = self.account(0)?;
+        let new_len = account.data.len().saturating_add(additional_bytes as usize);
+        if new_len > MAX_PERMITTED_DATA_LENGTH {
+            return Err(InstructionError::InvalidRealloc);
+        }
+        let upgrade_authority = match self.state(0)? {
+            LoaderState::ProgramData {
+                slot,
+                upgrade_authority,
+            } => {
+                if slot == self.simulator.slot {
+                    return Err(InstructionError::InvalidArgument);
+                }
+                if upgrade_authority.is_none() {
+                    return Err(InstructionError::Immutable);
+                }
+                upgrade_authority
+            }
+            _ => return Err(InstructionError::InvalidAccountData),
+        };
+
+        let required_payment = self
+            .simulator
+            .rent
+            .minimum_balance(new_len)
+            .max(1)
+            .saturating_sub(account.lamports);
+        if required_payment > 0 {
+            let payer = self.key(3)?;
+            self.invoke(
+                solana_system_interface::instruction::transfer(
+                    &payer,
+                    &program_data,
+                    required_payment,
+                ),
+                &[],
+            )?;
+        }
+        self.set_data_length(0, new_len)?;
+        self.verify_program(&self.account(0)?.data[PROGRAM_DATA_HEADER_SIZE..])?;
+        self.set_state(
+            0,
+            LoaderState::ProgramData {
+                slot: self.simulator.slot,
+                upgrade_authority,
+            },
+        )
+    }
+
+    fn process_system(&mut self) -> Result<(), InstructionError> {
+        let instruction: SystemInstruction = bincode::deserialize(self.data)
+            .map_err(|_| InstructionError::InvalidInstructionData)?;
+        match instruction {
+            SystemInstruction::CreateAccount {
+                lamports,
+                space,
+                owner,
+            } => {
+                self.check_accounts(2)?;
+                let to = self.key(1)?;
+                self.create_account(to, lamports, space, owner)
+            }
+            SystemInstruction::CreateAccountWithSeed {
+                base,
+                seed,
+                lamports,
+                space,
+                owner,
+            } => {
+                self.check_accounts(2)?;
+                let address = Address::create_with_seed(&base, &seed, &owner)
+                    .map_err(|error| InstructionError::Custom(error as u32))?;
+                if address != self.key(1)? {
+                    return Err(InstructionError::Custom(
+                        SystemError::AddressWithSeedMismatch as u32,
+                    ));
+                }
+                self.create_account(base, lamports, space, owner)
+            }
+            SystemInstruction::Transfer { lamports } => {
+                self.check_accounts(2)?;
+                self.transfer(lamports)
+            }
+            _ => Err(InstructionError::InvalidInstructionData),
+        }
+    }
+
+    /// Creates account 1, which `signer` must sign for, funded by account 0.
+    fn create_account(
+        &mut self,
+        signer: Address,
+        lamports: u64,
+        space: u64,
+        owner: Address,
+    ) -> Result<(), InstructionError> {
+        let in_use = InstructionError::Custom(SystemError::AccountAlreadyInUse as u32);
+        if self.account(1)?.lamports > 0 {
+            return Err(in_use);
+        }
+        let is_signed = self
+            .instruction_accounts
+            .iter()
+            .any(|account| account.is_signer && self.keys[account.index] == signer);
+        if !is_signed {
+            return Err(InstructionError::MissingRequiredSignature);
+        }
+        let to = self.account(1)?;
+        if !to.data.is_empty() || to.owner != SYSTEM_PROGRAM_ID {
+            return Err(in_use);
+        }
+        if space > MAX_PERMITTED_DATA_LENGTH as u64 {
+            return Err(InstructionError::Custom(
+                SystemError::InvalidAccountDataLength as u32,
+            ));
+        }
+        self.set_data_length(1, space as usize)?;
+        if owner != SYSTEM_PROGRAM_ID {
+            self.set_owner(1, owner)?;
+        }
+        self.transfer(lamports)
+    }
+
+    /// Transfers `lamports` from account 0 to account 1.
+    fn transfer(&mut self, lamports: u64) -> Result<(), InstructionError> {
+        if !self.is_signer(0)? {
+            return Err(InstructionError::MissingRequiredSignature);
+        }
+        let from = self.account(0)?;
+        if !from.data.is_empty() {
+            return Err(InstructionError::InvalidArgument);
+        }
+        if lamports > from.lamports {
+            return Err(InstructionError::Custom(
+                SystemError::ResultWithNegativeLamports as u32,
+            ));
+        }
+        self.sub_lamports(0, lamports)?;
+        self.add_lamports(1, lamports)
+    }
+}
+
+/// Length of the bincode encoding of `state`, which omits unset
+/// authorities.
+fn encoded_len(state: &LoaderState) -> usize {
+    match state {
+        LoaderState::Uninitialized => 4,
+        LoaderState::Buffer { authority: None } => 5,
+        LoaderState::Buffer { authority: Some(_) } => BUFFER_HEADER_SIZE,
+        LoaderState::Program { .. } => PROGRAM_ACCOUNT_SIZE,
+        LoaderState::ProgramData {
+            upgrade_authority: None,
+            ..
+        } => 13,
+        LoaderState::ProgramData {
+            upgrade_authority: Some(_),
+            ..
+        } => PROGRAM_DATA_HEADER_SIZE,
+    }
+}
@@ -0,0 +1,206 @@
+//! Account layouts of the loader-v3 program.
+//!
+//! The loader stores its accounts as a bincode-encoded
+//! `UpgradeableLoaderState` enum, followed (for Buffer and ProgramData
+//! accounts) by the raw program bytes.
+
+use {solana_address::Address, thiserror::Error};
+
+/// Size of the Buffer account header: enum tag (4) + optional authority
+/// (1 + 32).
+pub const BUFFER_HEADER_SIZE: usize = 37;
+
+/// Size of a Program account: enum tag (4) + ProgramData address (32).
+pub const PROGRAM_ACCOUNT_SIZE: usize = 36;
+
+/// Size of the ProgramData account header: enum tag (4) + deploy slot (8) +
+/// optional upgrade authority (1 + 32).
+pub const PROGRAM_DATA_HEADER_SIZE: usize = 45;
+
+/// Maximum size of any account's data permitted by the runtime (10 MiB).
+pub const MAX_PERMITTED_DATA_LENGTH: usize = 10 * 1024 * 1024;
+
+/// Maximum number of program bytes a ProgramData account can hold once its
+/// header is accounted for.
+pub const MAX_PROGRAM_DATA_LEN: usize = MAX_PERMITTED_DATA_LENGTH - PROGRAM_DATA_HEADER_SIZE;
+
+/// Address of the Rent sysvar, which `DeployWithMaxDataLen` and `Upgrade`
+/// take as an account.
+pub const RENT_SYSVAR_ID: Address =
+    Address::from_str_const("SysvarRent111111111111111111111111111111111");
+
+/// Address of the Clock sysvar, which `DeployWithMaxDataLen` and `Upgrade`
+/// take as an account.
+pub const CLOCK_SYSVAR_ID: Address =
+    Address::from_str_const("SysvarC1ock11111111111111111111111111111111");
+
+/// Total size of a Buffer account holding `data_len` program bytes.
+pub fn buffer_account_size(data_len: usize) -> Option<usize> {
+    BUFFER_HEADER_SIZE.checked_add(data_len)
+}
+
+/// Total size of a ProgramData account holding up to `max_data_len` program
+/// bytes.
+pub fn program_data_account_size(max_data_len: usize) -> Option<usize> {
+    PROGRAM_DATA_HEADER_SIZE.checked_add(max_data_len)
+}
+
+/// Errors returned when decoding loader-v3 accounts.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum StateError {
+    #[error("account data is too short")]
+    TooShort,
+    #[error("unknown account state tag {0}")]
+    UnknownTag(u32),
+    #[error("invalid option tag {0}")]
+    InvalidOption(u8),
+    #[error("expected a {expected} account")]
+    UnexpectedState { expected: &'static str },
+}
+
+/// Decoded header of a loader-v3 account.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LoaderState {
+    /// Account not initialized yet.
+    Uninitialized,
+    /// A Buffer account, followed by the program bytes written so far.
+    Buffer { authority: Option<Address> },
+    /// A Program account pointing at its ProgramData account.
+    Program { program_data: Address },
+    /// A ProgramData account, followed by the deployed program bytes.
+    ProgramData {
+        slot: u64,
+        upgrade_authority: Option<Address>,
+    },
+}
+
+impl LoaderState {
+    /// Decodes the header at the start of a loader-v3 account's data.
+    pub fn unpack(data: &[u8]) -> Result<Self, StateError> {
+        let tag = u32::from_le_bytes(read_array(data, 0)?);
+        match tag {
+            0 => Ok(Self::Uninitialized),
+            1 => Ok(Self::Buffer {
+                authority: read_optional_address(data, 4)?,
+            }),
+            2 => Ok(Self::Program {
+                program_data: Address::new_from_array(read_array(data, 4)?),
+            }),
+            3 => Ok(Self::ProgramData {
+                slot: u64::from_le_bytes(read_array(data, 4)?),
+                upgrade_authority: read_optional_address(data, 12)?,
+            }),
+            tag => Err(StateError::UnknownTag(tag)),
+        }
+    }
+
+    /// Encodes the header, padded to the fixed header size of its kind.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(PROGRAM_DATA_HEADER_SIZE);
+        match self {
+            Self::Uninitialized => bytes.extend_from_slice(&0u32.to_le_bytes()),
+            Self::Buffer { authority } => {
+                bytes.extend_from_slice(&1u32.to_le_bytes());
+                write_optional_address(&mut bytes, authority);
+            }
+            Self::Program { program_data } => {
+                bytes.extend_from_slice(&2u32.to_le_bytes());
+                bytes.extend_from_slice(program_data.as_ref());
+            }
+            Self::ProgramData {
+                slot,
+                upgrade_authority,
+            } => {
+                bytes.extend_from_slice(&3u32.to_le_bytes());
+                bytes.extend_from_slice(&slot.to_le_bytes());
+                write_optional_address(&mut bytes, upgrade_authority);
+            }
+        }
+        bytes
+    }
+}
+
+/// A decoded Buffer account.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Buffer<'a> {
+    pub authority: Option<Address>,
+    /// Program bytes following the header.
+    pub data: &'a [u8],
+}
+
+impl<'a> Buffer<'a> {
+    pub fn unpack(data: &'a [u8]) -> Result<Self, StateError> {
+        match LoaderState::unpack(data)? {
+            LoaderState::Buffer { authority } => Ok(Self {
+                authority,
+                data: data.get(BUFFER_HEADER_SIZE..).ok_or(StateError::TooShort)?,
+            }),
+            _ => Err(StateError::UnexpectedState { expected: "Buffer" }),
+        }
+    }
+}
+
+/// A decoded ProgramData account.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ProgramData<'a> {
+    /// Slot of the last deploy or upgrade.
+    pub slot: u64,
+    pub upgrade_authority: Option<Address>,
+    /// Program bytes following the header, including trailing padding up to
+    /// `max_data_len`.
+    pub data: &'a [u8],
+}
+
+impl<'a> ProgramData<'a> {
+    pub fn unpack(data: &'a [u8]) -> Result<Self, StateError> {
+        match LoaderState::unpack(data)? {
+            LoaderState::ProgramData {
+                slot,
+                upgrade_authority,
+            } => Ok(Self {
+                slot,
+                upgrade_authority,
+                data: data
+                    .get(PROGRAM_DATA_HEADER_SIZE..)
+                    .ok_or(StateError::TooShort)?,
+            }),
+            _ => Err(StateError::UnexpectedState {
+                expected: "ProgramData",
+            }),
+        }
+    }
+}
+
+/// Derives the ProgramData address of `program`.
+pub fn find_program_data_address(program: &Address) -> Address {
+    Address::find_program_address(&[program.as_ref()], &crate::LOADER_V3_ID).0
+}
+
+fn read_array<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N], StateError> {
+    data.get(offset..offset.checked_add(N).ok_or(StateError::TooShort)?)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(StateError::TooShort)
+}
+
+fn read_optional_address(data: &[u8], offset: usize) -> Result<Option<Address>, StateError> {
+    let [tag] = read_array(data, offset)?;
+    match tag {
+        0 => Ok(None),
+        1 => Ok(Some(Address::new_from_array(read_array(
+            data,
+            offset.checked_add(1).ok_or(StateError::TooShort)?,
+        )?))),
+        tag => Err(StateError::InvalidOption(tag)),
+    }
+}
+
+fn write_optional_address(bytes: &mut Vec<u8>, address: &Option<Address>) {
+    match address {
+        Some(address) => {
+            bytes.push(1);
+            bytes.extend_from_slice(address.as_ref());
+        }
+        // The loader reserves room for the address even when it is unset.
+        None => bytes.extend_from_slice(&[0; 33]),
+    }
+}
@@ -0,0 +1,139 @@
+//! [`LoaderRpc`] over an in-process LiteSVM instance, enabled by the
+//! `litesvm` feature.
+//!
+//! [`SvmRpc`] lets deploy and upgrade flows run against a local runtime,
+//! e.g. in tests, without a cluster. Besides the [`LoaderRpc`] methods, it
+//! signs and sends instructions and whole [`Plan`]s directly, returning
+//! LiteSVM's transaction metadata with the logs.
+
+use {
+    crate::{
+        plan::Plan,
+        rpc::{LoaderRpc, RpcError},
+    },
+    agave_feature_set::{enable_extend_program_checked, FeatureSet},
+    litesvm::{
+        types::{FailedTransactionMetadata, TransactionMetadata},
+        LiteSVM,
+    },
+    solana_account::Account,
+    solana_address::Address,
+    solana_clock::Clock,
+    solana_hash::Hash,
+    solana_instruction::Instruction,
+    solana_message::Message,
+    solana_rent::Rent,
+    solana_signature::Signature,
+    solana_signer::Signer,
+    solana_transaction::Transaction,
+};
+
+/// [`LoaderRpc`] executing transactions in an in-process LiteSVM instance.
+pub struct SvmRpc {
+    pub svm: LiteSVM,
+}
+
+impl SvmRpc {
+    /// A LiteSVM instance with every feature active but
+    /// `enable_extend_program_checked`: this client predates
+    /// `ExtendProgramChecked`, which supersedes `ExtendProgram` once the
+    /// feature is active.
+    pub fn new() -> Self {
+        let mut feature_set = FeatureSet::all_enabled();
+        feature_set.deactivate(&enable_extend_program_checked::id());
+        Self {
+            svm: LiteSVM::new().with_feature_set(feature_set),
+        }
+    }
+
+    /// Signs `instructions` with the latest blockhash, `payer` paying the
+    /// fees. `signers` may hold more signers than the message needs; the
+    /// others are left out.
+    ///
+    /// # Panics
+    ///
+    /// If a signer the message needs is missing.
+    pub fn transaction(
+        &self,
+        instructions: &[Instruction],
+        payer: &dyn Signer,
+        signers: &[&dyn Signer],
+    ) -> Transaction {
+        let message = Message::new(instructions, Some(&payer.pubkey()));
+        let signers: Vec<_> = std::iter::once(payer)
+            .chain(signers.iter().copied())
+            .filter(|signer| message.signer_keys().contains(&&signer.pubkey()))
+            .collect();
+        Transaction::new(&signers, message, self.svm.latest_blockhash())
+    }
+
+    /// Executes `transaction`, then moves to the next slot as a cluster
+    /// would before the confirmation arrives.
+    pub fn process_transaction(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<TransactionMetadata, Box<FailedTransactionMetadata>> {
+        let result = self.svm.send_transaction(transaction).map_err(Box::new);
+        let slot = self.svm.get_sysvar::<Clock>().slot;
+        self.svm.warp_to_slot(slot.saturating_add(1));
+        result
+    }
+
+    /// Signs `instructions` like [`transaction`](Self::transaction) and
+    /// executes them.
+    pub fn send(
+        &mut self,
+        instructions: &[Instruction],
+        payer: &dyn Signer,
+        signers: &[&dyn Signer],
+    ) -> Result<TransactionMetadata, Box<FailedTransactionMetadata>> {
+        let transaction = self.transaction(instructions, payer, signers);
+        self.process_transaction(transaction)
+    }
+
+    /// Sends the steps of `plan` in order, stopping at the first that fails.
+    pub fn send_plan(
+        &mut self,
+        plan: &Plan,
+        payer: &dyn Signer,
+        signers: &[&dyn Signer],
+    ) -> Result<Vec<TransactionMetadata>, Box<FailedTransactionMetadata>> {
+        plan.steps
+            .iter()
+            .map(|step| self.send(&step.instructions, payer, signers))
+            .collect()
+    }
+}
+
+impl Default for SvmRpc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoaderRpc for SvmRpc {
+    fn get_account(&self, address: &Address) -> Result<Option<Account>, RpcError> {
+        Ok(self.svm.get_account(address))
+    }
+
+    fn get_rent(&self) -> Result<Rent, RpcError> {
+        Ok(self.svm.get_sysvar::<Rent>())
+    }
+
+    fn get_latest_blockhash(&self) -> Result<Hash, RpcError> {
+        Ok(self.svm.latest_blockhash())
+    }
+
+    /// Executes the transaction with
+    /// [`process_transaction`](Self::process_transaction).
+    fn send_and_confirm_transaction(
+        &mut self,
+        transaction: &Transaction,
+    ) -> Result<Signature, RpcError> {
+        self.process_transaction(transaction.clone())
+            .map(|meta| meta.signature)
+            .map_err(|failed| {
+                RpcError::Transaction(format!("{} {:?}", failed.err, failed.meta.logs))
+            })
+    }
+}
@@ -0,0 +1,127 @@
+//! Verifiable-build hashes of deployed and buffered programs.
+//!
+//! The hash is the SHA-256 of the program bytes with trailing zero bytes
+//! removed, as computed by `solana-verify`. This makes the hash of a local
+//! ELF comparable with the hash of a Buffer or ProgramData account, whose
+//! program bytes are padded with zeros up to the account's capacity.
+
+use {
+    crate::state::{Buffer, ProgramData, StateError},
+    sha2::{Digest, Sha256},
+    std::{fmt, str::FromStr},
+    thiserror::Error,
+};
+
+/// SHA-256 of program bytes, without trailing zero padding.
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub struct ProgramHash(pub [u8; 32]);
+
+impl ProgramHash {
+    /// Hashes a local ELF file.
+    pub fn of_elf(elf: &[u8]) -> Self {
+        Self(Sha256::digest(trim_padding(elf)).into())
+    }
+
+    /// Hashes the program bytes of a Buffer account's data.
+    pub fn of_buffer(account_data: &[u8]) -> Result<Self, StateError> {
+        Ok(Self::of_elf(Buffer::unpack(account_data)?.data))
+    }
+
+    /// Hashes the program bytes of a ProgramData account's data.
+    pub fn of_program_data(account_data: &[u8]) -> Result<Self, StateError> {
+        Ok(Self::of_elf(ProgramData::unpack(account_data)?.data))
+    }
+}
+
+impl fmt::Display for ProgramHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl fmt::Debug for ProgramHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ProgramHash({self})")
+    }
+}
+
+/// Error returned when parsing a hex-encoded [`ProgramHash`].
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+#[error("expected 64 hexadecimal characters")]
+pub struct ParseProgramHashError;
+
+impl FromStr for ProgramHash {
+    type Err = ParseProgramHashError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(ParseProgramHashError);
+        }
+        let mut hash = [0; 32];
+        for (byte, pair) in hash.iter_mut().zip(s.as_bytes().chunks_exact(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| ParseProgramHashError)?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| ParseProgramHashError)?;
+        }
+        Ok(Self(hash))
+    }
+}
+
+/// Report of on-chain program bytes not matching the expected build.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HashMismatch {
+    pub expected: ProgramHash,
+    pub actual: ProgramHash,
+    /// Length of the on-chain program bytes once padding is removed.
+    pub actual_len: usize,
+}
+
+impl fmt::Display for HashMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "expected program hash {} but found {} ({} bytes)",
+            self.expected, self.actual, self.actual_len
+        )
+    }
+}
+
+/// Errors returned when verifying an account against an expected build.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum VerifyError {
+    #[error(transparent)]
+    State(#[from] StateError),
+    #[error("{0}")]
+    Mismatch(HashMismatch),
+}
+
+/// Checks that a Buffer account holds the expected build, typically before
+/// using someone else's buffer in an `Upgrade`.
+pub fn verify_buffer(account_data: &[u8], expected: &ProgramHash) -> Result<(), VerifyError> {
+    verify(Buffer::unpack(account_data)?.data, expected)
+}
+
+/// Checks that a ProgramData account holds the expected build.
+pub fn verify_program_data(account_data: &[u8], expected: &ProgramHash) -> Result<(), VerifyError> {
+    verify(ProgramData::unpack(account_data)?.data, expected)
+}
+
+fn verify(program: &[u8], expected: &ProgramHash) -> Result<(), VerifyError> {
+    let actual = ProgramHash::of_elf(program);
+    if actual == *expected {
+        return Ok(());
+    }
+    Err(VerifyError::Mismatch(HashMismatch {
+        expected: *expected,
+        actual,
+        actual_len: trim_padding(program).len(),
+    }))
+}
+
+/// Strips trailing zero bytes.
+pub(crate) fn trim_padding(bytes: &[u8]) -> &[u8] {
+    let len = bytes
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |last| last.saturating_add(1));
+    &bytes[..len]
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use {
+    common::svm::MEMO,
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{
+        buffer::{buffer_seed, create_buffer, find_buffer_address},
+        plan::{DeployPlanBuilder, Plan},
+        rpc::LoaderRpc,
+        state::{buffer_account_size, Buffer, ProgramData},
+        svm::SvmRpc,
+    },
+    solana_signer::Signer,
+};
+
+#[test]
+fn creates_rent_exempt_buffer() {
+    let mut rpc = SvmRpc::new();
+    let payer = Keypair::new();
+    rpc.svm.airdrop(&payer.pubkey(), 10_000_000_000).unwrap();
+    let buffer = Keypair::new();
+    let authority = Keypair::new().pubkey();
+    let rent = rpc.get_rent().unwrap();
+
+    let instructions = create_buffer(&payer.pubkey(), &buffer.pubkey(), &authority, 500, &rent);
+    rpc.send(&instructions, &payer, &[&buffer]).unwrap();
+
+    let account = rpc.get_loader_account(&buffer.pubkey()).unwrap();
+    let size = buffer_account_size(500).unwrap();
+    assert_eq!(account.data.len(), size);
+    assert_eq!(account.lamports, rent.minimum_balance(size));
+    let buffer = Buffer::unpack(&account.data).unwrap();
+    assert_eq!(buffer.authority, Some(authority));
+    assert_eq!(buffer.data, [0; 500]);
+}
+
+#[test]
+fn derives_buffer_address_from_payer_and_elf() {
+    let payer = Keypair::new();
+    let elf = vec![1; 100];
+    let buffer = find_buffer_address(&payer.pubkey(), &elf);
+
+    assert_eq!(buffer_seed(&elf).len(), 32);
+    assert_eq!(find_buffer_address(&payer.pubkey(), &elf), buffer);
+    assert_ne!(find_buffer_address(&payer.pubkey(), &[2; 100]), buffer);
+    assert_ne!(find_buffer_address(&Keypair::new().pubkey(), &elf), buffer);
+}
+
+#[test]
+fn deploys_from_seeded_buffer_without_buffer_keypair() {
+    let mut rpc = SvmRpc::new();
+    let payer = Keypair::new();
+    rpc.svm.airdrop(&payer.pubkey(), 10_000_000_000).unwrap();
+    let elf = rpc.get_account(&MEMO).unwrap().unwrap().data;
+    let program = Keypair::new();
+    let rent = rpc.get_rent().unwrap();
+    let build = || {
+        DeployPlanBuilder::new()
+            .payer(payer.pubkey())
+            .program(program.pubkey())
+            .seed_buffer()
+            .rent(rent.clone())
+            .build(&elf)
+    };
+    let plan = build();
+    assert_eq!(plan.buffer, find_buffer_address(&payer.pubkey(), &elf));
+    assert_eq!(plan.steps[0].signers(), [payer.pubkey()]);
+
+    // A restarted deploy rebuilds the same plan and finds the buffer.
+    let create_and_write = Plan {
+        buffer: plan.buffer,
+        steps: plan.steps[..plan.steps.len() - 1].to_vec(),
+    };
+    rpc.send_plan(&create_and_write, &payer, &[]).unwrap();
+    let restarted = build();
+    assert_eq!(restarted, plan);
+    let account = rpc.get_account(&restarted.buffer).unwrap().unwrap();
+    let buffer = Buffer::unpack(&account.data).unwrap();
+    assert_eq!(buffer.authority, Some(payer.pubkey()));
+    assert_eq!(buffer.data, elf.as_slice());
+
+    let deploy = Plan {
+        buffer: plan.buffer,
+        steps: restarted.steps[plan.steps.len() - 1..].to_vec(),
+    };
+    rpc.send_plan(&deploy, &payer, &[&program]).unwrap();
+    let program_data = rpc.get_program(&program.pubkey()).unwrap().program_data;
+    assert_eq!(
+        &ProgramData::unpack(&program_data.data).unwrap().data[..elf.len()],
+        elf.as_slice()
+    );
+}
@@ -0,0 +1,56 @@
+use solana_loader_v3_program_client::{
+    capacity::{
+        plan_capacity, plan_deploy, plan_extend, CapacityError, Exact, FixedHeadroom,
+        HistoricalGrowth, PercentageGrowth,
+    },
+    state::MAX_PROGRAM_DATA_LEN,
+};
+
+#[test]
+fn plans_capacity_per_policy() {
+    assert_eq!(plan_capacity(&Exact, 1_000), Ok(1_000));
+    assert_eq!(
+        plan_capacity(&FixedHeadroom { bytes: 24 }, 1_000),
+        Ok(1_024)
+    );
+    assert_eq!(
+        plan_capacity(&PercentageGrowth { percent: 50 }, 1_001),
+        Ok(1_502)
+    );
+    let historical = HistoricalGrowth {
+        history: vec![1_000, 1_100, 1_300],
+        upgrades: 3,
+    };
+    assert_eq!(historical.average_growth(), 150);
+    assert_eq!(plan_capacity(&historical, 1_300), Ok(1_750));
+}
+
+#[test]
+fn clamps_to_max_program_data_len() {
+    let policy = PercentageGrowth { percent: 100 };
+    assert_eq!(
+        plan_deploy(&policy, MAX_PROGRAM_DATA_LEN - 1)
+            .unwrap()
+            .max_data_len,
+        MAX_PROGRAM_DATA_LEN as u64
+    );
+    assert_eq!(
+        plan_capacity(&Exact, MAX_PROGRAM_DATA_LEN + 1),
+        Err(CapacityError::ElfTooLarge {
+            elf_len: MAX_PROGRAM_DATA_LEN + 1
+        })
+    );
+}
+
+#[test]
+fn plans_extension_only_when_needed() {
+    let policy = FixedHeadroom { bytes: 100 };
+    assert_eq!(plan_extend(&policy, 2_000, 1_500), Ok(None));
+    assert_eq!(
+        plan_extend(&policy, 2_000, 2_500)
+            .unwrap()
+            .unwrap()
+            .additional_bytes,
+        600
+    );
+}
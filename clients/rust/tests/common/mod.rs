@@ -0,0 +1,184 @@
+#![allow(clippy::arithmetic_side_effects, dead_code)]
+
+pub mod svm;
+
+use solana_loader_v3_program_client::elf::{EM_SBF, ET_DYN, SHF_EXECINSTR};
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_STRTAB: u32 = 3;
+const SHT_REL: u32 = 9;
+const SHT_DYNSYM: u32 = 11;
+
+/// A section to place in a [`TestElf`].
+pub struct TestSection {
+    pub name: &'static str,
+    pub sh_type: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub link: u32,
+    pub data: Vec<u8>,
+}
+
+/// Builder for small ELF64 files laid out as: file header, section contents,
+/// section name string table and section header table.
+pub struct TestElf {
+    pub e_type: u16,
+    pub machine: u16,
+    pub flags: u32,
+    pub entry: u64,
+    pub sections: Vec<TestSection>,
+}
+
+impl TestElf {
+    /// An SBPF v0 shared object with a `.text` section at 0x120 holding
+    /// `text` and the entrypoint at its start.
+    pub fn sbf(text: &[u8]) -> Self {
+        Self {
+            e_type: ET_DYN,
+            machine: EM_SBF,
+            flags: 0,
+            entry: 0x120,
+            sections: vec![TestSection {
+                name: ".text",
+                sh_type: SHT_PROGBITS,
+                flags: SHF_EXECINSTR,
+                addr: 0x120,
+                link: 0,
+                data: text.to_vec(),
+            }],
+        }
+    }
+
+    pub fn with_section(mut self, name: &'static str, data: &[u8]) -> Self {
+        self.sections.push(TestSection {
+            name,
+            sh_type: SHT_PROGBITS,
+            flags: 0,
+            addr: 0,
+            link: 0,
+            data: data.to_vec(),
+        });
+        self
+    }
+
+    pub fn with_typed_section(mut self, name: &'static str, sh_type: u32, data: &[u8]) -> Self {
+        self.sections.push(TestSection {
+            name,
+            sh_type,
+            flags: 0,
+            addr: 0,
+            link: 0,
+            data: data.to_vec(),
+        });
+        self
+    }
+
+    /// Adds `.dynstr` and `.dynsym` sections exporting global functions
+    /// `(name, value, size)` from `.text`.
+    pub fn with_exported_symbols(mut self, symbols: &[(&str, u64, u64)]) -> Self {
+        let mut names = vec![0u8];
+        let mut entries = vec![0u8; 24];
+        for (name, value, size) in symbols {
+            entries.extend_from_slice(&(names.len() as u32).to_le_bytes());
+            // STB_GLOBAL, STT_FUNC
+            entries.push(0x12);
+            entries.push(0);
+            entries.extend_from_slice(&1u16.to_le_bytes());
+            entries.extend_from_slice(&value.to_le_bytes());
+            entries.extend_from_slice(&size.to_le_bytes());
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        self = self.with_typed_section(".dynstr", SHT_STRTAB, &names);
+        let dynstr = self.sections.len() as u32;
+        self = self.with_typed_section(".dynsym", SHT_DYNSYM, &entries);
+        self.sections.last_mut().unwrap().link = dynstr;
+        self
+    }
+
+    /// Adds a `.rel.dyn` section with `(type, symbol index)` relocations
+    /// against `.dynsym`, which must be added first.
+    pub fn with_relocations(mut self, relocations: &[(u32, u32)]) -> Self {
+        let dynsym = self
+            .sections
+            .iter()
+            .position(|section| section.name == ".dynsym")
+            .expect("no .dynsym section") as u32
+            + 1;
+        let mut entries = Vec::new();
+        for (index, (relocation_type, symbol)) in relocations.iter().enumerate() {
+            entries.extend_from_slice(&(0x120 + 8 * index as u64).to_le_bytes());
+            entries.extend_from_slice(&relocation_type.to_le_bytes());
+            entries.extend_from_slice(&symbol.to_le_bytes());
+        }
+        self = self.with_typed_section(".rel.dyn", SHT_REL, &entries);
+        self.sections.last_mut().unwrap().link = dynsym;
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut bytes = vec![0; 64];
+        let mut names = vec![0u8];
+        // (name offset, type, flags, addr, offset, size, link)
+        let mut headers = vec![(0u32, 0u32, 0u64, 0u64, 0u64, 0u64, 0u32)];
+        for section in &self.sections {
+            let offset = bytes.len() as u64;
+            bytes.extend_from_slice(&section.data);
+            headers.push((
+                names.len() as u32,
+                section.sh_type,
+                section.flags,
+                section.addr,
+                offset,
+                section.data.len() as u64,
+                section.link,
+            ));
+            names.extend_from_slice(section.name.as_bytes());
+            names.push(0);
+        }
+        let shstrtab_name = names.len() as u32;
+        names.extend_from_slice(b".shstrtab\0");
+        headers.push((
+            shstrtab_name,
+            SHT_STRTAB,
+            0,
+            0,
+            bytes.len() as u64,
+            names.len() as u64,
+            0,
+        ));
+        bytes.extend_from_slice(&names);
+        while !bytes.len().is_multiple_of(8) {
+            bytes.push(0);
+        }
+
+        let shoff = bytes.len() as u64;
+        for (name, sh_type, flags, addr, offset, size, link) in &headers {
+            let mut header = [0u8; 64];
+            header[0..4].copy_from_slice(&name.to_le_bytes());
+            header[4..8].copy_from_slice(&sh_type.to_le_bytes());
+            header[8..16].copy_from_slice(&flags.to_le_bytes());
+            header[16..24].copy_from_slice(&addr.to_le_bytes());
+            header[24..32].copy_from_slice(&offset.to_le_bytes());
+            header[32..40].copy_from_slice(&size.to_le_bytes());
+            header[40..44].copy_from_slice(&link.to_le_bytes());
+            bytes.extend_from_slice(&header);
+        }
+
+        bytes[0..4].copy_from_slice(b"\x7fELF");
+        bytes[4] = 2;
+        bytes[5] = 1;
+        bytes[6] = 1;
+        bytes[16..18].copy_from_slice(&self.e_type.to_le_bytes());
+        bytes[18..20].copy_from_slice(&self.machine.to_le_bytes());
+        bytes[20..24].copy_from_slice(&1u32.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.entry.to_le_bytes());
+        bytes[40..48].copy_from_slice(&shoff.to_le_bytes());
+        bytes[48..52].copy_from_slice(&self.flags.to_le_bytes());
+        bytes[52..54].copy_from_slice(&64u16.to_le_bytes());
+        bytes[58..60].copy_from_slice(&64u16.to_le_bytes());
+        bytes[60..62].copy_from_slice(&(headers.len() as u16).to_le_bytes());
+        bytes[62..64].copy_from_slice(&((headers.len() - 1) as u16).to_le_bytes());
+        bytes
+    }
+}
@@ -0,0 +1,27 @@
+use {
+    solana_address::Address,
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{plan::DeployPlanBuilder, rpc::LoaderRpc, svm::SvmRpc},
+    solana_signer::Signer,
+};
+
+/// SPL Memo 3.0 and Associated Token Account 1.1, preloaded by LiteSVM.
+/// Their ELF bytes are used as test programs.
+pub const MEMO: Address = Address::from_str_const("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+pub const ASSOCIATED_TOKEN: Address =
+    Address::from_str_const("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// Deploys the memo program with `payer` as upgrade authority.
+pub fn deploy_memo(rpc: &mut SvmRpc, payer: &Keypair) -> (Address, Vec<u8>) {
+    let elf = rpc.get_account(&MEMO).unwrap().unwrap().data;
+    let program = Keypair::new();
+    let buffer = Keypair::new();
+    let plan = DeployPlanBuilder::new()
+        .payer(payer.pubkey())
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .rent(rpc.get_rent().unwrap())
+        .build(&elf);
+    rpc.send_plan(&plan, payer, &[&program, &buffer]).unwrap();
+    (program.pubkey(), elf)
+}
@@ -0,0 +1,45 @@
+use {
+    solana_loader_v3_program_client::{
+        cost::{CostError, DeployCost, WRITE_CHUNK_SIZE},
+        state::{BUFFER_HEADER_SIZE, PROGRAM_ACCOUNT_SIZE, PROGRAM_DATA_HEADER_SIZE},
+    },
+    solana_rent::Rent,
+};
+
+#[test]
+fn estimates_deploy_cost() {
+    let rent = Rent::default();
+    let elf_len = WRITE_CHUNK_SIZE * 10 + 1;
+    let cost = DeployCost::estimate(elf_len, elf_len * 2, &rent, 5_000).unwrap();
+
+    assert_eq!(
+        cost.buffer_lamports,
+        rent.minimum_balance(BUFFER_HEADER_SIZE + elf_len)
+    );
+    assert_eq!(
+        cost.program_lamports,
+        rent.minimum_balance(PROGRAM_ACCOUNT_SIZE)
+    );
+    assert_eq!(
+        cost.program_data_lamports,
+        rent.minimum_balance(PROGRAM_DATA_HEADER_SIZE + elf_len * 2)
+    );
+    assert_eq!(cost.write_transactions, 11);
+    assert_eq!(cost.signature_fees, (11 + 4) * 5_000);
+    assert_eq!(cost.upgrade_spill_refund, cost.buffer_lamports);
+    assert_eq!(
+        cost.locked_lamports(),
+        cost.program_lamports + cost.program_data_lamports
+    );
+}
+
+#[test]
+fn rejects_elf_larger_than_max_data_len() {
+    assert_eq!(
+        DeployCost::estimate(2, 1, &Rent::default(), 5_000),
+        Err(CostError::ElfLargerThanMaxDataLen {
+            elf_len: 2,
+            max_data_len: 1
+        })
+    );
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use {
+    common::TestElf,
+    solana_loader_v3_program_client::{
+        elf::{ElfDiff, RelocationEntry, SbpfVersion, SymbolChange},
+        state::LoaderState,
+    },
+};
+
+const R_BPF_64_RELATIVE: u32 = 8;
+const R_BPF_64_32: u32 = 10;
+
+#[test]
+fn diffs_deployed_program_against_candidate() {
+    let old = TestElf::sbf(&[0x95, 0, 0, 0, 0, 0, 0, 0])
+        .with_section(".rodata", b"hello")
+        .with_exported_symbols(&[("entrypoint", 0x120, 8), ("old_export", 0x120, 8)])
+        .with_relocations(&[
+            (R_BPF_64_RELATIVE, 0),
+            (R_BPF_64_RELATIVE, 0),
+            (R_BPF_64_32, 2),
+        ])
+        .build();
+    let mut new = TestElf::sbf(&[0xb7, 0, 0, 0, 0, 0, 0, 0, 0x95, 0, 0, 0, 0, 0, 0, 0])
+        .with_section(".rodata", b"hello")
+        .with_section(".data.rel.ro", &[1; 64])
+        .with_exported_symbols(&[("entrypoint", 0x120, 16), ("new_export", 0x128, 8)])
+        .with_relocations(&[(R_BPF_64_32, 1), (R_BPF_64_RELATIVE, 0), (R_BPF_64_32, 2)]);
+    new.flags = 3;
+    let new = new.build();
+
+    let mut program_data = LoaderState::ProgramData {
+        slot: 1,
+        upgrade_authority: None,
+    }
+    .to_bytes();
+    program_data.extend_from_slice(&old);
+    program_data.extend_from_slice(&[0; 32]);
+
+    let diff = ElfDiff::from_program_data(&program_data, &new).unwrap();
+
+    let text = diff.sections.iter().find(|s| s.name == ".text").unwrap();
+    assert_eq!(text.size_delta(), 8);
+    assert!(text.changed);
+    let rodata = diff.sections.iter().find(|s| s.name == ".rodata").unwrap();
+    assert!(!rodata.changed);
+    assert!(diff
+        .changed_sections()
+        .any(|s| s.name == ".data.rel.ro" && s.old_size.is_none()));
+
+    let names = diff
+        .exported_symbols
+        .iter()
+        .map(|change| match change {
+            SymbolChange::Added(symbol) => format!("+{}", symbol.name),
+            SymbolChange::Removed(symbol) => format!("-{}", symbol.name),
+            SymbolChange::Modified { new, .. } => format!("~{}", new.name),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["~entrypoint", "+new_export", "-old_export"]);
+
+    let relocation = |relocation_type, symbol: &str| RelocationEntry {
+        relocation_type,
+        symbol: symbol.to_string(),
+    };
+    assert_eq!((diff.old_relocations, diff.new_relocations), (3, 3));
+    assert_eq!(
+        diff.added_relocations,
+        [
+            relocation(R_BPF_64_32, "entrypoint"),
+            relocation(R_BPF_64_32, "new_export"),
+        ]
+    );
+    assert_eq!(
+        diff.removed_relocations,
+        [
+            relocation(R_BPF_64_RELATIVE, ""),
+            relocation(R_BPF_64_32, "old_export"),
+        ]
+    );
+
+    assert!(diff.sbpf_version_changed());
+    assert_eq!(diff.new_sbpf_version, SbpfVersion::V3);
+    assert_eq!(diff.capacity, old.len() + 32);
+    assert_eq!(diff.required_extension(), Some(new.len() - old.len() - 32));
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use {
+    common::TestElf,
+    solana_loader_v3_program_client::{
+        elf::{validate, Diagnostic, ElfError, SbpfVersion, Severity},
+        state::MAX_PROGRAM_DATA_LEN,
+    },
+};
+
+#[test]
+fn accepts_sbf_program() {
+    let elf = TestElf::sbf(&[0x95, 0, 0, 0, 0, 0, 0, 0]).build();
+    let report = validate(&elf, MAX_PROGRAM_DATA_LEN);
+
+    assert!(report.is_valid(), "{:?}", report.diagnostics);
+    assert_eq!(report.sbpf_version, Some(SbpfVersion::V0));
+}
+
+#[test]
+fn rejects_non_elf() {
+    let report = validate(b"#!/bin/sh\necho hello\n", MAX_PROGRAM_DATA_LEN);
+
+    assert_eq!(
+        report.diagnostics,
+        vec![Diagnostic::Malformed(ElfError::BadMagic)]
+    );
+}
+
+#[test]
+fn reports_every_problem() {
+    let mut elf = TestElf::sbf(&[0; 8]);
+    // x86-64, with the entrypoint past the end of `.text`.
+    elf.machine = 62;
+    elf.flags = 0x20;
+    elf.entry = 0x1000;
+    let elf = elf.build();
+    let report = validate(&elf, 16);
+
+    assert!(!report.is_valid());
+    assert_eq!(
+        report.diagnostics,
+        vec![
+            Diagnostic::TooLarge {
+                len: elf.len(),
+                max_data_len: 16
+            },
+            Diagnostic::UnsupportedMachine(62),
+            Diagnostic::UnknownSbpfVersion(0x20),
+            Diagnostic::EntrypointOutsideText(0x1000),
+        ]
+    );
+    assert!(report
+        .errors()
+        .all(|diagnostic| diagnostic.severity() == Severity::Error));
+}
@@ -0,0 +1,389 @@
+#![allow(clippy::arithmetic_side_effects)]
+
+mod common;
+
+use {
+    common::svm::{ASSOCIATED_TOKEN, MEMO},
+    litesvm::LiteSVM,
+    solana_account::Account,
+    solana_address::Address,
+    solana_clock::Clock,
+    solana_hash::Hash,
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{
+        capacity::{plan_extend, Exact},
+        executor::{ExecutorConfig, ExecutorError, PlanExecutor},
+        journal::{Journal, JournalWriter, Status},
+        nonce::{DurableNonce, NonceError},
+        plan::{DeployPlanBuilder, UpgradePlanBuilder},
+        rpc::{AsyncLoaderRpc, RpcError},
+        state::{find_program_data_address, ProgramData},
+        svm::SvmRpc,
+    },
+    solana_nonce::{
+        state::{DurableNonce as StoredNonce, State},
+        versions::Versions,
+    },
+    solana_signature::Signature,
+    solana_signer::Signer,
+    solana_transaction::Transaction,
+    std::{cell::RefCell, collections::HashMap, time::Duration},
+    tokio::time::Instant,
+};
+
+/// Time between two blocks of the simulated cluster.
+const BLOCK_TIME: Duration = Duration::from_millis(400);
+
+/// Number of blocks a blockhash stays valid for.
+const BLOCKHASH_VALIDITY: u64 = 20;
+
+/// A cluster behind an unreliable connection: every `drop_every`th
+/// transaction is lost, and the others land after a delay of up to two
+/// `delay`s. Blocks are produced as (paused) time goes by.
+struct SimulatedRpc {
+    start: Instant,
+    drop_every: u32,
+    delay: Duration,
+    state: RefCell<SimulatedState>,
+}
+
+struct SimulatedState {
+    svm: LiteSVM,
+    sent: u32,
+    pending: Vec<(Instant, Transaction)>,
+    last_valid_block_heights: HashMap<Hash, u64>,
+    statuses: HashMap<Signature, Result<(), String>>,
+}
+
+impl SimulatedRpc {
+    fn new(drop_every: u32, delay: Duration) -> Self {
+        Self {
+            start: Instant::now(),
+            drop_every,
+            delay,
+            state: RefCell::new(SimulatedState {
+                // Blockhash expiry is simulated here instead.
+                svm: SvmRpc::new().svm.with_blockhash_check(false),
+                sent: 0,
+                pending: Vec::new(),
+                last_valid_block_heights: HashMap::new(),
+                statuses: HashMap::new(),
+            }),
+        }
+    }
+
+    fn svm(&self) -> std::cell::RefMut<'_, LiteSVM> {
+        std::cell::RefMut::map(self.state.borrow_mut(), |state| &mut state.svm)
+    }
+
+    fn block_height(&self) -> u64 {
+        (self.start.elapsed().as_millis() / BLOCK_TIME.as_millis()) as u64
+    }
+
+    /// Processes the transactions whose delay has elapsed, dropping those
+    /// whose blockhash expired in the meantime. Transactions using a durable
+    /// nonce are left for the SVM to check.
+    fn deliver(&self) {
+        let height = self.block_height();
+        let now = Instant::now();
+        let mut state = self.state.borrow_mut();
+        let (arrived, pending) = std::mem::take(&mut state.pending)
+            .into_iter()
+            .partition(|(at, _)| *at <= now);
+        state.pending = pending;
+        for (_, transaction) in arrived {
+            let valid = state
+                .last_valid_block_heights
+                .get(&transaction.message.recent_blockhash)
+                .is_none_or(|last_valid| height <= *last_valid);
+            if !valid {
+                continue;
+            }
+            let result = state
+                .svm
+                .send_transaction(transaction.clone())
+                .map(|_| ())
+                .map_err(|failed| failed.err.to_string());
+            state.statuses.insert(transaction.signatures[0], result);
+            let slot = state.svm.get_sysvar::<Clock>().slot;
+            state.svm.warp_to_slot(slot + 1);
+        }
+    }
+}
+
+impl AsyncLoaderRpc for SimulatedRpc {
+    async fn get_account(&self, address: &Address) -> Result<Option<Account>, RpcError> {
+        self.deliver();
+        Ok(self.svm().get_account(address))
+    }
+
+    async fn get_latest_blockhash(&self) -> Result<(Hash, u64), RpcError> {
+        self.deliver();
+        let height = self.block_height();
+        let mut bytes = [0; 32];
+        bytes[..8].copy_from_slice(&height.to_le_bytes());
+        let blockhash = Hash::new_from_array(bytes);
+        let last_valid = height + BLOCKHASH_VALIDITY;
+        self.state
+            .borrow_mut()
+            .last_valid_block_heights
+            .insert(blockhash, last_valid);
+        Ok((blockhash, last_valid))
+    }
+
+    async fn get_block_height(&self) -> Result<u64, RpcError> {
+        self.deliver();
+        Ok(self.block_height())
+    }
+
+    async fn send_transaction(&self, transaction: &Transaction) -> Result<Signature, RpcError> {
+        self.deliver();
+        let mut state = self.state.borrow_mut();
+        state.sent += 1;
+        if !state.sent.is_multiple_of(self.drop_every) {
+            let delay = self.delay * (state.sent % 3);
+            state
+                .pending
+                .push((Instant::now() + delay, transaction.clone()));
+        }
+        Ok(transaction.signatures[0])
+    }
+
+    async fn get_signature_status(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<Result<(), String>>, RpcError> {
+        self.deliver();
+        Ok(self.state.borrow().statuses.get(signature).cloned())
+    }
+}
+
+fn config() -> ExecutorConfig {
+    ExecutorConfig {
+        concurrency: 8,
+        ..ExecutorConfig::default()
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn deploys_and_upgrades_through_dropped_and_delayed_transactions() {
+    let rpc = SimulatedRpc::new(4, Duration::from_secs(1));
+    let payer = Keypair::new();
+    rpc.svm().airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
+    let memo = rpc.svm().get_account(&MEMO).unwrap().data;
+    let program = Keypair::new();
+    let buffer = Keypair::new();
+    let executor = PlanExecutor::new(&rpc, config());
+
+    let plan = DeployPlanBuilder::new()
+        .payer(payer.pubkey())
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .build(&memo);
+    let report = executor
+        .execute(&plan, &memo, &payer, &[&program, &buffer])
+        .await
+        .unwrap();
+    assert_eq!(report.signatures.len(), plan.steps.len());
+    assert!(report.retries > 0);
+    {
+        let statuses = &rpc.state.borrow().statuses;
+        assert!(report
+            .signatures
+            .iter()
+            .all(|signature| statuses[signature].is_ok()));
+    }
+
+    // The upgrade to a larger program extends the ProgramData account first.
+    let program_data_address = find_program_data_address(&program.pubkey());
+    let ata = rpc.svm().get_account(&ASSOCIATED_TOKEN).unwrap().data;
+    let extend = plan_extend(&Exact, memo.len(), ata.len()).unwrap().unwrap();
+    let buffer = Keypair::new();
+    let plan = UpgradePlanBuilder::new()
+        .payer(payer.pubkey())
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .additional_bytes(extend.additional_bytes)
+        .build(&ata);
+    executor
+        .execute(&plan, &ata, &payer, &[&buffer])
+        .await
+        .unwrap();
+
+    let account = rpc.svm().get_account(&program_data_address).unwrap();
+    let program_data = ProgramData::unpack(&account.data).unwrap();
+    assert_eq!(&program_data.data[..ata.len()], ata.as_slice());
+}
+
+#[tokio::test(start_paused = true)]
+async fn refuses_to_deploy_from_mismatched_buffer() {
+    let rpc = SimulatedRpc::new(u32::MAX, Duration::ZERO);
+    let payer = Keypair::new();
+    rpc.svm().airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
+    let memo = rpc.svm().get_account(&MEMO).unwrap().data;
+    let program = Keypair::new();
+    let buffer = Keypair::new();
+    let plan = DeployPlanBuilder::new()
+        .payer(payer.pubkey())
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .build(&memo);
+
+    // The plan writes `memo`, but a patched ELF is expected.
+    let mut patched = memo.clone();
+    patched[2000] ^= 1;
+    let result = PlanExecutor::new(&rpc, config())
+        .execute(&plan, &patched, &payer, &[&program, &buffer])
+        .await;
+    assert_eq!(result, Err(ExecutorError::BufferMismatch(vec![1012])));
+    assert!(rpc.svm().get_account(&program.pubkey()).is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn reports_failed_transactions_and_missing_signers() {
+    let rpc = SimulatedRpc::new(u32::MAX, Duration::ZERO);
+    let payer = Keypair::new();
+    rpc.svm().airdrop(&payer.pubkey(), 1_000_000).unwrap();
+    let memo = rpc.svm().get_account(&MEMO).unwrap().data;
+    let program = Keypair::new();
+    let buffer = Keypair::new();
+    let plan = DeployPlanBuilder::new()
+        .payer(payer.pubkey())
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .build(&memo);
+    let executor = PlanExecutor::new(&rpc, config());
+
+    assert_eq!(
+        executor.execute(&plan, &memo, &payer, &[&program]).await,
+        Err(ExecutorError::MissingSigner(buffer.pubkey()))
+    );
+    // The payer cannot fund the buffer.
+    assert!(matches!(
+        executor
+            .execute(&plan, &memo, &payer, &[&program, &buffer])
+            .await,
+        Err(ExecutorError::TransactionFailed { .. })
+    ));
+}
+
+#[tokio::test(start_paused = true)]
+async fn journals_each_confirmed_step() {
+    let rpc = SimulatedRpc::new(5, Duration::from_millis(500));
+    let payer = Keypair::new();
+    rpc.svm().airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
+    let memo = rpc.svm().get_account(&MEMO).unwrap().data;
+    let program = Keypair::new();
+    let buffer = Keypair::new();
+    let plan = DeployPlanBuilder::new()
+        .payer(payer.pubkey())
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .build(&memo);
+
+    let mut journal = JournalWriter::create(Vec::new(), &plan, &memo).unwrap();
+    let report = PlanExecutor::new(&rpc, config())
+        .execute_journaled(&plan, &memo, &payer, &[&program, &buffer], &mut journal)
+        .await
+        .unwrap();
+
+    let journal = Journal::read(journal.into_inner().as_slice()).unwrap();
+    assert_eq!(journal.status, Status::Succeeded);
+    // Writes are journaled in the order they landed.
+    let mut journaled: Vec<_> = journal
+        .confirmed
+        .iter()
+        .map(|(_, signature)| *signature)
+        .collect();
+    let mut reported = report.signatures.clone();
+    journaled.sort();
+    reported.sort();
+    assert_eq!(journaled, reported);
+    assert!(plan
+        .steps
+        .iter()
+        .all(|step| journal.is_confirmed(&step.kind)));
+    assert!(journal.resume(&plan, &memo, None).unwrap().steps.is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn upgrades_with_durable_nonce() {
+    let rpc = SimulatedRpc::new(3, Duration::from_secs(1));
+    let payer = Keypair::new();
+    rpc.svm().airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
+    let memo = rpc.svm().get_account(&MEMO).unwrap().data;
+    let program = Keypair::new();
+    let buffer = Keypair::new();
+    let executor = PlanExecutor::new(&rpc, config());
+    let plan = DeployPlanBuilder::new()
+        .payer(payer.pubkey())
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .build(&memo);
+    executor
+        .execute(&plan, &memo, &payer, &[&program, &buffer])
+        .await
+        .unwrap();
+
+    let nonce_account = Address::new_unique();
+    let state = State::new_initialized(
+        &payer.pubkey(),
+        StoredNonce::from_blockhash(&Hash::new_unique()),
+        5000,
+    );
+    let data = bincode::serialize(&Versions::new(state)).unwrap();
+    let lamports = rpc.svm().minimum_balance_for_rent_exemption(data.len());
+    rpc.svm()
+        .set_account(
+            nonce_account,
+            Account {
+                lamports,
+                data: data.clone(),
+                owner: solana_system_interface::program::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        )
+        .unwrap();
+    let nonce = DurableNonce::from_account_data(nonce_account, &data).unwrap();
+
+    // A nonce that is no longer stored is refused before the upgrade is sent.
+    let stale = DurableNonce {
+        blockhash: Hash::new_unique(),
+        ..nonce
+    };
+    let buffer = Keypair::new();
+    let plan = UpgradePlanBuilder::new()
+        .payer(payer.pubkey())
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .nonce(stale)
+        .build(&memo);
+    assert_eq!(
+        executor.execute(&plan, &memo, &payer, &[&buffer]).await,
+        Err(ExecutorError::Nonce(NonceError::BlockhashMismatch {
+            expected: stale.blockhash,
+            actual: nonce.blockhash,
+        }))
+    );
+
+    // The upgrade lands although blocks pass while it is resent.
+    let buffer = Keypair::new();
+    let plan = UpgradePlanBuilder::new()
+        .payer(payer.pubkey())
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .nonce(nonce)
+        .build(&memo);
+    let report = executor
+        .execute(&plan, &memo, &payer, &[&buffer])
+        .await
+        .unwrap();
+    let upgrade = report.signatures.last().unwrap();
+    assert_eq!(rpc.state.borrow().statuses[upgrade], Ok(()));
+    let advanced = rpc.svm().get_account(&nonce_account).unwrap();
+    assert!(matches!(
+        nonce.verify(&advanced.data),
+        Err(NonceError::BlockhashMismatch { .. })
+    ));
+}
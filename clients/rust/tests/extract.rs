@@ -0,0 +1,50 @@
+mod common;
+
+use {
+    common::TestElf,
+    solana_address::Address,
+    solana_loader_v3_program_client::{
+        elf::{ElfError, ElfSource, ExtractError, ExtractedElf},
+        state::LoaderState,
+    },
+};
+
+#[test]
+fn extracts_elf_ending_in_zeros() {
+    // A section ending in zeros would be cut short by trimming padding.
+    let elf = TestElf::sbf(&[0x95, 0, 0, 0, 0, 0, 0, 0])
+        .with_section(".bss.data", &[0; 16])
+        .build();
+    let upgrade_authority = Some(Address::new_unique());
+    let mut data = LoaderState::ProgramData {
+        slot: 7,
+        upgrade_authority,
+    }
+    .to_bytes();
+    data.extend_from_slice(&elf);
+    data.extend_from_slice(&[0; 64]);
+
+    let extracted = ExtractedElf::from_account_data(&data).unwrap();
+    assert_eq!(extracted.elf, elf.as_slice());
+    assert_eq!(
+        extracted.source,
+        ElfSource::ProgramData {
+            slot: 7,
+            upgrade_authority
+        }
+    );
+}
+
+#[test]
+fn rejects_partially_written_buffer() {
+    let elf = TestElf::sbf(&[0x95, 0, 0, 0, 0, 0, 0, 0]).build();
+    let mut data = LoaderState::Buffer { authority: None }.to_bytes();
+    data.extend_from_slice(&elf[..elf.len() - 8]);
+
+    assert_eq!(
+        ExtractedElf::from_buffer(&data),
+        Err(ExtractError::Elf(ElfError::TableOutOfBounds {
+            what: "section header"
+        }))
+    );
+}
@@ -0,0 +1,88 @@
+use {
+    solana_address::Address,
+    solana_loader_v3_program_client::{
+        filter::{AccountFilter, BufferFiltersBuilder, ProgramDataFiltersBuilder},
+        state::LoaderState,
+    },
+};
+
+fn matches(filters: &[AccountFilter], data: &[u8]) -> bool {
+    filters.iter().all(|filter| filter.matches(data))
+}
+
+#[test]
+fn selects_buffers_by_authority_and_size() {
+    let authority = Address::new_unique();
+    let buffer = |authority, len| {
+        let mut data = LoaderState::Buffer { authority }.to_bytes();
+        data.resize(data.len() + len, 1);
+        data
+    };
+
+    let any = BufferFiltersBuilder::new().build();
+    assert!(matches(&any, &buffer(None, 10)));
+    assert!(matches(&any, &buffer(Some(authority), 10)));
+    let program_data = LoaderState::ProgramData {
+        slot: 1,
+        upgrade_authority: Some(authority),
+    }
+    .to_bytes();
+    assert!(!matches(&any, &program_data));
+
+    let filters = BufferFiltersBuilder::new()
+        .authority(authority)
+        .data_len(10)
+        .build();
+    assert!(matches(&filters, &buffer(Some(authority), 10)));
+    assert!(!matches(&filters, &buffer(Some(authority), 11)));
+    assert!(!matches(&filters, &buffer(Some(Address::new_unique()), 10)));
+    assert!(!matches(&filters, &buffer(None, 10)));
+    assert_eq!(filters[1], AccountFilter::DataSize(47));
+}
+
+#[test]
+fn selects_program_data_by_upgrade_authority() {
+    let authority = Address::new_unique();
+    let program_data = |slot, upgrade_authority| {
+        LoaderState::ProgramData {
+            slot,
+            upgrade_authority,
+        }
+        .to_bytes()
+    };
+
+    let filters = ProgramDataFiltersBuilder::new()
+        .upgrade_authority(authority)
+        .build();
+    // Any deploy slot matches.
+    assert!(matches(&filters, &program_data(1, Some(authority))));
+    assert!(matches(&filters, &program_data(u64::MAX, Some(authority))));
+    assert!(!matches(&filters, &program_data(1, None)));
+    assert!(!matches(
+        &filters,
+        &LoaderState::Buffer {
+            authority: Some(authority)
+        }
+        .to_bytes()
+    ));
+
+    let mut expected_authority = vec![1];
+    expected_authority.extend_from_slice(authority.as_ref());
+    assert_eq!(
+        filters[1],
+        AccountFilter::Memcmp {
+            offset: 12,
+            bytes: expected_authority,
+        }
+    );
+    assert_eq!(
+        filters[0].to_json(),
+        serde_json::json!({
+            "memcmp": { "offset": 0, "bytes": "AwAAAA==", "encoding": "base64" }
+        })
+    );
+    assert_eq!(
+        ProgramDataFiltersBuilder::new().max_data_len(100).build()[1].to_json(),
+        serde_json::json!({ "dataSize": 145 })
+    );
+}
@@ -0,0 +1,80 @@
+use {
+    solana_address::Address,
+    solana_loader_v3_program_client::{
+        guard::{classify, close_program, lint, make_immutable, Irreversible, IrreversibleAction},
+        instruction::set_authority,
+        instructions::{CloseBuilder, SetAuthorityBuilder, SetAuthorityCheckedBuilder},
+        state::find_program_data_address,
+    },
+};
+
+#[test]
+fn guarded_builders_require_acknowledgement() {
+    let program = Address::new_unique();
+    let authority = Address::new_unique();
+
+    let immutable = make_immutable(&program, &authority, Irreversible::acknowledge());
+    assert_eq!(
+        immutable,
+        set_authority(&find_program_data_address(&program), &authority, None)
+    );
+    assert_eq!(
+        classify(&immutable),
+        Some(IrreversibleAction::RemoveAuthority {
+            account: find_program_data_address(&program)
+        })
+    );
+
+    let close = close_program(
+        &program,
+        &Address::new_unique(),
+        &authority,
+        Irreversible::acknowledge(),
+    );
+    assert_eq!(
+        classify(&close),
+        Some(IrreversibleAction::CloseProgram { program })
+    );
+}
+
+#[test]
+fn lint_flags_only_irreversible_instructions() {
+    let account = Address::new_unique();
+    let authority = Address::new_unique();
+    let program = Address::new_unique();
+    let instructions = [
+        set_authority(&account, &authority, Some(&Address::new_unique())),
+        // The generated builder without a new authority passes the loader
+        // ID, which nobody can sign for.
+        SetAuthorityBuilder::new()
+            .buffer_or_program_data_account(account)
+            .current_authority(authority)
+            .instruction(),
+        SetAuthorityCheckedBuilder::new()
+            .buffer_or_program_data_account(account)
+            .current_authority(authority)
+            .new_authority(Address::new_unique())
+            .instruction(),
+        // Closing a buffer leaves nothing behind.
+        CloseBuilder::new()
+            .buffer_or_program_data_account(account)
+            .destination_account(authority)
+            .authority(Some(authority))
+            .instruction(),
+        CloseBuilder::new()
+            .buffer_or_program_data_account(find_program_data_address(&program))
+            .destination_account(authority)
+            .authority(Some(authority))
+            .program_account(Some(program))
+            .instruction(),
+        solana_system_interface::instruction::transfer(&authority, &account, 1),
+    ];
+
+    assert_eq!(
+        lint(&instructions),
+        [
+            (1, IrreversibleAction::RemoveAuthority { account }),
+            (4, IrreversibleAction::CloseProgram { program }),
+        ]
+    );
+}
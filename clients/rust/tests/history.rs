@@ -0,0 +1,386 @@
+mod common;
+
+use {
+    base64::{prelude::BASE64_STANDARD, Engine},
+    common::svm::{ASSOCIATED_TOKEN, MEMO},
+    serde_json::{json, Value},
+    solana_address::Address,
+    solana_clock::Clock,
+    solana_instruction::Instruction,
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{
+        history::{ConfirmedTransaction, HistoryError, HistoryEvent, HistoryIndexer},
+        instruction::set_authority,
+        instructions::{SetAuthorityCheckedBuilder, UpgradeBuilder},
+        plan::{DeployPlanBuilder, Plan, UpgradePlanBuilder},
+        rpc::LoaderRpc,
+        state::find_program_data_address,
+        svm::SvmRpc,
+        verify::ProgramHash,
+    },
+    solana_message::{
+        v0::{self, LoadedAddresses},
+        AddressLookupTableAccount, Message, VersionedMessage,
+    },
+    solana_signer::Signer,
+    solana_transaction::{versioned::VersionedTransaction, Transaction},
+};
+
+/// LiteSVM recording every transaction it is sent.
+struct Archive {
+    rpc: SvmRpc,
+    payer: Keypair,
+    transactions: Vec<ConfirmedTransaction>,
+}
+
+impl Archive {
+    fn new() -> Self {
+        let mut rpc = SvmRpc::new();
+        let payer = Keypair::new();
+        rpc.svm.airdrop(&payer.pubkey(), 10_000_000_000).unwrap();
+        Self {
+            rpc,
+            payer,
+            transactions: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, instructions: &[Instruction], keypairs: &[&dyn Signer]) {
+        let transaction = self.rpc.transaction(instructions, &self.payer, keypairs);
+        let slot = self.rpc.svm.get_sysvar::<Clock>().slot;
+        let failed = self.rpc.process_transaction(transaction.clone()).is_err();
+        self.transactions.push(ConfirmedTransaction {
+            slot,
+            transaction: transaction.into(),
+            loaded_addresses: LoadedAddresses::default(),
+            failed,
+        });
+    }
+
+    fn send_plan(&mut self, plan: &Plan, keypairs: &[&dyn Signer]) {
+        for step in &plan.steps {
+            self.send(&step.instructions, keypairs);
+        }
+    }
+}
+
+#[test]
+fn indexes_program_timeline() {
+    let mut archive = Archive::new();
+    let payer = archive.payer.pubkey();
+    let rent = archive.rpc.get_rent().unwrap();
+    let memo = archive.rpc.get_account(&MEMO).unwrap().unwrap().data;
+    let ata = archive
+        .rpc
+        .get_account(&ASSOCIATED_TOKEN)
+        .unwrap()
+        .unwrap()
+        .data;
+    let program = Keypair::new();
+    let buffer = Keypair::new();
+    let deploy = DeployPlanBuilder::new()
+        .payer(payer)
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .rent(rent.clone())
+        .max_data_len(ata.len() as u64)
+        .build(&memo);
+    archive.send_plan(&deploy, &[&program, &buffer]);
+    let program = program.pubkey();
+    let program_data = find_program_data_address(&program);
+
+    let upgrade_buffer = Keypair::new();
+    let upgrade = UpgradePlanBuilder::new()
+        .payer(payer)
+        .program(program)
+        .buffer(upgrade_buffer.pubkey())
+        .rent(rent.clone())
+        .build(&ata);
+    archive.send_plan(&upgrade, &[&upgrade_buffer]);
+
+    let new_authority = Keypair::new();
+    let handover = SetAuthorityCheckedBuilder::new()
+        .buffer_or_program_data_account(program_data)
+        .current_authority(payer)
+        .new_authority(new_authority.pubkey())
+        .instruction();
+    archive.send(&[handover], &[&new_authority]);
+    archive.send(
+        &[set_authority(&program_data, &new_authority.pubkey(), None)],
+        &[&new_authority],
+    );
+    // Failed transactions changed nothing.
+    let rejected = UpgradeBuilder::new()
+        .program_data_account(program_data)
+        .program_account(program)
+        .buffer_account(upgrade_buffer.pubkey())
+        .spill_account(payer)
+        .authority(payer)
+        .instruction();
+    archive.send(&[rejected], &[]);
+    assert!(archive.transactions.last().unwrap().failed);
+
+    let mut indexer = HistoryIndexer::new();
+    indexer.index_all(&archive.transactions);
+    let history = indexer.into_history();
+    assert_eq!(history.programs.len(), 1);
+    let timeline = history.timeline(&program);
+    let slot = |index: usize| archive.transactions[index].slot;
+    let deploy_index = deploy.steps.len() - 1;
+    let upgrade_index = deploy_index + upgrade.steps.len();
+    assert_eq!(
+        timeline
+            .iter()
+            .map(|entry| (
+                entry.slot,
+                entry.event,
+                entry.signer,
+                entry.old_authority,
+                entry.new_authority,
+                entry.buffer,
+                entry.elf_hash,
+            ))
+            .collect::<Vec<_>>(),
+        [
+            (
+                slot(deploy_index),
+                HistoryEvent::Deploy,
+                payer,
+                None,
+                Some(payer),
+                Some(buffer.pubkey()),
+                Some(ProgramHash::of_elf(&memo)),
+            ),
+            (
+                slot(upgrade_index),
+                HistoryEvent::Upgrade,
+                payer,
+                None,
+                None,
+                Some(upgrade_buffer.pubkey()),
+                Some(ProgramHash::of_elf(&ata)),
+            ),
+            (
+                slot(upgrade_index + 1),
+                HistoryEvent::SetAuthority,
+                payer,
+                Some(payer),
+                Some(new_authority.pubkey()),
+                None,
+                None,
+            ),
+            (
+                slot(upgrade_index + 2),
+                HistoryEvent::SetAuthority,
+                new_authority.pubkey(),
+                Some(new_authority.pubkey()),
+                None,
+                None,
+                None,
+            ),
+        ]
+    );
+    assert_eq!(
+        timeline[1].signature,
+        archive.transactions[upgrade_index].transaction.signatures[0]
+    );
+
+    let csv = history.to_csv();
+    let rows: Vec<_> = csv.lines().collect();
+    assert_eq!(
+        rows[0],
+        "program,slot,signature,event,signer,old_authority,new_authority,buffer,elf_hash"
+    );
+    assert_eq!(
+        rows[4],
+        format!(
+            "{program},{},{},set-authority,{},{},,,",
+            timeline[3].slot,
+            timeline[3].signature,
+            new_authority.pubkey(),
+            new_authority.pubkey()
+        )
+    );
+    assert_eq!(rows.len(), 5);
+
+    let json: Value = serde_json::from_str(&history.to_json()).unwrap();
+    assert_eq!(json["programs"][0]["program"], program.to_string());
+    assert_eq!(
+        json["programs"][0]["timeline"][1],
+        json!({
+            "slot": timeline[1].slot,
+            "signature": timeline[1].signature.to_string(),
+            "event": "upgrade",
+            "signer": payer.to_string(),
+            "oldAuthority": null,
+            "newAuthority": null,
+            "buffer": upgrade_buffer.pubkey().to_string(),
+            "elfHash": ProgramHash::of_elf(&ata).to_string(),
+        })
+    );
+}
+
+#[test]
+fn reads_transaction_files() {
+    let payer = Keypair::new();
+    let program = Address::new_unique();
+    let new_authority = Address::new_unique();
+    let transaction = Transaction::new(
+        &[&payer],
+        Message::new(
+            &[set_authority(
+                &find_program_data_address(&program),
+                &payer.pubkey(),
+                Some(&new_authority),
+            )],
+            Some(&payer.pubkey()),
+        ),
+        Default::default(),
+    );
+    let encoded = BASE64_STANDARD.encode(bincode::serialize(&transaction).unwrap());
+    let response = |slot: u64, err: Value| {
+        json!({
+            "slot": slot,
+            "blockTime": null,
+            "transaction": [encoded, "base64"],
+            "meta": { "err": err, "fee": 5000 },
+            "version": "legacy",
+        })
+    };
+
+    let one = ConfirmedTransaction::from_json(&response(7, Value::Null).to_string()).unwrap();
+    assert_eq!(
+        one,
+        [ConfirmedTransaction {
+            slot: 7,
+            transaction: transaction.clone().into(),
+            loaded_addresses: LoadedAddresses::default(),
+            failed: false,
+        }]
+    );
+    let many = json!([
+        response(7, Value::Null),
+        response(8, json!({ "InstructionError": [0, "Immutable"] })),
+    ]);
+    let path = std::env::temp_dir().join(format!("history-{program}.json"));
+    std::fs::write(&path, many.to_string()).unwrap();
+    let transactions = ConfirmedTransaction::read_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        transactions.iter().map(|tx| tx.failed).collect::<Vec<_>>(),
+        [false, true]
+    );
+    assert!(matches!(
+        ConfirmedTransaction::read_file(&path),
+        Err(HistoryError::Io { path: missing, .. }) if missing == path
+    ));
+
+    // Authority changes name only the ProgramData account, so the program
+    // must be known beforehand.
+    let mut indexer = HistoryIndexer::new();
+    indexer.index_all(&transactions);
+    assert!(indexer.history().programs.is_empty());
+    indexer.track(program).index_all(transactions);
+    let timeline = indexer.history().timeline(&program);
+    assert_eq!(timeline.len(), 1);
+    assert_eq!(timeline[0].slot, 7);
+    assert_eq!(timeline[0].new_authority, Some(new_authority));
+
+    let mut json_encoded = response(7, Value::Null);
+    json_encoded["transaction"][1] = json!("json");
+    assert_eq!(
+        ConfirmedTransaction::from_json(&json_encoded.to_string()),
+        Err(HistoryError::UnsupportedEncoding("json".to_string()))
+    );
+    let mut versioned = response(7, Value::Null);
+    versioned["version"] = json!(1);
+    assert_eq!(
+        ConfirmedTransaction::from_json(&versioned.to_string()),
+        Err(HistoryError::UnsupportedVersion("1".to_string()))
+    );
+    assert!(matches!(
+        ConfirmedTransaction::from_json("{}"),
+        Err(HistoryError::InvalidFile(_))
+    ));
+}
+
+#[test]
+fn reads_files_mixing_legacy_and_v0_transactions() {
+    let payer = Keypair::new();
+    let program = Address::new_unique();
+    let program_data = find_program_data_address(&program);
+    let first = Keypair::new();
+    let second = Address::new_unique();
+    let legacy = Transaction::new(
+        &[&payer],
+        Message::new(
+            &[set_authority(
+                &program_data,
+                &payer.pubkey(),
+                Some(&first.pubkey()),
+            )],
+            Some(&payer.pubkey()),
+        ),
+        Default::default(),
+    );
+    // The new authority is loaded from a lookup table.
+    let table = AddressLookupTableAccount {
+        key: Address::new_unique(),
+        addresses: vec![second],
+    };
+    let message = v0::Message::try_compile(
+        &payer.pubkey(),
+        &[set_authority(&program_data, &first.pubkey(), Some(&second))],
+        &[table],
+        Default::default(),
+    )
+    .unwrap();
+    assert_eq!(message.address_table_lookups.len(), 1);
+    let v0 =
+        VersionedTransaction::try_new(VersionedMessage::V0(message), &[&payer, &first]).unwrap();
+    let encode = |bytes: Vec<u8>| BASE64_STANDARD.encode(bytes);
+    let file = json!([
+        {
+            "slot": 7,
+            "transaction": [encode(bincode::serialize(&legacy).unwrap()), "base64"],
+            "meta": { "err": null },
+            "version": "legacy",
+        },
+        {
+            "slot": 8,
+            "transaction": [encode(bincode::serialize(&v0).unwrap()), "base64"],
+            "meta": {
+                "err": null,
+                "loadedAddresses": { "writable": [], "readonly": [second.to_string()] },
+            },
+            "version": 0,
+        },
+    ]);
+
+    let transactions = ConfirmedTransaction::from_json(&file.to_string()).unwrap();
+    assert_eq!(transactions[1].transaction, v0);
+    assert_eq!(transactions[1].loaded_addresses.readonly, [second]);
+    let mut indexer = HistoryIndexer::new();
+    indexer.track(program).index_all(&transactions);
+    assert_eq!(
+        indexer
+            .history()
+            .timeline(&program)
+            .iter()
+            .map(|entry| (entry.slot, entry.old_authority, entry.new_authority))
+            .collect::<Vec<_>>(),
+        [
+            (7, Some(payer.pubkey()), Some(first.pubkey())),
+            (8, Some(first.pubkey()), Some(second)),
+        ]
+    );
+
+    // Without the loaded addresses, the accounts of a v0 transaction
+    // cannot be resolved.
+    let mut unresolved = file;
+    unresolved[1]["meta"] = json!({ "err": null });
+    assert!(matches!(
+        ConfirmedTransaction::from_json(&unresolved.to_string()),
+        Err(HistoryError::InvalidFile(_))
+    ));
+}
@@ -0,0 +1,87 @@
+use {
+    solana_address::Address,
+    solana_loader_v3_program_client::{
+        instruction::{close_program, set_authority, LoaderInstruction},
+        instructions::{ExtendProgramInstructionArgs, WriteBuilder, WriteInstructionArgs},
+        state::find_program_data_address,
+    },
+};
+
+#[test]
+fn set_authority_omits_missing_new_authority() {
+    let account = Address::new_unique();
+    let current = Address::new_unique();
+    let new = Address::new_unique();
+
+    let instruction = set_authority(&account, &current, Some(&new));
+    assert_eq!(instruction.accounts.len(), 3);
+    assert_eq!(instruction.accounts[2].pubkey, new);
+    assert!(!instruction.accounts[2].is_signer);
+
+    // The loader would take a placeholder third account as the new
+    // authority.
+    let instruction = set_authority(&account, &current, None);
+    assert_eq!(instruction.accounts.len(), 2);
+    assert_eq!(instruction.accounts[1].pubkey, current);
+    assert!(instruction.accounts[1].is_signer);
+}
+
+#[test]
+fn close_program_marks_program_writable() {
+    let program = Address::new_unique();
+    let recipient = Address::new_unique();
+    let authority = Address::new_unique();
+
+    let instruction = close_program(&program, &recipient, &authority);
+    let accounts: Vec<_> = instruction
+        .accounts
+        .iter()
+        .map(|meta| (meta.pubkey, meta.is_signer, meta.is_writable))
+        .collect();
+    assert_eq!(
+        accounts,
+        [
+            (find_program_data_address(&program), false, true),
+            (recipient, false, true),
+            (authority, true, false),
+            (program, false, true),
+        ]
+    );
+}
+
+#[test]
+fn decodes_instruction_data() {
+    let write = WriteBuilder::new()
+        .buffer_account(Address::new_unique())
+        .buffer_authority(Address::new_unique())
+        .offset(7)
+        .bytes(vec![1, 2, 3].into())
+        .instruction();
+    assert_eq!(
+        LoaderInstruction::of(&write),
+        Some(LoaderInstruction::Write(WriteInstructionArgs {
+            offset: 7,
+            bytes: vec![1, 2, 3].into(),
+        }))
+    );
+
+    // Trailing bytes are ignored, truncated arguments are not.
+    assert_eq!(
+        LoaderInstruction::unpack(&[6, 0, 0, 0, 16, 0, 0, 0, 0xff]),
+        Some(LoaderInstruction::ExtendProgram(
+            ExtendProgramInstructionArgs {
+                additional_bytes: 16
+            }
+        ))
+    );
+    assert_eq!(LoaderInstruction::unpack(&[6, 0, 0, 0, 16]), None);
+    assert_eq!(LoaderInstruction::unpack(&[9, 0, 0, 0]), None);
+    assert_eq!(
+        LoaderInstruction::of(&close_program(
+            &Address::new_unique(),
+            &Address::new_unique(),
+            &Address::new_unique()
+        )),
+        Some(LoaderInstruction::Close)
+    );
+}
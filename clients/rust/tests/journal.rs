@@ -0,0 +1,130 @@
+mod common;
+
+use {
+    common::svm::MEMO,
+    solana_address::Address,
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{
+        journal::{Journal, JournalError, JournalWriter, Operation, Status},
+        plan::{DeployPlanBuilder, StepKind},
+        rpc::LoaderRpc,
+        state::{find_program_data_address, ProgramData},
+        svm::SvmRpc,
+        verify::ProgramHash,
+    },
+    solana_signature::Signature,
+    solana_signer::Signer,
+};
+
+#[test]
+fn reads_back_journal_and_ignores_torn_last_line() {
+    let elf = vec![7; 3000];
+    let plan = DeployPlanBuilder::new()
+        .payer(Address::new_unique())
+        .program(Address::new_unique())
+        .buffer(Address::new_unique())
+        .build(&elf);
+    let mut writer = JournalWriter::create(Vec::new(), &plan, &elf).unwrap();
+    writer
+        .confirmed(StepKind::CreateBuffer, Signature::from([1; 64]))
+        .unwrap();
+    writer
+        .confirmed(plan.steps[2].kind, Signature::from([2; 64]))
+        .unwrap();
+    writer.failed("transaction expired").unwrap();
+    let journal = writer.journal().clone();
+    assert_eq!(journal.operation, Operation::Deploy);
+    assert_eq!(journal.elf_hash, ProgramHash::of_elf(&elf));
+    assert_eq!(journal.chunks.len(), 3);
+    assert_eq!(
+        journal.status,
+        Status::Failed("transaction expired".to_string())
+    );
+
+    let mut bytes = writer.into_inner();
+    assert_eq!(Journal::read(bytes.as_slice()), Ok(journal.clone()));
+    let text = String::from_utf8(bytes.clone()).unwrap();
+    assert_eq!(text.lines().count(), 4);
+    assert!(text.lines().all(|line| line.starts_with(r#"{"event":"#)));
+
+    // A crash in the middle of a write leaves a partial last line.
+    bytes.extend_from_slice(br#"{"event":"confirmed","step":"wri"#);
+    assert_eq!(Journal::read(bytes.as_slice()), Ok(journal));
+    bytes.push(b'\n');
+    assert!(matches!(
+        Journal::read(bytes.as_slice()),
+        Err(JournalError::InvalidLine { line: 5, .. })
+    ));
+    assert_eq!(Journal::read(&b""[..]), Err(JournalError::MissingHeader));
+}
+
+#[test]
+fn resumes_interrupted_deploy_from_buffer_contents() {
+    let mut rpc = SvmRpc::new();
+    let payer = Keypair::new();
+    rpc.svm.airdrop(&payer.pubkey(), 10_000_000_000).unwrap();
+    let elf = rpc.get_account(&MEMO).unwrap().unwrap().data;
+    let program = Keypair::new();
+    let buffer = Keypair::new();
+    let plan = DeployPlanBuilder::new()
+        .payer(payer.pubkey())
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .rent(rpc.get_rent().unwrap())
+        .build(&elf);
+
+    // The process dies after sending the first ten writes, having journaled
+    // only eight of them.
+    let mut writer = JournalWriter::create(Vec::new(), &plan, &elf).unwrap();
+    for (index, step) in plan.steps[..11].iter().enumerate() {
+        let signature = rpc
+            .send(&step.instructions, &payer, &[&buffer])
+            .unwrap()
+            .signature;
+        if index < 9 {
+            writer.confirmed(step.kind, signature).unwrap();
+        }
+    }
+    let journal = Journal::read(writer.into_inner().as_slice()).unwrap();
+    assert_eq!(journal.confirmed.len(), 9);
+    assert_eq!(journal.status, Status::InProgress);
+
+    let buffer_account = rpc.get_account(&journal.buffer).unwrap().unwrap();
+    let resumed = journal
+        .resume(&plan, &elf, Some(&buffer_account.data))
+        .unwrap();
+    assert_eq!(resumed.steps, plan.steps[11..]);
+
+    let mut patched = elf.clone();
+    patched[0] ^= 1;
+    assert!(matches!(
+        journal.resume(&plan, &patched, Some(&buffer_account.data)),
+        Err(JournalError::ElfMismatch { .. })
+    ));
+    assert_eq!(
+        journal.resume(&plan, &elf, None),
+        Err(JournalError::BufferMissing(buffer.pubkey()))
+    );
+
+    let mut writer = JournalWriter::append(Vec::new(), journal);
+    for step in &resumed.steps {
+        let signature = rpc
+            .send(&step.instructions, &payer, &[&program])
+            .unwrap()
+            .signature;
+        writer.confirmed(step.kind, signature).unwrap();
+    }
+    writer.succeeded().unwrap();
+    let program_data = rpc
+        .get_account(&find_program_data_address(&program.pubkey()))
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        &ProgramData::unpack(&program_data.data).unwrap().data[..elf.len()],
+        elf.as_slice()
+    );
+
+    // Once deployed, the consumed buffer leaves nothing to resume.
+    let done = writer.journal().resume(&plan, &elf, None).unwrap();
+    assert!(done.steps.is_empty());
+}
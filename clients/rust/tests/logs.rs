@@ -0,0 +1,307 @@
+mod common;
+
+use {
+    common::svm::MEMO,
+    solana_address::Address,
+    solana_instruction::{error::InstructionError, Instruction},
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{
+        buffer::create_buffer,
+        instruction::{close_program, set_authority, LoaderInstruction},
+        instructions::{
+            CloseBuilder, ExtendProgramBuilder, SetAuthorityCheckedBuilder, UpgradeBuilder,
+        },
+        logs::{
+            events, parse_invocations, ClosedAccount, InstructionEvent, Invocation, LoaderEvent,
+            LogError,
+        },
+        plan::DeployPlanBuilder,
+        rpc::LoaderRpc,
+        state::find_program_data_address,
+        svm::SvmRpc,
+    },
+    solana_message::Message,
+    solana_signer::Signer,
+};
+
+const LOADER: &str = "BPFLoaderUpgradeab1e11111111111111111111111";
+const SYSTEM: &str = "11111111111111111111111111111111";
+
+/// Sends `instructions` and returns the events of the transaction.
+fn send(
+    rpc: &mut SvmRpc,
+    instructions: &[Instruction],
+    payer: &Keypair,
+    keypairs: &[&dyn Signer],
+) -> Vec<InstructionEvent> {
+    let transaction = rpc.transaction(instructions, payer, keypairs);
+    let message = transaction.message.clone();
+    let logs = match rpc.process_transaction(transaction) {
+        Ok(meta) => meta.logs,
+        Err(failed) => failed.meta.logs,
+    };
+    events(&message, &logs).unwrap()
+}
+
+/// The events of `send`, without the instructions.
+fn send_events(
+    rpc: &mut SvmRpc,
+    instructions: &[Instruction],
+    payer: &Keypair,
+    keypairs: &[&dyn Signer],
+) -> Vec<(usize, LoaderEvent)> {
+    send(rpc, instructions, payer, keypairs)
+        .into_iter()
+        .map(|event| (event.index, event.event))
+        .collect()
+}
+
+fn lines(logs: &[&str]) -> Vec<String> {
+    logs.iter().map(|line| line.to_string()).collect()
+}
+
+#[test]
+fn reports_events_of_program_lifecycle() {
+    let mut rpc = SvmRpc::new();
+    let payer = Keypair::new();
+    let authority = payer.pubkey();
+    rpc.svm.airdrop(&authority, 10_000_000_000).unwrap();
+    let rent = rpc.get_rent().unwrap();
+    let memo = rpc.get_account(&MEMO).unwrap().unwrap().data;
+    let program = Keypair::new();
+    let buffer = Keypair::new();
+    let plan = DeployPlanBuilder::new()
+        .payer(authority)
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .rent(rent.clone())
+        .build(&memo);
+    let events: Vec<_> = plan
+        .steps
+        .iter()
+        .flat_map(|step| send(&mut rpc, &step.instructions, &payer, &[&program, &buffer]))
+        .collect();
+    let first = &events[0];
+    assert_eq!(first.index, 1);
+    assert_eq!(first.instruction, Some(LoaderInstruction::InitializeBuffer));
+    assert_eq!(
+        first.event,
+        LoaderEvent::BufferInitialized {
+            buffer: buffer.pubkey(),
+            authority: Some(authority),
+        }
+    );
+    let written: usize = events
+        .iter()
+        .filter_map(|event| match event.event {
+            LoaderEvent::BufferWritten { len, .. } => Some(len),
+            _ => None,
+        })
+        .sum();
+    assert_eq!(written, memo.len());
+    assert_eq!(
+        events.last().unwrap().event,
+        LoaderEvent::ProgramDeployed {
+            program: program.pubkey(),
+            buffer: buffer.pubkey(),
+            authority,
+            max_data_len: memo.len() as u64,
+        }
+    );
+
+    let program = program.pubkey();
+    let program_data = find_program_data_address(&program);
+    let buffer = Keypair::new();
+    let mut instructions = create_buffer(&authority, &buffer.pubkey(), &authority, 0, &rent);
+    instructions.push(
+        ExtendProgramBuilder::new()
+            .program_data_account(program_data)
+            .program_account(program)
+            .system_program(Some(solana_system_interface::program::ID))
+            .payer(Some(authority))
+            .additional_bytes(8)
+            .instruction(),
+    );
+    instructions.push(
+        CloseBuilder::new()
+            .buffer_or_program_data_account(buffer.pubkey())
+            .destination_account(authority)
+            .authority(Some(authority))
+            .instruction(),
+    );
+    assert_eq!(
+        send_events(&mut rpc, &instructions, &payer, &[&buffer]),
+        [
+            (
+                1,
+                LoaderEvent::BufferInitialized {
+                    buffer: buffer.pubkey(),
+                    authority: Some(authority),
+                }
+            ),
+            (
+                2,
+                LoaderEvent::ProgramExtended {
+                    program,
+                    additional_bytes: 8,
+                }
+            ),
+            (
+                3,
+                LoaderEvent::AccountClosed {
+                    account: buffer.pubkey(),
+                    kind: ClosedAccount::Buffer,
+                    recipient: authority,
+                }
+            ),
+        ]
+    );
+
+    let new_authority = Keypair::new();
+    let handover = SetAuthorityCheckedBuilder::new()
+        .buffer_or_program_data_account(program_data)
+        .current_authority(authority)
+        .new_authority(new_authority.pubkey())
+        .instruction();
+    let make_immutable = set_authority(&program_data, &new_authority.pubkey(), None);
+    assert_eq!(
+        send_events(
+            &mut rpc,
+            &[handover, make_immutable],
+            &payer,
+            &[&new_authority]
+        ),
+        [
+            (
+                0,
+                LoaderEvent::AuthorityChanged {
+                    account: program_data,
+                    new_authority: Some(new_authority.pubkey()),
+                }
+            ),
+            (
+                1,
+                LoaderEvent::AuthorityChanged {
+                    account: program_data,
+                    new_authority: None,
+                }
+            ),
+        ]
+    );
+
+    // Failures carry the loader's explanation, and later instructions do
+    // not run.
+    let buffer = Keypair::new();
+    let mut instructions = create_buffer(&authority, &buffer.pubkey(), &authority, 1, &rent);
+    instructions.push(
+        UpgradeBuilder::new()
+            .program_data_account(program_data)
+            .program_account(program)
+            .buffer_account(buffer.pubkey())
+            .spill_account(authority)
+            .authority(authority)
+            .instruction(),
+    );
+    instructions.push(close_program(&program, &authority, &authority));
+    assert_eq!(
+        send_events(&mut rpc, &instructions, &payer, &[&buffer]),
+        [
+            (
+                1,
+                LoaderEvent::BufferInitialized {
+                    buffer: buffer.pubkey(),
+                    authority: Some(authority),
+                }
+            ),
+            (
+                2,
+                LoaderEvent::Failed {
+                    error: InstructionError::Immutable.to_string(),
+                    reason: Some("Program not upgradeable".to_string()),
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn checks_logs_against_the_message() {
+    let payer = Address::new_unique();
+    let program = Address::new_unique();
+    let program_data = find_program_data_address(&program);
+    let new_authority = Address::new_unique();
+    let message = Message::new(
+        &[
+            solana_system_interface::instruction::transfer(&payer, &program, 1),
+            set_authority(&program_data, &payer, Some(&new_authority)),
+        ],
+        Some(&payer),
+    );
+    let invoke = |program: &str, depth: u8| format!("Program {program} invoke [{depth}]");
+    let success = |program: &str| format!("Program {program} success");
+    let transfer = [invoke(SYSTEM, 1), success(SYSTEM)];
+    let logs = [
+        transfer[0].clone(),
+        transfer[1].clone(),
+        invoke(LOADER, 1),
+        invoke(SYSTEM, 2),
+        "Program log: nested".to_string(),
+        success(SYSTEM),
+        format!("New authority Some({new_authority})"),
+        success(LOADER),
+    ];
+
+    assert_eq!(
+        parse_invocations(&logs).unwrap(),
+        [
+            Invocation {
+                program: solana_system_interface::program::ID,
+                messages: Vec::new(),
+                result: Some(Ok(())),
+            },
+            Invocation {
+                program: solana_loader_v3_program_client::ID,
+                messages: lines(&[&format!("New authority Some({new_authority})")]),
+                result: Some(Ok(())),
+            },
+        ]
+    );
+    assert_eq!(
+        events(&message, &logs).unwrap(),
+        [InstructionEvent {
+            index: 1,
+            instruction: Some(LoaderInstruction::SetAuthority),
+            event: LoaderEvent::AuthorityChanged {
+                account: program_data,
+                new_authority: Some(new_authority),
+            },
+        }]
+    );
+
+    // Truncated logs end without the result of the last instruction.
+    let truncated = [logs[0].clone(), logs[1].clone(), logs[2].clone()]
+        .into_iter()
+        .chain(lines(&["Log truncated"]))
+        .collect::<Vec<_>>();
+    assert_eq!(events(&message, &truncated).unwrap(), []);
+
+    let other = Address::new_unique();
+    let mut mismatched = logs.to_vec();
+    mismatched[6] = format!("New authority Some({other})");
+    assert_eq!(
+        events(&message, &mismatched),
+        Err(LogError::AccountMismatch(1))
+    );
+    let swapped = [&logs[2..], &transfer[..]].concat();
+    assert_eq!(
+        events(&message, &swapped),
+        Err(LogError::ProgramMismatch {
+            index: 0,
+            program: solana_loader_v3_program_client::ID,
+        })
+    );
+    assert_eq!(
+        parse_invocations(&lines(&["Deployed program", &success(LOADER)])),
+        Err(LogError::UnexpectedLine("Deployed program".to_string()))
+    );
+}
@@ -0,0 +1,223 @@
+mod common;
+
+use {
+    common::svm::{deploy_memo, ASSOCIATED_TOKEN},
+    solana_address::Address,
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{
+        buffer::find_buffer_address,
+        guard::Irreversible,
+        manifest::{reconcile, Action, DesiredState, Manifest, ManifestError, MaxDataLenPolicy},
+        plan::WriteBufferPlanBuilder,
+        rpc::LoaderRpc,
+        state::{find_program_data_address, ProgramData},
+        svm::SvmRpc,
+        verify::ProgramHash,
+    },
+    solana_signer::Signer,
+};
+
+#[test]
+fn parses_manifest_entries() {
+    let authority = Address::new_unique();
+    let program = Address::new_unique();
+    let hash = ProgramHash::of_elf(b"elf");
+    let manifest = Manifest::from_toml(&format!(
+        r#"
+        [[program]]
+        name = "memo"
+        program_id = "{program}"
+        elf = "memo.so"
+        elf_hash = "{hash}"
+        upgrade_authority = "{authority}"
+        max_data_len = {{ headroom_percent = 20 }}
+
+        [[program]]
+        program_id = "{program}"
+        elf_hash = "{hash}"
+        upgrade_authority = "none"
+
+        [[program]]
+        program_id = "{program}"
+        closed = true
+        "#
+    ))
+    .unwrap();
+    let programs = &manifest.programs;
+    assert_eq!(programs[0].name.as_deref(), Some("memo"));
+    assert_eq!(programs[0].elf.as_deref(), Some("memo.so".as_ref()));
+    assert_eq!(programs[0].elf_hash, Some(hash));
+    assert_eq!(programs[0].state, DesiredState::Upgradeable { authority });
+    assert_eq!(
+        programs[0].max_data_len,
+        MaxDataLenPolicy::HeadroomPercent(20)
+    );
+    assert_eq!(programs[1].state, DesiredState::Immutable);
+    assert_eq!(programs[1].max_data_len, MaxDataLenPolicy::Exact);
+    assert_eq!(programs[2].state, DesiredState::Closed);
+
+    // Every live program needs an ELF and an explicit authority.
+    assert!(matches!(
+        Manifest::from_toml(&format!(
+            "[[program]]\nprogram_id = \"{program}\"\nupgrade_authority = \"none\""
+        )),
+        Err(ManifestError::InvalidEntry { index: 0, .. })
+    ));
+    assert!(matches!(
+        Manifest::from_toml(&format!(
+            "[[program]]\nprogram_id = \"{program}\"\nelf = \"a.so\"\nauthority = \"none\""
+        )),
+        Err(ManifestError::InvalidToml(_))
+    ));
+}
+
+#[test]
+fn reconciles_upgrade_extension_and_authority_handover() {
+    let mut rpc = SvmRpc::new();
+    let payer = Keypair::new();
+    rpc.svm.airdrop(&payer.pubkey(), 10_000_000_000).unwrap();
+    let (program, memo) = deploy_memo(&mut rpc, &payer);
+    let ata = rpc.get_account(&ASSOCIATED_TOKEN).unwrap().unwrap().data;
+    let new_authority = Keypair::new();
+
+    let dir = std::env::temp_dir().join(format!("manifest-{program}"));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("program.so"), &ata).unwrap();
+    let manifest = Manifest::from_toml(&format!(
+        r#"
+        [[program]]
+        program_id = "{program}"
+        elf = "program.so"
+        upgrade_authority = "{}"
+        max_data_len = {{ headroom_bytes = 1000 }}
+        "#,
+        new_authority.pubkey()
+    ))
+    .unwrap();
+
+    let [reconciliation] = manifest
+        .reconcile(&rpc, &payer.pubkey(), &dir)
+        .unwrap()
+        .try_into()
+        .unwrap();
+    let buffer = find_buffer_address(&payer.pubkey(), &ata);
+    assert_eq!(
+        reconciliation.actions,
+        [
+            Action::Extend {
+                additional_bytes: (ata.len() + 1000 - memo.len()) as u32,
+                payer: payer.pubkey(),
+            },
+            Action::Upgrade {
+                buffer,
+                elf_hash: ProgramHash::of_elf(&ata),
+                authority: payer.pubkey(),
+                spill: payer.pubkey(),
+            },
+            Action::SetAuthority {
+                current: payer.pubkey(),
+                new: new_authority.pubkey(),
+            },
+        ]
+    );
+    assert!(reconciliation
+        .to_string()
+        .contains(&format!("2. upgrade to ELF {}", ProgramHash::of_elf(&ata))));
+
+    let write = WriteBufferPlanBuilder::new()
+        .payer(payer.pubkey())
+        .seed_buffer()
+        .build(&ata);
+    rpc.send_plan(&write, &payer, &[]).unwrap();
+    for instruction in reconciliation.instructions(None).unwrap() {
+        rpc.send(&[instruction], &payer, &[&new_authority]).unwrap();
+    }
+
+    let account = rpc
+        .get_account(&find_program_data_address(&program))
+        .unwrap()
+        .unwrap();
+    let program_data = ProgramData::unpack(&account.data).unwrap();
+    assert_eq!(program_data.upgrade_authority, Some(new_authority.pubkey()));
+    assert_eq!(program_data.data.len(), ata.len() + 1000);
+    let reconciliations = manifest.reconcile(&rpc, &payer.pubkey(), &dir).unwrap();
+    assert!(reconciliations[0].is_converged());
+    assert_eq!(
+        reconciliations[0].to_string(),
+        format!("{program}: up to date")
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn irreversible_actions_require_acknowledgement() {
+    let mut rpc = SvmRpc::new();
+    let payer = Keypair::new();
+    rpc.svm.airdrop(&payer.pubkey(), 10_000_000_000).unwrap();
+    let (program, memo) = deploy_memo(&mut rpc, &payer);
+    let manifest = |entry: &str| {
+        Manifest::from_toml(&format!("[[program]]\nprogram_id = \"{program}\"\n{entry}"))
+            .unwrap()
+            .programs
+            .remove(0)
+    };
+    let immutable = manifest(&format!(
+        "elf_hash = \"{}\"\nupgrade_authority = \"none\"",
+        ProgramHash::of_elf(&memo)
+    ));
+    let closed = manifest("closed = true");
+    let program_data = |rpc: &SvmRpc| {
+        rpc.get_account(&find_program_data_address(&program))
+            .unwrap()
+            .map(|account| account.data)
+    };
+
+    let close = reconcile(
+        &closed,
+        program_data(&rpc).as_deref(),
+        None,
+        &payer.pubkey(),
+    )
+    .unwrap();
+    assert_eq!(
+        close.actions,
+        [Action::Close {
+            authority: payer.pubkey(),
+            recipient: payer.pubkey(),
+        }]
+    );
+    let reconciliation = reconcile(
+        &immutable,
+        program_data(&rpc).as_deref(),
+        None,
+        &payer.pubkey(),
+    )
+    .unwrap();
+    assert_eq!(
+        reconciliation.actions,
+        [Action::MakeImmutable {
+            current: payer.pubkey()
+        }]
+    );
+    assert!(reconciliation.actions[0].is_irreversible());
+    assert_eq!(
+        reconciliation.instructions(None),
+        Err(ManifestError::Unacknowledged(program))
+    );
+
+    let instructions = reconciliation
+        .instructions(Some(Irreversible::acknowledge()))
+        .unwrap();
+    rpc.send(&instructions, &payer, &[]).unwrap();
+    let data = program_data(&rpc);
+    assert!(
+        reconcile(&immutable, data.as_deref(), None, &payer.pubkey())
+            .unwrap()
+            .is_converged()
+    );
+    // An immutable program can no longer be closed.
+    assert_eq!(
+        reconcile(&closed, data.as_deref(), None, &payer.pubkey()),
+        Err(ManifestError::Immutable(program))
+    );
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use {
+    common::TestElf,
+    solana_loader_v3_program_client::elf::{Contact, MetadataError, ProgramMetadata, SecurityTxt},
+};
+
+const SECURITY_TXT: &[u8] = b"=======BEGIN SECURITY.TXT V1=======\0\
+name\0Example\0\
+project_url\0https://example.com\0\
+contacts\0email:security@example.com, discord:example#1234\0\
+policy\0https://example.com/policy\0\
+source_code\0https://github.com/example/program\0\
+source_revision\0abc123\0\
+auditors\0Auditor One,Auditor Two\0\
+=======END SECURITY.TXT V1=======\0";
+
+#[test]
+fn reads_security_txt_and_build_metadata() {
+    let elf = TestElf::sbf(&[0x95, 0, 0, 0, 0, 0, 0, 0])
+        .with_section(".security.txt", SECURITY_TXT)
+        .with_section(".comment", b"Linker: LLD 19.1.7\0rustc version 1.84.1\0")
+        .build();
+    let metadata = ProgramMetadata::from_elf(&elf).unwrap();
+    let security_txt = metadata.security_txt.unwrap();
+
+    assert_eq!(security_txt.name, "Example");
+    assert_eq!(
+        security_txt.contacts,
+        vec![
+            Contact {
+                kind: "email".to_string(),
+                value: "security@example.com".to_string()
+            },
+            Contact {
+                kind: "discord".to_string(),
+                value: "example#1234".to_string()
+            },
+        ]
+    );
+    assert_eq!(security_txt.source_revision.as_deref(), Some("abc123"));
+    assert_eq!(security_txt.auditors, vec!["Auditor One", "Auditor Two"]);
+    assert_eq!(
+        metadata.build,
+        vec!["Linker: LLD 19.1.7", "rustc version 1.84.1"]
+    );
+}
+
+#[test]
+fn finds_security_txt_outside_its_section() {
+    let elf = TestElf::sbf(&[0x95, 0, 0, 0, 0, 0, 0, 0])
+        .with_section(".rodata", SECURITY_TXT)
+        .build();
+    assert!(SecurityTxt::find(&elf).unwrap().is_some());
+
+    let elf = TestElf::sbf(&[0x95, 0, 0, 0, 0, 0, 0, 0]).build();
+    assert_eq!(SecurityTxt::find(&elf), Ok(None));
+}
+
+#[test]
+fn requires_contacts() {
+    assert_eq!(
+        SecurityTxt::parse(b"name\0x\0project_url\0y\0policy\0z\0"),
+        Err(MetadataError::MissingField("contacts"))
+    );
+}
@@ -0,0 +1,63 @@
+use {
+    solana_address::Address,
+    solana_hash::Hash,
+    solana_loader_v3_program_client::nonce::{DurableNonce, NonceError},
+    solana_nonce::{
+        state::{Data, DurableNonce as StoredNonce, State},
+        versions::Versions,
+    },
+};
+
+fn nonce_account(authority: &Address, blockhash: &Hash) -> Vec<u8> {
+    let state = State::new_initialized(authority, StoredNonce::from_blockhash(blockhash), 5000);
+    bincode::serialize(&Versions::new(state)).unwrap()
+}
+
+#[test]
+fn decodes_stored_blockhash() {
+    let account = Address::new_unique();
+    let authority = Address::new_unique();
+    let data = nonce_account(&authority, &Hash::new_from_array([1; 32]));
+
+    let nonce = DurableNonce::from_account_data(account, &data).unwrap();
+    assert_eq!(nonce.account, account);
+    assert_eq!(nonce.authority, authority);
+    assert_eq!(
+        nonce.blockhash,
+        *StoredNonce::from_blockhash(&Hash::new_from_array([1; 32])).as_hash()
+    );
+    assert_eq!(nonce.verify(&data), Ok(()));
+
+    let uninitialized = bincode::serialize(&Versions::new(State::Uninitialized)).unwrap();
+    assert_eq!(
+        DurableNonce::from_account_data(account, &uninitialized),
+        Err(NonceError::Uninitialized)
+    );
+    let legacy = Versions::Legacy(Box::new(State::Initialized(Data::default())));
+    assert_eq!(
+        DurableNonce::from_account_data(account, &bincode::serialize(&legacy).unwrap()),
+        Err(NonceError::Legacy)
+    );
+}
+
+#[test]
+fn verify_detects_advanced_nonce() {
+    let authority = Address::new_unique();
+    let nonce = DurableNonce::from_account_data(
+        Address::new_unique(),
+        &nonce_account(&authority, &Hash::new_from_array([1; 32])),
+    )
+    .unwrap();
+
+    // The account was advanced after the nonce was decoded.
+    let advanced = nonce_account(&authority, &Hash::new_from_array([2; 32]));
+    assert!(matches!(
+        nonce.verify(&advanced),
+        Err(NonceError::BlockhashMismatch { .. })
+    ));
+    let other_authority = nonce_account(&Address::new_unique(), &Hash::new_from_array([1; 32]));
+    assert!(matches!(
+        nonce.verify(&other_authority),
+        Err(NonceError::AuthorityMismatch { .. })
+    ));
+}
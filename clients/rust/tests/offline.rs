@@ -0,0 +1,249 @@
+use {
+    solana_address::Address,
+    solana_hash::Hash,
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{
+        instruction::close_program,
+        instructions::{SetAuthorityCheckedBuilder, UpgradeBuilder, WriteBuilder},
+        nonce::DurableNonce,
+        offline::{OfflineError, OfflineOperation, OfflineTransaction},
+        state::find_program_data_address,
+    },
+    solana_signer::Signer,
+};
+
+struct Upgrade {
+    payer: Keypair,
+    authority: Keypair,
+    nonce: DurableNonce,
+    instruction: solana_instruction::Instruction,
+}
+
+/// An `Upgrade` whose authority also advances the nonce, paid for by a
+/// separate fee payer.
+fn new_upgrade() -> Upgrade {
+    let payer = Keypair::new();
+    let authority = Keypair::new();
+    let program = Address::new_unique();
+    let nonce = DurableNonce {
+        account: Address::new_unique(),
+        authority: authority.pubkey(),
+        blockhash: Hash::new_from_array([3; 32]),
+    };
+    let instruction = UpgradeBuilder::new()
+        .program_data_account(find_program_data_address(&program))
+        .program_account(program)
+        .buffer_account(Address::new_unique())
+        .spill_account(payer.pubkey())
+        .authority(authority.pubkey())
+        .instruction();
+    Upgrade {
+        payer,
+        authority,
+        nonce,
+        instruction,
+    }
+}
+
+#[test]
+fn signs_offline_and_merges_partial_files() {
+    let upgrade = new_upgrade();
+    let unsigned = OfflineTransaction::new(
+        upgrade.instruction.clone(),
+        &upgrade.payer.pubkey(),
+        &upgrade.nonce,
+    )
+    .unwrap();
+    assert_eq!(
+        unsigned.signers(),
+        [upgrade.payer.pubkey(), upgrade.authority.pubkey()]
+    );
+    assert_eq!(unsigned.message().recent_blockhash, upgrade.nonce.blockhash);
+    let file = unsigned.to_json();
+
+    // Each party signs its own copy of the file.
+    let mut authority_copy = OfflineTransaction::from_json(&file).unwrap();
+    authority_copy.sign(&upgrade.authority).unwrap();
+    assert_eq!(authority_copy.missing_signers(), [upgrade.payer.pubkey()]);
+    let authority_file = authority_copy.to_json();
+
+    let mut payer_copy = OfflineTransaction::from_json(&file).unwrap();
+    payer_copy.sign(&upgrade.payer).unwrap();
+    let payer_file = payer_copy.to_json();
+
+    // The partial files are merged into a fully signed transaction.
+    let mut merged = OfflineTransaction::from_json(&payer_file).unwrap();
+    merged
+        .merge(&OfflineTransaction::from_json(&authority_file).unwrap())
+        .unwrap();
+    assert!(merged.is_fully_signed());
+    let transaction = merged
+        .into_transaction(&upgrade.instruction, &upgrade.nonce)
+        .unwrap();
+    assert!(transaction.verify().is_ok());
+    assert!(transaction.is_signed());
+}
+
+#[test]
+fn file_round_trips() {
+    let upgrade = new_upgrade();
+    let mut transaction =
+        OfflineTransaction::new(upgrade.instruction, &upgrade.payer.pubkey(), &upgrade.nonce)
+            .unwrap();
+    transaction.sign(&upgrade.authority).unwrap();
+
+    let json = transaction.to_json();
+    assert_eq!(OfflineTransaction::from_json(&json).unwrap(), transaction);
+    assert_eq!(
+        OfflineTransaction::from_json(&json).unwrap().to_json(),
+        json
+    );
+}
+
+#[test]
+fn supports_set_authority_checked_and_close() {
+    let payer = Address::new_unique();
+    let authority = Address::new_unique();
+    let nonce = DurableNonce {
+        account: Address::new_unique(),
+        authority,
+        blockhash: Hash::new_from_array([1; 32]),
+    };
+    let set_authority = SetAuthorityCheckedBuilder::new()
+        .buffer_or_program_data_account(Address::new_unique())
+        .current_authority(authority)
+        .new_authority(Address::new_unique())
+        .instruction();
+    let close = close_program(&Address::new_unique(), &payer, &authority);
+    assert_eq!(
+        OfflineOperation::of(&set_authority),
+        Ok(OfflineOperation::SetAuthorityChecked)
+    );
+    assert_eq!(OfflineOperation::of(&close), Ok(OfflineOperation::Close));
+
+    // The new authority of a checked change must sign too.
+    let transaction = OfflineTransaction::new(set_authority.clone(), &payer, &nonce).unwrap();
+    assert_eq!(
+        transaction.signers(),
+        [payer, authority, set_authority.accounts[2].pubkey]
+    );
+    let transaction = OfflineTransaction::new(close, &payer, &nonce).unwrap();
+    assert_eq!(transaction.signers(), [payer, authority]);
+
+    let write = WriteBuilder::new()
+        .buffer_account(Address::new_unique())
+        .buffer_authority(authority)
+        .offset(0)
+        .bytes(vec![1, 2, 3].into())
+        .instruction();
+    assert_eq!(
+        OfflineTransaction::new(write, &payer, &nonce),
+        Err(OfflineError::UnsupportedInstruction)
+    );
+}
+
+#[test]
+fn rejects_foreign_and_conflicting_signatures() {
+    let upgrade = new_upgrade();
+    let mut transaction =
+        OfflineTransaction::new(upgrade.instruction, &upgrade.payer.pubkey(), &upgrade.nonce)
+            .unwrap();
+
+    // Not a required signer.
+    let stranger = Keypair::new();
+    assert_eq!(
+        transaction.sign(&stranger),
+        Err(OfflineError::NotRequiredSigner(stranger.pubkey()))
+    );
+
+    // A signature over another message.
+    let forged = upgrade.authority.sign_message(b"something else");
+    assert_eq!(
+        transaction.add_signature(upgrade.authority.pubkey(), forged),
+        Err(OfflineError::InvalidSignature(upgrade.authority.pubkey()))
+    );
+
+    // Messages that differ cannot be merged.
+    let other = OfflineTransaction::new(
+        new_upgrade().instruction,
+        &upgrade.payer.pubkey(),
+        &upgrade.nonce,
+    )
+    .unwrap();
+    assert_eq!(
+        transaction.merge(&other),
+        Err(OfflineError::MessageMismatch)
+    );
+}
+
+#[test]
+fn validates_against_expected_instruction() {
+    let upgrade = new_upgrade();
+    let mut transaction = OfflineTransaction::new(
+        upgrade.instruction.clone(),
+        &upgrade.payer.pubkey(),
+        &upgrade.nonce,
+    )
+    .unwrap();
+    transaction.sign(&upgrade.authority).unwrap();
+
+    // Missing the fee payer's signature.
+    assert_eq!(
+        transaction
+            .clone()
+            .into_transaction(&upgrade.instruction, &upgrade.nonce),
+        Err(OfflineError::MissingSignatures(vec![upgrade
+            .payer
+            .pubkey()]))
+    );
+
+    transaction.sign(&upgrade.payer).unwrap();
+
+    // An upgrade with another buffer is not what the signers expect.
+    let mut other_buffer = upgrade.instruction.clone();
+    other_buffer.accounts[2].pubkey = Address::new_unique();
+    assert_eq!(
+        transaction.validate(&other_buffer, &upgrade.nonce),
+        Err(OfflineError::UnexpectedMessage)
+    );
+
+    // Nor is one using another nonce.
+    let other_nonce = DurableNonce {
+        blockhash: Hash::new_from_array([4; 32]),
+        ..upgrade.nonce
+    };
+    assert_eq!(
+        transaction.validate(&upgrade.instruction, &other_nonce),
+        Err(OfflineError::UnexpectedMessage)
+    );
+
+    assert!(transaction
+        .into_transaction(&upgrade.instruction, &upgrade.nonce)
+        .is_ok());
+}
+
+#[test]
+fn rejects_tampered_file() {
+    let upgrade = new_upgrade();
+    let mut transaction =
+        OfflineTransaction::new(upgrade.instruction, &upgrade.payer.pubkey(), &upgrade.nonce)
+            .unwrap();
+    transaction.sign(&upgrade.authority).unwrap();
+    let json = transaction.to_json();
+
+    // Swapping the signers' order no longer matches the message.
+    let payer = upgrade.payer.pubkey().to_string();
+    let authority = upgrade.authority.pubkey().to_string();
+    let swapped = json
+        .replace(&payer, "PAYER")
+        .replace(&authority, &payer)
+        .replace("PAYER", &authority);
+    assert!(matches!(
+        OfflineTransaction::from_json(&swapped),
+        Err(OfflineError::InvalidFile(_))
+    ));
+    assert!(matches!(
+        OfflineTransaction::from_json("{}"),
+        Err(OfflineError::InvalidFile(_))
+    ));
+}
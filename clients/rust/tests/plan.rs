@@ -0,0 +1,182 @@
+use {
+    solana_address::Address,
+    solana_hash::Hash,
+    solana_loader_v3_program_client::{
+        cost::{write_transaction_count, WRITE_CHUNK_SIZE},
+        instructions::{UPGRADE_DISCRIMINATOR, WRITE_DISCRIMINATOR},
+        nonce::DurableNonce,
+        plan::{DeployPlanBuilder, StepKind, UpgradePlanBuilder, WriteBufferPlanBuilder},
+        state::{buffer_account_size, find_program_data_address, PROGRAM_ACCOUNT_SIZE},
+        ID,
+    },
+    solana_rent::Rent,
+};
+
+const ELF_LEN: usize = 2 * WRITE_CHUNK_SIZE + 100;
+
+#[test]
+fn write_buffer_plan_creates_then_writes_in_chunks() {
+    let payer = Address::new_unique();
+    let buffer = Address::new_unique();
+    let authority = Address::new_unique();
+    let elf = vec![7; ELF_LEN];
+
+    let plan = WriteBufferPlanBuilder::new()
+        .payer(payer)
+        .buffer(buffer)
+        .buffer_authority(authority)
+        .build(&elf);
+
+    assert_eq!(plan.buffer, buffer);
+    assert_eq!(plan.steps[0].kind, StepKind::CreateBuffer);
+    assert_eq!(plan.steps[0].signers(), [payer, buffer]);
+    let create = &plan.steps[0].instructions;
+    assert_eq!(create[1].program_id, ID);
+
+    let writes: Vec<_> = plan.writes().collect();
+    assert_eq!(writes.len() as u64, write_transaction_count(ELF_LEN));
+    assert_eq!(
+        writes.iter().map(|step| step.kind).collect::<Vec<_>>(),
+        [
+            StepKind::Write {
+                offset: 0,
+                len: WRITE_CHUNK_SIZE
+            },
+            StepKind::Write {
+                offset: WRITE_CHUNK_SIZE as u32,
+                len: WRITE_CHUNK_SIZE
+            },
+            StepKind::Write {
+                offset: 2 * WRITE_CHUNK_SIZE as u32,
+                len: 100
+            },
+        ]
+    );
+    for write in writes {
+        assert_eq!(write.signers(), [authority]);
+        assert_eq!(
+            write.instructions[0].data[..4],
+            WRITE_DISCRIMINATOR.to_le_bytes()
+        );
+    }
+    assert_eq!(plan.signers(), [payer, buffer, authority]);
+}
+
+#[test]
+fn deploy_plan_funds_accounts_with_rent() {
+    let payer = Address::new_unique();
+    let program = Address::new_unique();
+    let buffer = Address::new_unique();
+    let rent = Rent::default();
+
+    let plan = DeployPlanBuilder::new()
+        .payer(payer)
+        .program(program)
+        .buffer(buffer)
+        .max_data_len(4 * ELF_LEN as u64)
+        .rent(rent.clone())
+        .build(&vec![1; ELF_LEN]);
+
+    // The create-buffer step funds a rent-exempt buffer for the ELF.
+    let create_buffer = &plan.steps[0].instructions[0];
+    let lamports = u64::from_le_bytes(create_buffer.data[4..12].try_into().unwrap());
+    assert_eq!(
+        lamports,
+        rent.minimum_balance(buffer_account_size(ELF_LEN).unwrap())
+    );
+
+    // The deploy step creates the Program account, which must sign, and
+    // deploys with the payer as upgrade authority.
+    let deploy = plan.steps.last().unwrap();
+    assert_eq!(deploy.kind, StepKind::Deploy);
+    assert_eq!(deploy.signers(), [payer, program]);
+    let lamports = u64::from_le_bytes(deploy.instructions[0].data[4..12].try_into().unwrap());
+    assert_eq!(lamports, rent.minimum_balance(PROGRAM_ACCOUNT_SIZE));
+    let accounts: Vec<_> = deploy.instructions[1]
+        .accounts
+        .iter()
+        .map(|meta| meta.pubkey)
+        .collect();
+    assert_eq!(
+        accounts[..4],
+        [payer, find_program_data_address(&program), program, buffer]
+    );
+    assert_eq!(accounts[7], payer);
+    assert_eq!(
+        deploy.instructions[1].data[4..],
+        (4 * ELF_LEN as u64).to_le_bytes()
+    );
+}
+
+#[test]
+fn upgrade_plan_extends_in_a_separate_step() {
+    let payer = Address::new_unique();
+    let program = Address::new_unique();
+    let buffer = Address::new_unique();
+    let authority = Address::new_unique();
+    let spill = Address::new_unique();
+
+    let mut builder = UpgradePlanBuilder::new();
+    builder
+        .payer(payer)
+        .program(program)
+        .buffer(buffer)
+        .upgrade_authority(authority)
+        .spill(spill);
+    let plan = builder.build(&[1; ELF_LEN]);
+    let kinds: Vec<_> = plan.steps.iter().map(|step| step.kind).collect();
+    assert_eq!(kinds[kinds.len() - 1], StepKind::Upgrade);
+    assert!(!kinds.contains(&StepKind::Extend));
+
+    let plan = builder.additional_bytes(512).build(&[1; ELF_LEN]);
+    let steps = &plan.steps[plan.steps.len() - 2..];
+    assert_eq!(steps[0].kind, StepKind::Extend);
+    assert_eq!(steps[0].signers(), [payer]);
+    assert_eq!(steps[0].instructions[0].data[4..], 512u32.to_le_bytes());
+    assert_eq!(steps[1].kind, StepKind::Upgrade);
+    assert_eq!(steps[1].signers(), [authority]);
+    let upgrade = &steps[1].instructions[0];
+    assert_eq!(upgrade.data, UPGRADE_DISCRIMINATOR.to_le_bytes());
+    assert_eq!(upgrade.accounts[3].pubkey, spill);
+}
+
+#[test]
+fn final_step_advances_durable_nonce() {
+    let payer = Address::new_unique();
+    let program = Address::new_unique();
+    let authority = Address::new_unique();
+    let nonce = DurableNonce {
+        account: Address::new_unique(),
+        authority: Address::new_unique(),
+        blockhash: Hash::new_from_array([5; 32]),
+    };
+
+    let plan = UpgradePlanBuilder::new()
+        .payer(payer)
+        .program(program)
+        .buffer(Address::new_unique())
+        .upgrade_authority(authority)
+        .additional_bytes(512)
+        .nonce(nonce)
+        .build(&[1; ELF_LEN]);
+
+    // Only the upgrade step uses the nonce, advancing it first.
+    let (upgrade, others) = plan.steps.split_last().unwrap();
+    assert!(others.iter().all(|step| step.nonce.is_none()));
+    assert_eq!(upgrade.nonce, Some(nonce));
+    assert_eq!(upgrade.instructions[0], nonce.advance_instruction());
+    assert_eq!(upgrade.signers(), [nonce.authority, authority]);
+    assert!(plan.signers().contains(&nonce.authority));
+
+    let plan = DeployPlanBuilder::new()
+        .payer(payer)
+        .program(program)
+        .buffer(Address::new_unique())
+        .nonce(nonce)
+        .build(&[1; ELF_LEN]);
+    let deploy = plan.steps.last().unwrap();
+    assert_eq!(deploy.kind, StepKind::Deploy);
+    assert_eq!(deploy.instructions.len(), 3);
+    assert_eq!(deploy.instructions[0], nonce.advance_instruction());
+    assert_eq!(deploy.signers(), [nonce.authority, payer, program]);
+}
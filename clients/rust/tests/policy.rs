@@ -0,0 +1,119 @@
+use {
+    serde_json::{json, Value},
+    solana_address::Address,
+    solana_loader_v3_program_client::{
+        policy::{BufferState, Inventory, Policy, ProgramState, Rule},
+        state::LoaderState,
+    },
+};
+
+fn program(slot: u64, upgrade_authority: Option<Address>) -> ProgramState {
+    let data = LoaderState::ProgramData {
+        slot,
+        upgrade_authority,
+    }
+    .to_bytes();
+    ProgramState::from_program_data(Address::new_unique(), &data).unwrap()
+}
+
+fn buffer(authority: Option<Address>) -> BufferState {
+    let data = LoaderState::Buffer { authority }.to_bytes();
+    BufferState::from_account_data(Address::new_unique(), &data).unwrap()
+}
+
+#[test]
+fn reports_each_broken_rule() {
+    let multisig = Address::new_unique();
+    let hot_wallet = Address::new_unique();
+    let compliant = program(990, Some(multisig));
+    let hot = program(995, Some(hot_wallet));
+    let stale = program(100, Some(multisig));
+    let allowlisted = program(1, None);
+    let immutable = program(1, None);
+    let upgrade_buffer = buffer(Some(multisig));
+    let foreign_buffer = buffer(Some(Address::new_unique()));
+    let frozen_buffer = buffer(None);
+    let inventory = Inventory {
+        current_slot: 1000,
+        programs: vec![compliant, hot, stale, allowlisted, immutable],
+        buffers: vec![upgrade_buffer, foreign_buffer, frozen_buffer],
+    };
+    let policy = Policy {
+        rules: vec![
+            Rule::AuthorityInSet {
+                allowed: vec![multisig],
+            },
+            Rule::AuthorityRequired {
+                immutable_allowlist: vec![allowlisted.program],
+            },
+            Rule::MaxDeployAge { max_slots: 500 },
+            Rule::BufferAuthorityMatches,
+        ],
+    };
+
+    let report = policy.evaluate(&inventory);
+    assert!(!report.is_compliant());
+    assert_eq!(
+        report
+            .violations
+            .iter()
+            .map(|violation| (violation.rule, violation.account))
+            .collect::<Vec<_>>(),
+        [
+            ("authorityInSet", hot.program),
+            ("authorityRequired", immutable.program),
+            ("maxDeployAge", stale.program),
+            ("bufferAuthorityMatches", foreign_buffer.address),
+            ("bufferAuthorityMatches", frozen_buffer.address),
+        ]
+    );
+    // The hot wallet upgrades a program, so its buffers are accepted here
+    // even though the program itself is flagged.
+    let inventory = Inventory {
+        buffers: vec![buffer(Some(hot_wallet))],
+        ..inventory
+    };
+    assert!(Policy {
+        rules: vec![Rule::BufferAuthorityMatches],
+    }
+    .evaluate(&inventory)
+    .is_compliant());
+}
+
+#[test]
+fn serializes_report_to_json() {
+    let multisig = Address::new_unique();
+    let hot_wallet = Address::new_unique();
+    let hot = program(10, Some(hot_wallet));
+    let inventory = Inventory {
+        current_slot: 20,
+        programs: vec![program(10, Some(multisig)), hot],
+        buffers: vec![buffer(Some(multisig))],
+    };
+    let policy = Policy {
+        rules: vec![Rule::AuthorityInSet {
+            allowed: vec![multisig],
+        }],
+    };
+
+    let report: Value = serde_json::from_str(&policy.evaluate(&inventory).to_json()).unwrap();
+    assert_eq!(
+        report,
+        json!({
+            "compliant": false,
+            "programs": 2,
+            "buffers": 1,
+            "violations": [{
+                "rule": "authorityInSet",
+                "account": hot.program.to_string(),
+                "detail": format!("upgrade authority {hot_wallet} is not allowed"),
+            }],
+        })
+    );
+
+    let compliant = Policy::default().evaluate(&inventory);
+    assert!(compliant.is_compliant());
+    let report: Value = serde_json::from_str(&compliant.to_json()).unwrap();
+    assert_eq!(report["compliant"], true);
+    assert_eq!(report["violations"], json!([]));
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use {
+    common::{
+        svm::{deploy_memo, ASSOCIATED_TOKEN},
+        TestElf,
+    },
+    solana_account::Account,
+    solana_address::Address,
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{
+        instructions::Upgrade,
+        plan::WriteBufferPlanBuilder,
+        preflight::{check_upgrade, UpgradeAccounts, UpgradeViolation},
+        rpc::LoaderRpc,
+        state::{
+            find_program_data_address, LoaderState, CLOCK_SYSVAR_ID, PROGRAM_DATA_HEADER_SIZE,
+            RENT_SYSVAR_ID,
+        },
+        svm::SvmRpc,
+    },
+    solana_rent::Rent,
+    solana_signer::Signer,
+};
+
+fn loader_account(state: LoaderState, elf: &[u8], lamports: u64) -> Account {
+    let mut data = state.to_bytes();
+    data.extend_from_slice(elf);
+    Account {
+        lamports,
+        data,
+        owner: solana_loader_v3_program_client::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+#[test]
+fn lists_every_violated_precondition() {
+    let rent = Rent::default();
+    let authority = Address::new_unique();
+    let program = Address::new_unique();
+    let program_data = find_program_data_address(&program);
+    let buffer = Address::new_unique();
+    let elf = TestElf::sbf(&[0; 64]).build();
+    let upgrade = Upgrade {
+        program_data_account: program_data,
+        program_account: program,
+        buffer_account: buffer,
+        spill_account: authority,
+        rent_sysvar: RENT_SYSVAR_ID,
+        clock_sysvar: CLOCK_SYSVAR_ID,
+        authority,
+    };
+    let program_data_state = |upgrade_authority| LoaderState::ProgramData {
+        slot: 1,
+        upgrade_authority,
+    };
+    let accounts = UpgradeAccounts {
+        program: Some(loader_account(
+            LoaderState::Program { program_data },
+            &[],
+            1,
+        )),
+        program_data: Some(loader_account(
+            program_data_state(Some(authority)),
+            &vec![0; elf.len()],
+            rent.minimum_balance(PROGRAM_DATA_HEADER_SIZE + elf.len()),
+        )),
+        buffer: Some(loader_account(
+            LoaderState::Buffer {
+                authority: Some(authority),
+            },
+            &elf,
+            1,
+        )),
+    };
+    assert_eq!(check_upgrade(&upgrade, &accounts, &rent), []);
+
+    let other = Address::new_unique();
+    let broken = Upgrade {
+        spill_account: buffer,
+        rent_sysvar: CLOCK_SYSVAR_ID,
+        ..upgrade
+    };
+    let mut larger_elf = elf.clone();
+    larger_elf.push(0);
+    let broken_accounts = UpgradeAccounts {
+        program_data: Some(loader_account(program_data_state(None), &elf, 0)),
+        buffer: Some(loader_account(
+            LoaderState::Buffer {
+                authority: Some(other),
+            },
+            &larger_elf,
+            1,
+        )),
+        ..accounts.clone()
+    };
+    let violations = check_upgrade(&broken, &broken_accounts, &rent);
+    assert_eq!(
+        violations,
+        [
+            UpgradeViolation::RentSysvar(CLOCK_SYSVAR_ID),
+            UpgradeViolation::BufferAuthorityMismatch {
+                buffer_authority: other,
+                authority,
+            },
+            UpgradeViolation::Immutable,
+            UpgradeViolation::ExceedsCapacity {
+                buffer_len: elf.len() + 1,
+                capacity: elf.len(),
+            },
+            UpgradeViolation::InsufficientFunds {
+                available: 1,
+                required: rent.minimum_balance(PROGRAM_DATA_HEADER_SIZE + elf.len()),
+            },
+            UpgradeViolation::SpillIsBuffer,
+        ]
+    );
+    assert_eq!(
+        violations[3].to_string(),
+        format!(
+            "buffer holds {} bytes but ProgramData has room for {}; extend the program by 1 bytes",
+            elf.len() + 1,
+            elf.len()
+        )
+    );
+
+    let missing = UpgradeAccounts {
+        program: Some(loader_account(
+            LoaderState::Program {
+                program_data: other,
+            },
+            &[],
+            1,
+        )),
+        buffer: Some(loader_account(
+            LoaderState::Buffer {
+                authority: Some(authority),
+            },
+            &[],
+            1,
+        )),
+        program_data: None,
+    };
+    assert_eq!(
+        check_upgrade(&upgrade, &missing, &rent),
+        [
+            UpgradeViolation::ProgramDataMismatch {
+                expected: other,
+                actual: program_data,
+            },
+            UpgradeViolation::EmptyBuffer,
+            UpgradeViolation::AccountNotFound(program_data),
+        ]
+    );
+}
+
+#[test]
+fn agrees_with_the_loader() {
+    let mut rpc = SvmRpc::new();
+    let payer = Keypair::new();
+    rpc.svm.airdrop(&payer.pubkey(), 10_000_000_000).unwrap();
+    let rent = rpc.get_rent().unwrap();
+    let (program, memo) = deploy_memo(&mut rpc, &payer);
+    let ata = rpc.get_account(&ASSOCIATED_TOKEN).unwrap().unwrap().data;
+
+    // A larger ELF written by another authority fails in two ways.
+    let other = Keypair::new();
+    let buffer = Keypair::new();
+    let write = WriteBufferPlanBuilder::new()
+        .payer(payer.pubkey())
+        .buffer(buffer.pubkey())
+        .buffer_authority(other.pubkey())
+        .rent(rent.clone())
+        .build(&ata);
+    rpc.send_plan(&write, &payer, &[&buffer, &other]).unwrap();
+    let upgrade = Upgrade {
+        program_data_account: find_program_data_address(&program),
+        program_account: program,
+        buffer_account: buffer.pubkey(),
+        spill_account: payer.pubkey(),
+        rent_sysvar: RENT_SYSVAR_ID,
+        clock_sysvar: CLOCK_SYSVAR_ID,
+        authority: payer.pubkey(),
+    };
+    let accounts = UpgradeAccounts::fetch(&rpc, &upgrade).unwrap();
+    assert_eq!(
+        check_upgrade(&upgrade, &accounts, &rent),
+        [
+            UpgradeViolation::BufferAuthorityMismatch {
+                buffer_authority: other.pubkey(),
+                authority: payer.pubkey(),
+            },
+            UpgradeViolation::ExceedsCapacity {
+                buffer_len: ata.len(),
+                capacity: memo.len(),
+            },
+        ]
+    );
+    assert!(rpc.send(&[upgrade.instruction()], &payer, &[]).is_err());
+
+    let buffer = Keypair::new();
+    let write = WriteBufferPlanBuilder::new()
+        .payer(payer.pubkey())
+        .buffer(buffer.pubkey())
+        .rent(rent.clone())
+        .build(&memo);
+    rpc.send_plan(&write, &payer, &[&buffer]).unwrap();
+    let upgrade = Upgrade {
+        buffer_account: buffer.pubkey(),
+        ..upgrade
+    };
+    let accounts = UpgradeAccounts::fetch(&rpc, &upgrade).unwrap();
+    assert_eq!(check_upgrade(&upgrade, &accounts, &rent), []);
+    rpc.send(&[upgrade.instruction()], &payer, &[]).unwrap();
+}
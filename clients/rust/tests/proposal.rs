@@ -0,0 +1,126 @@
+use {
+    solana_address::Address,
+    solana_instruction::{AccountMeta, Instruction},
+    solana_loader_v3_program_client::{
+        instruction::set_authority,
+        instructions::{ExtendProgramBuilder, SetAuthorityCheckedBuilder, UpgradeBuilder},
+        proposal::{Proposal, ProposalError},
+        state::find_program_data_address,
+    },
+    solana_message::VersionedMessage,
+};
+
+fn instructions(vault: &Address) -> Vec<Instruction> {
+    let program = Address::new_unique();
+    let program_data = find_program_data_address(&program);
+    vec![
+        UpgradeBuilder::new()
+            .program_data_account(program_data)
+            .program_account(program)
+            .buffer_account(Address::new_unique())
+            .spill_account(*vault)
+            .authority(*vault)
+            .instruction(),
+        set_authority(&program_data, vault, Some(&Address::new_unique())),
+        set_authority(&program_data, vault, None),
+        ExtendProgramBuilder::new()
+            .program_data_account(program_data)
+            .program_account(program)
+            .system_program(Some(solana_system_interface::program::ID))
+            .payer(Some(*vault))
+            .additional_bytes(1024)
+            .instruction(),
+    ]
+}
+
+#[test]
+fn instruction_json_round_trips() {
+    let vault = Address::new_unique();
+    for instruction in instructions(&vault) {
+        let proposal = Proposal::new(instruction.clone(), vault).unwrap();
+        let json = proposal.to_instruction_json();
+        assert_eq!(proposal.to_instruction_json(), json);
+
+        let parsed = Proposal::from_instruction_json(&json, vault).unwrap();
+        assert_eq!(parsed.instruction, instruction);
+        assert_eq!(parsed.to_instruction_json(), json);
+    }
+
+    let value: serde_json::Value = serde_json::from_str(
+        &Proposal::new(instructions(&vault).remove(0), vault)
+            .unwrap()
+            .to_instruction_json(),
+    )
+    .unwrap();
+    assert_eq!(value["accounts"][6]["pubkey"], vault.to_string());
+    assert_eq!(value["accounts"][6]["isSigner"], true);
+    assert_eq!(value["data"], "AwAAAA==");
+}
+
+#[test]
+fn message_round_trips_with_vault_as_signer() {
+    let vault = Address::new_unique();
+    for instruction in instructions(&vault) {
+        let proposal = Proposal::new(instruction.clone(), vault).unwrap();
+        let encoded = proposal.to_base64_message();
+        assert_eq!(proposal.to_base64_message(), encoded);
+
+        let message = proposal.to_message();
+        assert!(matches!(message, VersionedMessage::V0(_)));
+        assert_eq!(message.header().num_required_signatures, 1);
+        assert_eq!(message.static_account_keys()[0], vault);
+
+        // The vault signs and is writable as fee payer wherever it appears;
+        // everything else is unchanged.
+        let parsed = Proposal::from_base64_message(&encoded).unwrap();
+        assert_eq!(parsed.vault, vault);
+        assert_eq!(parsed.instruction.program_id, instruction.program_id);
+        assert_eq!(parsed.instruction.data, instruction.data);
+        for (parsed, expected) in parsed
+            .instruction
+            .accounts
+            .iter()
+            .zip(&instruction.accounts)
+        {
+            let is_vault = expected.pubkey == vault;
+            let expected = AccountMeta {
+                pubkey: expected.pubkey,
+                is_signer: expected.is_signer || is_vault,
+                is_writable: expected.is_writable || is_vault,
+            };
+            assert_eq!(*parsed, expected);
+        }
+        assert_eq!(parsed.to_base64_message(), encoded);
+    }
+}
+
+#[test]
+fn rejects_instructions_the_vault_cannot_execute() {
+    let vault = Address::new_unique();
+
+    // A checked authority change also needs the new authority's signature.
+    let new_authority = Address::new_unique();
+    let checked = SetAuthorityCheckedBuilder::new()
+        .buffer_or_program_data_account(Address::new_unique())
+        .current_authority(vault)
+        .new_authority(new_authority)
+        .instruction();
+    assert_eq!(
+        Proposal::new(checked, vault),
+        Err(ProposalError::ForeignSigner(new_authority))
+    );
+
+    let transfer = solana_system_interface::instruction::transfer(&vault, &new_authority, 1);
+    assert_eq!(
+        Proposal::new(transfer, vault),
+        Err(ProposalError::NotLoaderInstruction)
+    );
+    assert!(matches!(
+        Proposal::from_base64_message("not base64"),
+        Err(ProposalError::InvalidMessage(_))
+    ));
+    assert!(matches!(
+        Proposal::from_instruction_json("{}", vault),
+        Err(ProposalError::InvalidJson(_))
+    ));
+}
@@ -0,0 +1,96 @@
+use {
+    solana_address::Address,
+    solana_loader_v3_program_client::{
+        instructions::CLOSE_DISCRIMINATOR,
+        reclaim::{ReclaimPlan, StaleBuffer},
+        state::LoaderState,
+    },
+    solana_message::Message,
+    solana_transaction::Transaction,
+};
+
+fn stale(authority: Option<Address>, lamports: u64) -> StaleBuffer {
+    StaleBuffer {
+        address: Address::new_unique(),
+        authority,
+        lamports,
+    }
+}
+
+#[test]
+fn packs_closes_into_full_transactions() {
+    let payer = Address::new_unique();
+    let destination = Address::new_unique();
+    let authorities = [Address::new_unique(), Address::new_unique()];
+    let buffers: Vec<_> = (0..60)
+        .map(|index| stale(Some(authorities[index % 2]), 1_000))
+        .chain([stale(None, 5_000)])
+        .collect();
+
+    let plan = ReclaimPlan::new(&buffers, &payer, &destination);
+    assert_eq!(plan.closed.len(), 60);
+    assert_eq!(plan.skipped, [buffers[60].address]);
+    assert_eq!(plan.recovered_lamports, 60_000);
+    assert_eq!(plan.transactions.iter().map(Vec::len).sum::<usize>(), 60);
+
+    for (index, instructions) in plan.transactions.iter().enumerate() {
+        assert!(instructions
+            .iter()
+            .all(|close| close.data == CLOSE_DISCRIMINATOR.to_le_bytes()
+                && close.accounts[1].pubkey == destination));
+        let size = |instructions: &[_]| {
+            let message = Message::new(instructions, Some(&payer));
+            bincode::serialized_size(&Transaction::new_unsigned(message)).unwrap()
+        };
+        assert!(size(instructions) <= 1232);
+        // No later close would have fit.
+        if let Some(next) = plan.transactions.get(index + 1) {
+            let mut more = instructions.clone();
+            more.push(next[0].clone());
+            assert!(size(&more) > 1232);
+        }
+    }
+
+    // Grouping by authority means only the transaction where the
+    // authorities meet needs both signatures.
+    let two_signers = plan
+        .transactions
+        .iter()
+        .filter(|instructions| {
+            let authorities: std::collections::HashSet<_> = instructions
+                .iter()
+                .map(|close| close.accounts[2].pubkey)
+                .collect();
+            authorities.len() > 1
+        })
+        .count();
+    assert!(two_signers <= 1);
+}
+
+#[test]
+fn decodes_buffer_accounts() {
+    let authority = Address::new_unique();
+    let address = Address::new_unique();
+    let data = LoaderState::Buffer {
+        authority: Some(authority),
+    }
+    .to_bytes();
+
+    assert_eq!(
+        StaleBuffer::from_account_data(address, 42, &data),
+        Ok(StaleBuffer {
+            address,
+            authority: Some(authority),
+            lamports: 42,
+        })
+    );
+    let program = LoaderState::Program {
+        program_data: Address::new_unique(),
+    }
+    .to_bytes();
+    assert!(StaleBuffer::from_account_data(address, 42, &program).is_err());
+    assert_eq!(
+        ReclaimPlan::new(&[], &authority, &authority),
+        ReclaimPlan::default()
+    );
+}
@@ -0,0 +1,139 @@
+use {
+    solana_address::Address,
+    solana_hash::Hash,
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{
+        nonce::DurableNonce,
+        offline::OfflineTransaction,
+        rotation::{AuthorityRotation, RotationError, RotationTarget},
+        state::LoaderState,
+    },
+    solana_signer::Signer,
+};
+
+fn nonce(authority: Address) -> DurableNonce {
+    DurableNonce {
+        account: Address::new_unique(),
+        authority,
+        blockhash: Hash::new_from_array([9; 32]),
+    }
+}
+
+#[test]
+fn collects_both_signatures_across_parties() {
+    let payer = Keypair::new();
+    let current = Keypair::new();
+    let new = Keypair::new();
+    let nonce = nonce(payer.pubkey());
+    let program = Address::new_unique();
+
+    let mut rotation = AuthorityRotation::new(
+        RotationTarget::Program(program),
+        current.pubkey(),
+        new.pubkey(),
+        &payer.pubkey(),
+        &nonce,
+    )
+    .unwrap();
+    // The message orders signers after the fee payer by address.
+    let mut missing = rotation.missing_signers();
+    missing[1..].sort();
+    let mut expected = [payer.pubkey(), current.pubkey(), new.pubkey()];
+    expected[1..].sort();
+    assert_eq!(missing, expected);
+
+    // The incoming team signs its own copy of the transaction file.
+    let mut incoming = OfflineTransaction::from_json(&rotation.transaction().to_json()).unwrap();
+    incoming.sign(&new).unwrap();
+
+    rotation.sign(&payer).unwrap();
+    rotation.sign(&current).unwrap();
+    assert!(rotation.current_authority_signed());
+    assert!(!rotation.new_authority_signed());
+
+    rotation.merge(&incoming).unwrap();
+    assert!(rotation.new_authority_signed());
+    assert!(rotation.missing_signers().is_empty());
+    let transaction = rotation.into_transaction(&nonce).unwrap();
+    assert!(transaction.is_signed());
+}
+
+#[test]
+fn refuses_unchecked_fallback_for_program_data() {
+    let current = Address::new_unique();
+    let new = Address::new_unique();
+    let payer = Address::new_unique();
+    let nonce = nonce(payer);
+
+    let program = AuthorityRotation::new(
+        RotationTarget::Program(Address::new_unique()),
+        current,
+        new,
+        &payer,
+        &nonce,
+    )
+    .unwrap();
+    assert_eq!(
+        program.unchecked_fallback(),
+        Err(RotationError::UncheckedProgramData)
+    );
+
+    let buffer = Address::new_unique();
+    let rotation =
+        AuthorityRotation::new(RotationTarget::Buffer(buffer), current, new, &payer, &nonce)
+            .unwrap();
+    let fallback = rotation.unchecked_fallback().unwrap();
+    assert_eq!(fallback.accounts[0].pubkey, buffer);
+    assert!(!fallback.accounts[2].is_signer);
+
+    assert_eq!(
+        AuthorityRotation::new(
+            RotationTarget::Buffer(buffer),
+            current,
+            current,
+            &payer,
+            &nonce
+        ),
+        Err(RotationError::SameAuthority)
+    );
+}
+
+#[test]
+fn verifies_decoded_upgrade_authority() {
+    let current = Address::new_unique();
+    let new = Address::new_unique();
+    let payer = Address::new_unique();
+    let rotation = AuthorityRotation::new(
+        RotationTarget::Program(Address::new_unique()),
+        current,
+        new,
+        &payer,
+        &nonce(payer),
+    )
+    .unwrap();
+    let program_data = |upgrade_authority| {
+        LoaderState::ProgramData {
+            slot: 1,
+            upgrade_authority,
+        }
+        .to_bytes()
+    };
+
+    assert_eq!(rotation.verify(&program_data(Some(new))), Ok(()));
+    assert_eq!(
+        rotation.verify(&program_data(Some(current))),
+        Err(RotationError::UnexpectedAuthority {
+            expected: new,
+            actual: Some(current),
+        })
+    );
+    assert!(matches!(
+        rotation.verify(
+            &LoaderState::Buffer {
+                authority: Some(new)
+            }
+            .to_bytes()
+        ),
+        Err(RotationError::State(_))
+    ));
+}
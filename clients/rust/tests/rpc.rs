@@ -0,0 +1,72 @@
+mod common;
+
+use {
+    common::svm::MEMO,
+    solana_address::Address,
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{
+        plan::DeployPlanBuilder,
+        rpc::{LoaderRpc, RpcError},
+        state::{find_program_data_address, ProgramData},
+        svm::SvmRpc,
+    },
+    solana_signer::Signer,
+};
+
+#[test]
+fn deploys_and_fetches_program_accounts() {
+    let mut rpc = SvmRpc::new();
+    let payer = Keypair::new();
+    rpc.svm.airdrop(&payer.pubkey(), 10_000_000_000).unwrap();
+    let elf = rpc.get_account(&MEMO).unwrap().unwrap().data;
+    let program = Keypair::new();
+    let buffer = Keypair::new();
+
+    let plan = DeployPlanBuilder::new()
+        .payer(payer.pubkey())
+        .program(program.pubkey())
+        .buffer(buffer.pubkey())
+        .rent(rpc.get_rent().unwrap())
+        .build(&elf);
+    rpc.send_plan(&plan, &payer, &[&program, &buffer]).unwrap();
+
+    let accounts = rpc.get_program(&program.pubkey()).unwrap();
+    assert_eq!(
+        accounts.program_data_address,
+        find_program_data_address(&program.pubkey())
+    );
+    let program_data = ProgramData::unpack(&accounts.program_data.data).unwrap();
+    assert_eq!(program_data.upgrade_authority, Some(payer.pubkey()));
+    assert_eq!(&program_data.data[..elf.len()], elf.as_slice());
+
+    // The buffer was consumed by the deploy.
+    assert_eq!(
+        rpc.get_multiple_accounts(&[buffer.pubkey(), program.pubkey()])
+            .unwrap()
+            .iter()
+            .map(Option::is_some)
+            .collect::<Vec<_>>(),
+        [false, true]
+    );
+}
+
+#[test]
+fn rejects_accounts_not_owned_by_loader() {
+    let mut rpc = SvmRpc::new();
+    let payer = Address::new_unique();
+    rpc.svm.airdrop(&payer, 1_000_000_000).unwrap();
+    let missing = Address::new_unique();
+
+    assert_eq!(
+        rpc.get_loader_account(&payer),
+        Err(RpcError::NotOwnedByLoader(payer))
+    );
+    assert_eq!(
+        rpc.get_loader_account(&missing),
+        Err(RpcError::AccountNotFound(missing))
+    );
+    assert_eq!(
+        rpc.get_program(&missing),
+        Err(RpcError::AccountNotFound(missing))
+    );
+}
@@ -0,0 +1,376 @@
+mod common;
+
+use {
+    common::svm::{ASSOCIATED_TOKEN, MEMO},
+    solana_account::Account,
+    solana_address::Address,
+    solana_clock::Clock,
+    solana_instruction::Instruction,
+    solana_keypair::Keypair,
+    solana_loader_v3_program_client::{
+        buffer::create_buffer,
+        instruction::set_authority,
+        instructions::{
+            CloseBuilder, DeployWithMaxDataLenBuilder, ExtendProgramBuilder,
+            InitializeBufferBuilder, SetAuthorityCheckedBuilder, UpgradeBuilder, WriteBuilder,
+        },
+        plan::{DeployPlanBuilder, Plan, UpgradePlanBuilder},
+        rpc::LoaderRpc,
+        simulate::{SimulationError, Simulator},
+        state::find_program_data_address,
+        svm::SvmRpc,
+    },
+    solana_message::Message,
+    solana_rent::Rent,
+    solana_signer::Signer,
+    solana_transaction::{InstructionError, TransactionError},
+};
+
+/// LiteSVM and a [`Simulator`] holding the same accounts.
+struct Harness {
+    rpc: SvmRpc,
+    simulator: Simulator,
+    payer: Keypair,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let mut rpc = SvmRpc::new();
+        let payer = Keypair::new();
+        rpc.svm.airdrop(&payer.pubkey(), 10_000_000_000).unwrap();
+        let mut simulator = Simulator::new(rpc.get_rent().unwrap());
+        simulator.accounts.insert(
+            payer.pubkey(),
+            rpc.svm.get_account(&payer.pubkey()).unwrap(),
+        );
+        Self {
+            rpc,
+            simulator,
+            payer,
+        }
+    }
+
+    fn elf(&self, program: &Address) -> Vec<u8> {
+        self.rpc.svm.get_account(program).unwrap().data
+    }
+
+    /// Sends `instructions` to both, asserting they agree on the outcome
+    /// and on every writable account afterwards.
+    fn send(
+        &mut self,
+        instructions: &[Instruction],
+        keypairs: &[&dyn Signer],
+    ) -> Result<(), TransactionError> {
+        // Transactions stay in the current slot, which some checks depend
+        // on.
+        self.rpc.svm.expire_blockhash();
+        let transaction = self.rpc.transaction(instructions, &self.payer, keypairs);
+        let message = transaction.message.clone();
+        let expected = self
+            .rpc
+            .svm
+            .send_transaction(transaction)
+            .map(|_| ())
+            .map_err(|failed| failed.err);
+
+        self.simulator.slot = self.rpc.svm.get_sysvar::<Clock>().slot;
+        assert_eq!(
+            self.simulator.process_message(&message),
+            expected.clone().map_err(SimulationError::Transaction)
+        );
+        for (index, key) in message.account_keys.iter().enumerate() {
+            if message.is_maybe_writable(index, None) {
+                assert_eq!(
+                    self.simulator.accounts.get(key),
+                    self.rpc.svm.get_account(key).as_ref(),
+                    "account {key}"
+                );
+            }
+        }
+        expected
+    }
+
+    /// Sends every step of `plan`, each in its own slot.
+    fn send_plan(&mut self, plan: &Plan, keypairs: &[&dyn Signer]) {
+        for step in &plan.steps {
+            self.send(&step.instructions, keypairs).unwrap();
+            self.next_slot();
+        }
+    }
+
+    fn next_slot(&mut self) {
+        let slot = self.rpc.svm.get_sysvar::<Clock>().slot;
+        self.rpc.svm.warp_to_slot(slot.saturating_add(1));
+    }
+
+    /// Deploys `elf` with the payer as upgrade authority.
+    fn deploy(&mut self, elf: &[u8]) -> Address {
+        let program = Keypair::new();
+        let buffer = Keypair::new();
+        let plan = DeployPlanBuilder::new()
+            .payer(self.payer.pubkey())
+            .program(program.pubkey())
+            .buffer(buffer.pubkey())
+            .rent(self.simulator.rent.clone())
+            .build(elf);
+        self.send_plan(&plan, &[&program, &buffer]);
+        program.pubkey()
+    }
+}
+
+fn failed(index: u8, error: InstructionError) -> Result<(), TransactionError> {
+    Err(TransactionError::InstructionError(index, error))
+}
+
+#[test]
+fn matches_litesvm_across_program_lifecycle() {
+    let mut harness = Harness::new();
+    let memo = harness.elf(&MEMO);
+    let ata = harness.elf(&ASSOCIATED_TOKEN);
+    let payer = harness.payer.pubkey();
+    let program = harness.deploy(&memo);
+
+    let buffer = Keypair::new();
+    let upgrade = UpgradePlanBuilder::new()
+        .payer(payer)
+        .program(program)
+        .buffer(buffer.pubkey())
+        .additional_bytes((ata.len() - memo.len()) as u32)
+        .rent(harness.simulator.rent.clone())
+        .build(&ata);
+    harness.send_plan(&upgrade, &[&buffer]);
+
+    let new_authority = Keypair::new();
+    let handover = SetAuthorityCheckedBuilder::new()
+        .buffer_or_program_data_account(find_program_data_address(&program))
+        .current_authority(payer)
+        .new_authority(new_authority.pubkey())
+        .instruction();
+    harness.send(&[handover], &[&new_authority]).unwrap();
+    harness.next_slot();
+    // LiteSVM fails to reload a program whose ProgramData was closed, so
+    // the authority closes a buffer and gives up the program instead.
+    let buffer = Keypair::new();
+    let rent = harness.simulator.rent.clone();
+    harness
+        .send(
+            &create_buffer(&payer, &buffer.pubkey(), &new_authority.pubkey(), 10, &rent),
+            &[&buffer],
+        )
+        .unwrap();
+    let close = CloseBuilder::new()
+        .buffer_or_program_data_account(buffer.pubkey())
+        .destination_account(payer)
+        .authority(Some(new_authority.pubkey()))
+        .instruction();
+    let make_immutable = set_authority(
+        &find_program_data_address(&program),
+        &new_authority.pubkey(),
+        None,
+    );
+    harness
+        .send(&[close, make_immutable], &[&new_authority])
+        .unwrap();
+    assert!(!harness.simulator.accounts.contains_key(&buffer.pubkey()));
+}
+
+#[test]
+fn reports_the_error_of_the_loader() {
+    let mut harness = Harness::new();
+    let memo = harness.elf(&MEMO);
+    let payer = harness.payer.pubkey();
+    let rent = harness.simulator.rent.clone();
+    let other = Keypair::new();
+
+    // Buffer checks.
+    let buffer = Keypair::new();
+    let len = memo.len();
+    harness
+        .send(
+            &create_buffer(&payer, &buffer.pubkey(), &payer, len, &rent),
+            &[&buffer],
+        )
+        .unwrap();
+    let write = |authority: Address, offset: u32, bytes: &[u8]| {
+        WriteBuilder::new()
+            .buffer_account(buffer.pubkey())
+            .buffer_authority(authority)
+            .offset(offset)
+            .bytes(bytes.into())
+            .instruction()
+    };
+    assert_eq!(
+        harness.send(&[write(other.pubkey(), 0, &[1])], &[&other]),
+        failed(0, InstructionError::IncorrectAuthority)
+    );
+    // Signatures are per transaction: the fee payer signs for its
+    // authority even where the instruction does not ask it to.
+    let mut unsigned = write(payer, 0, &[1]);
+    unsigned.accounts[1].is_signer = false;
+    assert_eq!(harness.send(&[unsigned], &[]), Ok(()));
+    assert_eq!(
+        harness.send(&[write(payer, len as u32 - 1, &[1, 2])], &[]),
+        failed(0, InstructionError::AccountDataTooSmall)
+    );
+    let initialize = InitializeBufferBuilder::new()
+        .source_account(buffer.pubkey())
+        .buffer_authority(payer)
+        .instruction();
+    assert_eq!(
+        harness.send(&[initialize], &[]),
+        failed(0, InstructionError::AccountAlreadyInitialized)
+    );
+    assert_eq!(
+        harness.send(&[set_authority(&buffer.pubkey(), &payer, None)], &[]),
+        failed(0, InstructionError::IncorrectAuthority)
+    );
+    let close = CloseBuilder::new()
+        .buffer_or_program_data_account(buffer.pubkey())
+        .destination_account(buffer.pubkey())
+        .authority(Some(payer))
+        .instruction();
+    assert_eq!(
+        harness.send(&[close], &[]),
+        failed(0, InstructionError::InvalidArgument)
+    );
+    for (offset, chunk) in memo.chunks(1000).enumerate() {
+        harness
+            .send(&[write(payer, offset as u32 * 1000, chunk)], &[])
+            .unwrap();
+    }
+    let program = Keypair::new();
+    let deploy = |max_data_len: u64| {
+        let program_len = 36;
+        vec![
+            solana_system_interface::instruction::create_account(
+                &payer,
+                &program.pubkey(),
+                rent.minimum_balance(program_len),
+                program_len as u64,
+                &solana_loader_v3_program_client::ID,
+            ),
+            DeployWithMaxDataLenBuilder::new()
+                .payer_account(payer)
+                .program_data_account(find_program_data_address(&program.pubkey()))
+                .program_account(program.pubkey())
+                .buffer_account(buffer.pubkey())
+                .authority(payer)
+                .max_data_len(max_data_len)
+                .instruction(),
+        ]
+    };
+    assert_eq!(
+        harness.send(&deploy(len as u64 - 1), &[&program]),
+        failed(1, InstructionError::AccountDataTooSmall)
+    );
+    harness.send(&deploy(len as u64), &[&program]).unwrap();
+    let program = program.pubkey();
+    let program_data = find_program_data_address(&program);
+
+    // Program checks.
+    let extend = |additional_bytes: u32| {
+        ExtendProgramBuilder::new()
+            .program_data_account(program_data)
+            .program_account(program)
+            .system_program(Some(solana_system_interface::program::ID))
+            .payer(Some(payer))
+            .additional_bytes(additional_bytes)
+            .instruction()
+    };
+    assert_eq!(
+        harness.send(&[extend(1)], &[]),
+        failed(0, InstructionError::InvalidArgument)
+    );
+    harness.next_slot();
+    assert_eq!(
+        harness.send(&[extend(0)], &[]),
+        failed(0, InstructionError::InvalidInstructionData)
+    );
+    let mut unchecked = SetAuthorityCheckedBuilder::new()
+        .buffer_or_program_data_account(program_data)
+        .current_authority(payer)
+        .new_authority(other.pubkey())
+        .instruction();
+    unchecked.accounts[2].is_signer = false;
+    assert_eq!(
+        harness.send(&[unchecked], &[]),
+        failed(0, InstructionError::MissingRequiredSignature)
+    );
+
+    let buffer = Keypair::new();
+    harness
+        .send(
+            &create_buffer(&payer, &buffer.pubkey(), &payer, len, &rent),
+            &[&buffer],
+        )
+        .unwrap();
+    harness
+        .send(&[set_authority(&program_data, &payer, None)], &[])
+        .unwrap();
+    harness.next_slot();
+    let upgrade = UpgradeBuilder::new()
+        .program_data_account(program_data)
+        .program_account(program)
+        .buffer_account(buffer.pubkey())
+        .spill_account(payer)
+        .authority(payer)
+        .instruction();
+    assert_eq!(
+        harness.send(&[upgrade], &[]),
+        failed(0, InstructionError::Immutable)
+    );
+}
+
+/// LiteSVM skips the rent-state check of accounts without data, so these
+/// cases cannot go through the [`Harness`].
+#[test]
+fn rejects_rent_paying_system_accounts() {
+    let rent = Rent::default();
+    let payer = Address::new_unique();
+    let recipient = Address::new_unique();
+    let incinerator = Address::from_str_const("1nc1nerator11111111111111111111111111111111");
+    let minimum = rent.minimum_balance(0);
+    let mut simulator = Simulator::new(rent);
+    let balance = 10 * minimum;
+    simulator.accounts.insert(
+        payer,
+        Account {
+            lamports: balance,
+            data: Vec::new(),
+            owner: solana_system_interface::program::ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    let transfer = |to: &Address, lamports: u64| {
+        Message::new(
+            &[solana_system_interface::instruction::transfer(
+                &payer, to, lamports,
+            )],
+            Some(&payer),
+        )
+    };
+
+    // Draining the payer below the rent-exempt minimum leaves it paying
+    // rent, as does funding a new account with less.
+    assert_eq!(
+        simulator.process_message(&transfer(&recipient, balance - minimum)),
+        Err(SimulationError::Transaction(
+            TransactionError::InsufficientFundsForRent { account_index: 0 }
+        ))
+    );
+    assert_eq!(
+        simulator.process_message(&transfer(&recipient, 1)),
+        Err(SimulationError::Transaction(
+            TransactionError::InsufficientFundsForRent { account_index: 1 }
+        ))
+    );
+    assert_eq!(simulator.accounts[&payer].lamports, balance - 10_000);
+    assert!(!simulator.accounts.contains_key(&recipient));
+
+    // The incinerator is exempt.
+    simulator
+        .process_message(&transfer(&incinerator, 1))
+        .unwrap();
+    assert_eq!(simulator.accounts[&incinerator].lamports, 1);
+}
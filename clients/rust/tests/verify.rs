@@ -0,0 +1,49 @@
+use {
+    solana_address::Address,
+    solana_loader_v3_program_client::{
+        state::LoaderState,
+        verify::{verify_buffer, verify_program_data, ProgramHash, VerifyError},
+    },
+};
+
+fn program_data_account(elf: &[u8], padding: usize) -> Vec<u8> {
+    let mut data = LoaderState::ProgramData {
+        slot: 42,
+        upgrade_authority: Some(Address::new_unique()),
+    }
+    .to_bytes();
+    data.extend_from_slice(elf);
+    data.extend(std::iter::repeat_n(0, padding));
+    data
+}
+
+#[test]
+fn hashes_without_padding() {
+    let hash = ProgramHash::of_elf(b"abc");
+    assert_eq!(
+        hash.to_string(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(hash.to_string().parse(), Ok(hash));
+    assert_eq!(
+        ProgramHash::of_program_data(&program_data_account(b"abc", 100)),
+        Ok(hash)
+    );
+}
+
+#[test]
+fn reports_mismatch() {
+    let expected = ProgramHash::of_elf(b"abc");
+    let mut buffer = LoaderState::Buffer { authority: None }.to_bytes();
+    buffer.extend_from_slice(b"abd\0\0");
+
+    assert_eq!(
+        verify_program_data(&program_data_account(b"abc", 10), &expected),
+        Ok(())
+    );
+    let Err(VerifyError::Mismatch(mismatch)) = verify_buffer(&buffer, &expected) else {
+        panic!("expected a mismatch");
+    };
+    assert_eq!(mismatch.actual, ProgramHash::of_elf(b"abd"));
+    assert_eq!(mismatch.actual_len, 3);
+}